@@ -0,0 +1,22 @@
+use std::{process::Command, time::SystemTime};
+
+/// Embeds which commit the bot was built from and when, for `/about`
+fn main() {
+    let git_hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_owned())
+        .unwrap_or_else(|| "unknown".to_owned());
+    let built_at = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("The clock is set before 1970")
+        .as_secs();
+    println!("cargo:rustc-env=SET_GIT_HASH={}", git_hash);
+    println!("cargo:rustc-env=SET_BUILT_AT={}", built_at);
+    // Checking out or committing moves HEAD or the branch it points to
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs/heads");
+}
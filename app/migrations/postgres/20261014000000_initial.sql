@@ -0,0 +1,23 @@
+-- Mirrors the SQLite schema, with real timestamps
+CREATE TABLE channels (
+    channel_id BIGINT PRIMARY KEY,
+    round TEXT NOT NULL,
+    past_rounds TEXT NOT NULL
+);
+CREATE TABLE entries (
+    channel_id BIGINT NOT NULL,
+    entry TEXT NOT NULL,
+    first_seen TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (channel_id, entry)
+);
+CREATE TABLE last_message_ids (
+    channel_id BIGINT PRIMARY KEY,
+    message_id BIGINT NOT NULL
+);
+CREATE TABLE blocklist (
+    user_id BIGINT PRIMARY KEY
+);
+CREATE TABLE dead_letters (
+    position SERIAL PRIMARY KEY,
+    dead_letter TEXT NOT NULL
+);
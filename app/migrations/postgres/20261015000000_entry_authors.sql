@@ -0,0 +1,2 @@
+-- Who sent the message that introduced an entry, so `/forget` can remove it
+ALTER TABLE entries ADD COLUMN author BIGINT;
@@ -0,0 +1,5 @@
+-- Settings changed with `/config set`, applied over the config file
+CREATE TABLE IF NOT EXISTS settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
@@ -0,0 +1,2 @@
+-- Days in a row each user had a message accepted, as JSON by user ID
+ALTER TABLE channels ADD COLUMN streaks TEXT NOT NULL DEFAULT '{}';
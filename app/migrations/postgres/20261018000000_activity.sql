@@ -0,0 +1,8 @@
+-- Messages accepted and duplicates deleted in each channel per day
+CREATE TABLE activity (
+    channel_id BIGINT NOT NULL,
+    day DATE NOT NULL,
+    accepted INTEGER NOT NULL,
+    deleted INTEGER NOT NULL,
+    PRIMARY KEY (channel_id, day)
+);
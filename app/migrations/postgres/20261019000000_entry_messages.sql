@@ -0,0 +1,4 @@
+-- Where the message that introduced an entry is, so warnings can link to it
+ALTER TABLE entries ADD COLUMN message_guild_id BIGINT;
+ALTER TABLE entries ADD COLUMN message_channel_id BIGINT;
+ALTER TABLE entries ADD COLUMN message_id BIGINT;
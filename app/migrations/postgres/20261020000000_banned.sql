@@ -0,0 +1,4 @@
+-- Entries that are always deleted, even the first time they are posted
+CREATE TABLE banned (
+    entry TEXT PRIMARY KEY
+);
@@ -0,0 +1,5 @@
+-- Games of SET by the channel they are played in, as JSON
+CREATE TABLE card_games (
+    channel_id BIGINT PRIMARY KEY,
+    game TEXT NOT NULL
+);
@@ -0,0 +1,234 @@
+use crate::{cache::MessagesCache, config::Config, feed::{Event as FeedEvent, Feed}, get_the_channel_id, latency::{Latencies, Summary}, Error};
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{self, Sse},
+        IntoResponse, Response,
+    },
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude as serenity;
+use serde::{Deserialize, Serialize};
+use set_core::{
+    cache::{ChannelCache, Entry, RoundStats},
+    dedup,
+    normalize::normalize_string,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    convert::Infallible,
+    sync::{Arc, RwLock},
+};
+use tokio::sync::Mutex;
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+
+/// Entries listed when no limit is asked for
+const DEFAULT_LIMIT: usize = 100;
+/// Most entries listed per request
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Clone)]
+struct ApiState {
+    config: Arc<RwLock<Arc<Config>>>,
+    messages_cache: Arc<Mutex<MessagesCache>>,
+    feed: Arc<Feed>,
+    latencies: Arc<Latencies>,
+}
+
+impl ApiState {
+    fn config(&self) -> Arc<Config> {
+        self.config.read().expect("The config lock was poisoned").clone()
+    }
+}
+
+/// Serves a read-only HTTP API over the cache at `api.address`, until the bot shuts down
+///
+/// Every request needs the configured token as a bearer token. Snowflakes are strings, since they
+/// don't fit into JavaScript's numbers.
+pub async fn serve(config: Arc<RwLock<Arc<Config>>>, messages_cache: Arc<Mutex<MessagesCache>>, feed: Arc<Feed>, latencies: Arc<Latencies>) -> Result<(), Error> {
+    let address = config.read().expect("The config lock was poisoned").api.address;
+    let state = ApiState { config, messages_cache, feed, latencies };
+    let router = Router::new()
+        .route("/entries", get(entries))
+        .route("/stats", get(stats))
+        .route("/users/{id}/entries", get(user_entries))
+        .route("/events", get(events))
+        .layer(middleware::from_fn_with_state(state.clone(), authorize))
+        .with_state(state);
+    let listener = tokio::net::TcpListener::bind(address).await?;
+    log::info!("Serving the API at {}", address);
+    axum::serve(listener, router).await?;
+    Ok(())
+}
+
+async fn authorize(State(state): State<ApiState>, request: Request, next: Next) -> Response {
+    let token = state.config().api.token.clone();
+    let given = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    // Compared by their hashes, so how long it takes doesn't tell how much of the token was right
+    match given {
+        Some(given) if Sha256::digest(given) == Sha256::digest(&token) => next.run(request).await,
+        _ => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
+
+/// Which channel's cache a request is about, the watched channel's by default
+fn requested_scope(channel: Option<u64>) -> serenity::ChannelId {
+    serenity::ChannelId::new(channel.unwrap_or_else(get_the_channel_id))
+}
+
+#[derive(Deserialize)]
+struct ChannelQuery {
+    channel: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct EntriesQuery {
+    channel: Option<u64>,
+    /// Only entries containing or resembling this, best matches first. Newest first otherwise.
+    query: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct EntryResponse {
+    entry: String,
+    first_seen: DateTime<Utc>,
+    author: Option<String>,
+    /// How similar the entry is to the query, when searching
+    #[serde(skip_serializing_if = "Option::is_none")]
+    similarity: Option<f64>,
+}
+
+impl EntryResponse {
+    fn new(entry: &str, metadata: &Entry, similarity: Option<f64>) -> Self {
+        Self {
+            entry: entry.to_owned(),
+            first_seen: metadata.first_seen,
+            author: metadata.author.map(|author| author.to_string()),
+            similarity,
+        }
+    }
+}
+
+/// Runs `f` on the cache of `scope`, or answers 404 if there is none
+async fn with_channel<T>(state: &ApiState, scope: serenity::ChannelId, f: impl FnOnce(&ChannelCache) -> T) -> Result<T, StatusCode> {
+    let messages_cache = state.messages_cache.lock().await;
+    messages_cache.channels.get(&scope).map(f).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// `GET /entries?query=&limit=&channel=`
+async fn entries(State(state): State<ApiState>, Query(params): Query<EntriesQuery>) -> Result<Json<Vec<EntryResponse>>, StatusCode> {
+    let scope = requested_scope(params.channel);
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let query = params.query.map(|query| normalize_string(&query, &state.config().normalization_in(scope)));
+    let entries = with_channel(&state, scope, |channel_cache| match &query {
+        Some(query) => dedup::search(&channel_cache.cache, query, limit)
+            .into_iter()
+            .map(|(entry, similarity)| EntryResponse::new(entry, &channel_cache.cache[entry], Some(similarity)))
+            .collect(),
+        None => channel_cache
+            .recent(limit)
+            .into_iter()
+            .map(|(entry, metadata)| EntryResponse::new(entry, metadata, None))
+            .collect(),
+    })
+    .await?;
+    Ok(Json(entries))
+}
+
+#[derive(Serialize)]
+struct StatsResponse {
+    channel_id: String,
+    entries: usize,
+    round: RoundStats,
+    past_rounds: Vec<RoundStats>,
+    latency: LatencyResponse,
+}
+
+/// How long things took in the last hour, unset if nothing happened
+#[derive(Serialize)]
+struct LatencyResponse {
+    /// Requests to Discord's REST API
+    requests: Option<Summary>,
+    /// From receiving a duplicate until Discord confirmed deleting it
+    deletions: Option<Summary>,
+}
+
+/// `GET /stats?channel=`
+async fn stats(State(state): State<ApiState>, Query(params): Query<ChannelQuery>) -> Result<Json<StatsResponse>, StatusCode> {
+    let scope = requested_scope(params.channel);
+    let latency = LatencyResponse {
+        requests: state.latencies.requests().await,
+        deletions: state.latencies.deletions().await,
+    };
+    let stats = with_channel(&state, scope, |channel_cache| StatsResponse {
+        channel_id: scope.to_string(),
+        entries: channel_cache.cache.len(),
+        round: channel_cache.round.clone(),
+        past_rounds: channel_cache.past_rounds.clone(),
+        latency,
+    })
+    .await?;
+    Ok(Json(stats))
+}
+
+/// `GET /users/{id}/entries?limit=&channel=`, newest first
+async fn user_entries(
+    State(state): State<ApiState>,
+    Path(user_id): Path<u64>,
+    Query(params): Query<EntriesQuery>,
+) -> Result<Json<Vec<EntryResponse>>, StatusCode> {
+    let scope = requested_scope(params.channel);
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let entries = with_channel(&state, scope, |channel_cache| {
+        let mut entries: Vec<_> = channel_cache
+            .cache
+            .iter()
+            .filter(|(_, metadata)| metadata.author == Some(user_id))
+            .collect();
+        entries.sort_unstable_by(|a, b| b.1.first_seen.cmp(&a.1.first_seen).then(a.0.cmp(b.0)));
+        entries
+            .into_iter()
+            .take(limit)
+            .map(|(entry, metadata)| EntryResponse::new(entry, metadata, None))
+            .collect()
+    })
+    .await?;
+    Ok(Json(entries))
+}
+
+/// `GET /events`, a stream of server-sent events, one per accepted message or deleted duplicate
+///
+/// Each event is named like the `event` field of its JSON. Clients that fall too far behind miss
+/// events rather than holding up the bot.
+async fn events(State(state): State<ApiState>) -> Sse<impl Stream<Item = Result<sse::Event, Infallible>>> {
+    let stream = BroadcastStream::new(state.feed.subscribe()).filter_map(|event| {
+        let event = match event {
+            Ok(event) => event,
+            Err(error) => {
+                log::debug!("An API subscriber fell behind: {}", error);
+                return None;
+            }
+        };
+        let name = match &event {
+            FeedEvent::EntryAccepted(_) => "entry_accepted",
+            FeedEvent::DuplicateDeleted(_) => "duplicate_deleted",
+        };
+        match sse::Event::default().event(name).json_data(&event) {
+            Ok(event) => Some(Ok(event)),
+            Err(error) => {
+                log::error!("Failed to serialize an event for the API: {}", error);
+                None
+            }
+        }
+    });
+    Sse::new(stream).keep_alive(sse::KeepAlive::default())
+}
@@ -0,0 +1,311 @@
+use crate::{get_the_data_path, i18n::{self, tr}, Data, Error};
+use chrono::{DateTime, Utc};
+use set_core::dedup::Key;
+use poise::serenity_prelude::{self as serenity, Mentionable};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, path,
+};
+use tokio::sync::Mutex;
+
+/// How long a deleted message can be appealed for
+const APPEAL_PERIOD: chrono::Duration = chrono::Duration::days(7);
+
+/// Name of the webhook approved messages are re-posted through
+const WEBHOOK_NAME: &str = "set appeals";
+
+/// Custom IDs of the buttons, followed by the ID of the deleted message
+const APPEAL: &str = "appeal:";
+const APPROVE: &str = "appeal-approve:";
+const DENY: &str = "appeal-deny:";
+
+/// A deleted duplicate whose author may appeal
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Appeal {
+    pub channel_id: serenity::ChannelId,
+    /// Channel whose cache the message was checked against
+    pub scope: serenity::ChannelId,
+    pub author_id: serenity::UserId,
+    pub author_name: String,
+    pub avatar_url: String,
+    pub content: String,
+    /// The message's cache entries, whitelisted if the appeal is approved
+    pub entries: Vec<String>,
+    /// Why the message was deleted
+    pub reason: String,
+    pub deleted_at: DateTime<Utc>,
+    /// Whether the author appealed, so it is waiting for a moderator
+    #[serde(default)]
+    pub submitted: bool,
+}
+
+/// What the appeals file holds
+#[derive(Serialize, Deserialize, Default)]
+struct AppealsFile {
+    /// Appeals by the ID of the deleted message
+    pending: HashMap<serenity::MessageId, Appeal>,
+    /// Entries of approved appeals and those whitelisted with `/whitelist`, which are never
+    /// treated as duplicates again
+    whitelist: HashMap<serenity::ChannelId, HashSet<String>>,
+}
+
+/// Pending appeals and the whitelisted entries, kept in a JSON file of their own next to the data
+/// file
+pub struct Appeals {
+    path: path::PathBuf,
+    file: Mutex<AppealsFile>,
+}
+
+impl Appeals {
+    pub fn load() -> Self {
+        Self::load_from(get_the_data_path().with_file_name("set-bot-appeals.json"))
+    }
+    /// The appeals kept in the file at `path`, none if there is no such file yet
+    pub fn load_from(path: path::PathBuf) -> Self {
+        let file = match fs::File::open(&path) {
+            Ok(file) => serde_json::from_reader(file).unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e)),
+            Err(_) => AppealsFile::default(),
+        };
+        Self { path, file: Mutex::new(file) }
+    }
+    fn save(&self, file: &AppealsFile) -> Result<(), Error> {
+        serde_json::to_writer(fs::File::create(&self.path)?, file)?;
+        Ok(())
+    }
+    /// `keys` without those of `scope` whose entries are whitelisted
+    pub async fn without_whitelisted(&self, scope: serenity::ChannelId, mut keys: Vec<Key>) -> Vec<Key> {
+        if let Some(whitelist) = self.file.lock().await.whitelist.get(&scope) {
+            keys.retain(|key| !whitelist.contains(&key.to_entry()));
+        }
+        keys
+    }
+    /// Lets `entry` repeat freely in `scope`, returning whether it wasn't whitelisted yet
+    pub async fn whitelist(&self, scope: serenity::ChannelId, entry: String) -> Result<bool, Error> {
+        let mut file = self.file.lock().await;
+        let added = file.whitelist.entry(scope).or_default().insert(entry);
+        if added {
+            self.save(&file)?;
+        }
+        Ok(added)
+    }
+    /// Makes `entry` count as a duplicate in `scope` again, returning whether it was whitelisted
+    pub async fn unwhitelist(&self, scope: serenity::ChannelId, entry: &str) -> Result<bool, Error> {
+        let mut file = self.file.lock().await;
+        let removed = file.whitelist.get_mut(&scope).is_some_and(|whitelist| whitelist.remove(entry));
+        if removed {
+            self.save(&file)?;
+        }
+        Ok(removed)
+    }
+    /// The whitelisted entries of `scope`, sorted
+    pub async fn whitelisted(&self, scope: serenity::ChannelId) -> Vec<String> {
+        let mut entries: Vec<String> = self.file.lock().await.whitelist.get(&scope).into_iter().flatten().cloned().collect();
+        entries.sort_unstable();
+        entries
+    }
+}
+
+/// Offers the author of a deleted duplicate to appeal, by DM or in the channel it was sent in
+///
+/// The offer expires after a week.
+pub async fn offer(
+    ctx: &serenity::Context,
+    data: &Data,
+    message: &serenity::Message,
+    scope: serenity::ChannelId,
+    entries: Vec<String>,
+    reason: String,
+) -> Result<(), Error> {
+    let appeal = Appeal {
+        channel_id: message.channel_id,
+        scope,
+        author_id: message.author.id,
+        author_name: message.author_nick(ctx).await.unwrap_or_else(|| message.author.name.clone()),
+        avatar_url: message.author.face(),
+        content: message.content.clone(),
+        entries,
+        reason,
+        deleted_at: Utc::now(),
+        submitted: false,
+    };
+    {
+        let mut file = data.appeals.file.lock().await;
+        // Appeals that were submitted stay until a moderator decides them
+        file.pending.retain(|_, appeal| appeal.submitted || appeal.deleted_at + APPEAL_PERIOD > Utc::now());
+        file.pending.insert(message.id, appeal);
+        data.appeals.save(&file)?;
+    }
+    let locale = data.config().i18n.locale.clone();
+    let prompt = |content: String| {
+        let button = serenity::CreateButton::new(format!("{}{}", APPEAL, message.id))
+            .style(serenity::ButtonStyle::Secondary)
+            .label(tr(&locale, "appeals.button", &[]));
+        serenity::CreateMessage::new()
+            .content(content)
+            .components(vec![serenity::CreateActionRow::Buttons(vec![button])])
+    };
+    let channel = message.channel_id.mention();
+    if data.config().appeals.dm {
+        let notice = tr(&locale, "appeals.dm", &[("channel", &channel)]);
+        if message.author.id.direct_message(ctx, prompt(notice)).await.is_ok() {
+            return Ok(());
+        }
+        log::debug!("Failed to DM {} about their appeal, offering it in the channel instead", message.author.id);
+    }
+    let prompt = prompt(tr(&locale, "appeals.channel", &[("author", &message.author.mention()), ("channel", &channel)]));
+    message.channel_id.send_message(ctx, prompt).await?;
+    Ok(())
+}
+
+/// Handles a click on one of the appeal buttons, returning whether it was one
+pub async fn on_component(ctx: &serenity::Context, data: &Data, interaction: &serenity::ComponentInteraction) -> Result<bool, Error> {
+    let custom_id = &interaction.data.custom_id;
+    let locale = i18n::user_locale(data, Some(&interaction.locale));
+    let (action, message_id) = match [APPEAL, APPROVE, DENY].iter().find_map(|prefix| Some((*prefix, custom_id.strip_prefix(prefix)?))) {
+        Some((action, message_id)) => (action, serenity::MessageId::new(message_id.parse()?)),
+        None => return Ok(false),
+    };
+    let Some(appeal) = data.appeals.file.lock().await.pending.get(&message_id).cloned() else {
+        respond(ctx, interaction, &tr(&locale, "appeals.expired", &[])).await?;
+        return Ok(true);
+    };
+    match action {
+        APPEAL => submit(ctx, data, interaction, message_id, appeal).await?,
+        _ => {
+            let is_moderator = interaction.member.as_ref()
+                .and_then(|member| member.permissions)
+                .is_some_and(|permissions| permissions.manage_messages());
+            if !is_moderator {
+                respond(ctx, interaction, &tr(&locale, "appeals.not_moderator", &[])).await?;
+                return Ok(true);
+            }
+            decide(ctx, data, interaction, message_id, appeal, action == APPROVE).await?;
+        }
+    }
+    Ok(true)
+}
+
+/// Replies to a click with a message only the clicker sees
+async fn respond(ctx: &serenity::Context, interaction: &serenity::ComponentInteraction, content: &str) -> Result<(), Error> {
+    let response = serenity::CreateInteractionResponseMessage::new().content(content).ephemeral(true);
+    interaction.create_response(ctx, serenity::CreateInteractionResponse::Message(response)).await?;
+    Ok(())
+}
+
+/// Sends the author's appeal to the moderators
+async fn submit(
+    ctx: &serenity::Context,
+    data: &Data,
+    interaction: &serenity::ComponentInteraction,
+    message_id: serenity::MessageId,
+    appeal: Appeal,
+) -> Result<(), Error> {
+    let locale = i18n::user_locale(data, Some(&interaction.locale));
+    if interaction.user.id != appeal.author_id {
+        return respond(ctx, interaction, &tr(&locale, "appeals.not_author", &[])).await;
+    }
+    if appeal.submitted {
+        return respond(ctx, interaction, &tr(&locale, "appeals.already_appealed", &[])).await;
+    }
+    // Appeals may have been turned off, or the moderator channel unset, since the button was sent
+    let config = data.config();
+    let moderator_channel = match config.appeals.moderator_channel {
+        Some(moderator_channel) if config.appeals.enabled => moderator_channel,
+        _ => return respond(ctx, interaction, &tr(&locale, "appeals.unavailable", &[])).await,
+    };
+    // The moderators' channel is in the configured locale, like the bot's other notices
+    let moderator_locale = &config.i18n.locale;
+    let embed = serenity::CreateEmbed::new()
+        .title(tr(moderator_locale, "appeals.case", &[]))
+        .author(serenity::CreateEmbedAuthor::new(&appeal.author_name).icon_url(&appeal.avatar_url))
+        .description(&appeal.content)
+        .field(tr(moderator_locale, "appeals.case_channel", &[]), appeal.channel_id.mention().to_string(), true)
+        .field(tr(moderator_locale, "appeals.case_reason", &[]), &appeal.reason, true)
+        .timestamp(serenity::Timestamp::from(appeal.deleted_at));
+    let buttons = vec![
+        serenity::CreateButton::new(format!("{}{}", APPROVE, message_id))
+            .style(serenity::ButtonStyle::Success)
+            .label(tr(moderator_locale, "appeals.approve", &[])),
+        serenity::CreateButton::new(format!("{}{}", DENY, message_id))
+            .style(serenity::ButtonStyle::Danger)
+            .label(tr(moderator_locale, "appeals.deny", &[])),
+    ];
+    let case = serenity::CreateMessage::new()
+        .embed(embed)
+        .components(vec![serenity::CreateActionRow::Buttons(buttons)]);
+    moderator_channel.send_message(ctx, case).await?;
+    {
+        let mut file = data.appeals.file.lock().await;
+        if let Some(pending) = file.pending.get_mut(&message_id) {
+            pending.submitted = true;
+        }
+        data.appeals.save(&file)?;
+    }
+    let response = serenity::CreateInteractionResponseMessage::new()
+        .content(tr(&locale, "appeals.sent", &[]))
+        .components(vec![]);
+    interaction.create_response(ctx, serenity::CreateInteractionResponse::UpdateMessage(response)).await?;
+    Ok(())
+}
+
+/// Approves or denies an appeal. Approved messages are re-posted and their entries whitelisted.
+async fn decide(
+    ctx: &serenity::Context,
+    data: &Data,
+    interaction: &serenity::ComponentInteraction,
+    message_id: serenity::MessageId,
+    appeal: Appeal,
+    approved: bool,
+) -> Result<(), Error> {
+    {
+        let mut file = data.appeals.file.lock().await;
+        file.pending.remove(&message_id);
+        if approved {
+            file.whitelist.entry(appeal.scope).or_default().extend(appeal.entries.iter().cloned());
+        }
+        data.appeals.save(&file)?;
+    }
+    if approved {
+        repost(ctx, data, &appeal).await?;
+    }
+    let locale = &data.config().i18n.locale;
+    let moderator = interaction.user.mention();
+    let verdict = match approved {
+        true => tr(locale, "appeals.approved", &[("moderator", &moderator)]),
+        false => tr(locale, "appeals.denied", &[("moderator", &moderator)]),
+    };
+    log::info!("Appeal of message {} by {} was {}", message_id, appeal.author_id, if approved { "approved" } else { "denied" });
+    let response = serenity::CreateInteractionResponseMessage::new()
+        .content(verdict)
+        .components(vec![]);
+    interaction.create_response(ctx, serenity::CreateInteractionResponse::UpdateMessage(response)).await?;
+    Ok(())
+}
+
+/// Posts an approved message again under its author's name, through a webhook of the bot's
+async fn repost(ctx: &serenity::Context, data: &Data, appeal: &Appeal) -> Result<(), Error> {
+    // Threads have no webhooks of their own, their parent's post into them
+    let parent = data.thread_parents.lock().await.get(&appeal.channel_id).copied().flatten();
+    let webhook_channel = parent.unwrap_or(appeal.channel_id);
+    let existing = webhook_channel
+        .webhooks(ctx)
+        .await?
+        .into_iter()
+        .find(|webhook| webhook.name.as_deref() == Some(WEBHOOK_NAME) && webhook.token.is_some());
+    let webhook = match existing {
+        Some(webhook) => webhook,
+        None => webhook_channel.create_webhook(ctx, serenity::CreateWebhook::new(WEBHOOK_NAME)).await?,
+    };
+    let mut message = serenity::ExecuteWebhook::new()
+        .content(&appeal.content)
+        .username(&appeal.author_name)
+        .avatar_url(&appeal.avatar_url)
+        .allowed_mentions(serenity::CreateAllowedMentions::new());
+    if parent.is_some() {
+        message = message.in_thread(appeal.channel_id);
+    }
+    webhook.execute(ctx, false, message).await?;
+    Ok(())
+}
+
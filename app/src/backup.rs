@@ -0,0 +1,113 @@
+use crate::{
+    cache::MessagesCache,
+    config::{BackupConfig, StorageConfig},
+    store::{self, Compression, Key},
+    Error,
+};
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Uploads copies of the cache to an S3-compatible bucket and deletes old ones
+///
+/// Backups are named by when they were taken, so sorting their names sorts them by age. They are
+/// written like the JSON file, compressed and encrypted as configured for it, so one can be
+/// restored by downloading it as the data file.
+pub struct Backup {
+    bucket: Box<s3::Bucket>,
+    config: BackupConfig,
+    compression: Compression,
+    key: Option<Key>,
+}
+
+impl Backup {
+    pub fn open(config: &BackupConfig, storage: &StorageConfig) -> Result<Self, Error> {
+        // Like the bot's token, the credentials belong in `.env`
+        dotenvy::dotenv().ok();
+        let credentials = s3::creds::Credentials::from_env()?;
+        let region = match &config.endpoint {
+            Some(endpoint) => s3::Region::Custom {
+                region: config.region.clone(),
+                endpoint: endpoint.clone(),
+            },
+            None => config.region.parse()?,
+        };
+        let mut bucket = s3::Bucket::new(&config.bucket, region, credentials)?;
+        // Most S3-compatible services don't support a subdomain per bucket
+        if config.endpoint.is_some() {
+            bucket = bucket.with_path_style();
+        }
+        Ok(Self {
+            bucket,
+            config: config.clone(),
+            compression: storage.compression,
+            key: Key::from_env()?,
+        })
+    }
+    /// Name that backups start with
+    fn name_prefix(&self) -> String {
+        format!("{}set-bot-cache.", self.config.prefix)
+    }
+    /// Uploads a copy of `messages_cache`, returning its name
+    pub async fn upload(&self, messages_cache: &Mutex<MessagesCache>) -> Result<String, Error> {
+        let contents = store::encode_json(&*messages_cache.lock().await, self.compression, self.key.as_ref())?;
+        let name = format!(
+            "{}{}.{}",
+            self.name_prefix(),
+            Utc::now().format("%Y%m%dT%H%M%SZ"),
+            self.compression.extension(),
+        );
+        self.bucket.put_object(&name, &contents).await?;
+        Ok(name)
+    }
+    /// Deletes the backups that fall outside the retention policy, returning how many
+    pub async fn prune(&self) -> Result<usize, Error> {
+        let mut backups = Vec::new();
+        for page in self.bucket.list(self.name_prefix(), None).await? {
+            backups.extend(page.contents);
+        }
+        backups.sort_unstable_by(|a, b| b.key.cmp(&a.key));
+        let oldest_kept = self.config.max_age
+            .and_then(|max_age| chrono::Duration::from_std(max_age).ok())
+            .map(|max_age| Utc::now() - max_age);
+        let mut pruned = 0;
+        for (i, backup) in backups.iter().enumerate() {
+            let too_many = self.config.keep > 0 && i >= self.config.keep;
+            let too_old = match (oldest_kept, backup.last_modified.parse::<DateTime<Utc>>()) {
+                (Some(oldest_kept), Ok(last_modified)) => last_modified < oldest_kept,
+                _ => false,
+            };
+            if too_many || too_old {
+                self.bucket.delete_object(&backup.key).await?;
+                pruned += 1;
+            }
+        }
+        Ok(pruned)
+    }
+    /// Uploads a backup and prunes old ones, logging what happened
+    pub async fn back_up(&self, messages_cache: &Mutex<MessagesCache>) {
+        match self.upload(messages_cache).await {
+            Ok(name) => log::info!("Uploaded backup {} to bucket {}", name, self.config.bucket),
+            Err(error) => {
+                log::error!("Failed to upload a backup to bucket {}: {:?}", self.config.bucket, error);
+                // Old backups are only pruned once a new one is safely stored
+                return;
+            }
+        }
+        match self.prune().await {
+            Ok(0) => {}
+            Ok(pruned) => log::info!("Deleted {} old backups", pruned),
+            Err(error) => log::error!("Failed to delete old backups: {:?}", error),
+        }
+    }
+}
+
+/// Backs up the cache every `backup.interval`, starting one interval from now
+pub async fn back_up_periodically(backup: Arc<Backup>, messages_cache: Arc<Mutex<MessagesCache>>) {
+    let start = tokio::time::Instant::now() + backup.config.interval;
+    let mut interval = tokio::time::interval_at(start, backup.config.interval);
+    loop {
+        interval.tick().await;
+        backup.back_up(&messages_cache).await;
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{cache::MessagesCache, config::Config, keys, store, Error};
+use poise::serenity_prelude as serenity;
+use set_core::dedup::{self, Verdict};
+use std::time::{Duration, Instant};
+
+const WORDS: &[&str] = &[
+    "apple", "river", "stone", "cloud", "ember", "maple", "quartz", "harbor", "lantern", "meadow",
+    "signal", "velvet", "thunder", "willow", "cobalt", "saffron", "glacier", "orbit", "pepper", "tundra",
+];
+
+/// Deterministic pseudo-random numbers, so every run replays the same channel
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self, below: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % below as u64) as usize
+    }
+}
+
+/// `count` messages of a few words each, about a tenth of which repeat an earlier one
+fn synthetic_channel(count: usize) -> Vec<String> {
+    let mut rng = XorShift(0x5EED);
+    let mut messages: Vec<String> = Vec::with_capacity(count);
+    for i in 0..count {
+        if i > 0 && rng.next(10) == 0 {
+            let earlier = messages[rng.next(i)].clone();
+            messages.push(earlier);
+            continue;
+        }
+        let words: Vec<_> = (0..1 + rng.next(5)).map(|_| WORDS[rng.next(WORDS.len())]).collect();
+        messages.push(format!("{} {}", words.join(" "), i));
+    }
+    messages
+}
+
+fn rate(count: usize, took: Duration) -> String {
+    format!("{:.0}/s", count as f64 / took.as_secs_f64().max(f64::EPSILON))
+}
+
+/// Replays a synthetic channel of `count` messages through the dedup engine with the current
+/// config, printing how long normalizing, checking and snapshotting took
+pub fn bench(config: &Config, count: usize) -> Result<(), Error> {
+    let messages = synthetic_channel(count);
+    let scope = serenity::ChannelId::new(1);
+
+    let started = Instant::now();
+    let keys: Vec<_> = messages
+        .iter()
+        .map(|message| keys::text_keys(message, config, &config.normalization_in(scope)))
+        .collect();
+    let normalized = started.elapsed();
+
+    let mut messages_cache = MessagesCache::new();
+    let rules = config.dedup_rules_in(scope);
+    let now = chrono::Utc::now();
+    let mut deleted = 0;
+    let started = Instant::now();
+    for keys in &keys {
+        let channel_cache = messages_cache.channel(scope);
+        match dedup::check_all(&channel_cache.cache, keys, &rules, now) {
+            Verdict::Unique => channel_cache.insert_keys(keys, now, None, None),
+            _ => deleted += 1,
+        }
+    }
+    let checked = started.elapsed();
+
+    let started = Instant::now();
+    let snapshot = store::encode_json(&messages_cache, config.storage.compression, None)?;
+    let serialized = started.elapsed();
+
+    println!("{} messages, {} would be deleted, {} unique entries", count, deleted, messages_cache.total_entries());
+    println!("Normalizing: {:?} ({})", normalized, rate(count, normalized));
+    println!("Checking and inserting: {:?} ({})", checked, rate(count, checked));
+    println!("Serializing the snapshot: {:?} ({} bytes, {:?} compression)", serialized, snapshot.len(), config.storage.compression);
+    Ok(())
+}
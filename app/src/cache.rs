@@ -0,0 +1,128 @@
+use crate::{
+    config::Config,
+    deletion::{DeadLetter, Outcome},
+    Error,
+};
+use poise::serenity_prelude as serenity;
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use set_core::{
+    cache::{ChannelCache, CompactionReport},
+    cards::CardGame,
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io,
+};
+
+/// Everything the bot persists in its data file
+#[derive(Serialize, Deserialize, Default)]
+pub struct MessagesCache {
+    /// Caches by the channel (or thread) they belong to
+    pub channels: HashMap<serenity::ChannelId, ChannelCache>,
+    /// Newest handled message of every channel and thread, where the next catch-up resumes
+    pub last_message_ids: HashMap<serenity::ChannelId, serenity::MessageId>,
+    /// Users who may not use commands (and, if configured, whose messages are ignored)
+    #[serde(default)]
+    pub blocklist: HashSet<serenity::UserId>,
+    /// Entries that are always deleted, even the first time they are posted
+    #[serde(default)]
+    pub banned: HashSet<String>,
+    /// Most recent deletions that failed for good, oldest first
+    #[serde(default)]
+    pub dead_letters: Vec<DeadLetter>,
+    /// Settings changed with `/config set`, as TOML values by their dotted key
+    #[serde(default)]
+    pub settings: BTreeMap<String, String>,
+    /// Games of SET by the channel they are played in
+    #[serde(default)]
+    pub card_games: HashMap<serenity::ChannelId, CardGame>,
+}
+
+/// How many failed deletions are remembered
+const MAX_DEAD_LETTERS: usize = 100;
+
+/// The data file format from before threads were supported, holding a single channel's cache
+#[derive(Deserialize)]
+struct LegacyMessagesCache {
+    #[serde(flatten)]
+    channel: ChannelCache,
+    last_message_id: Option<serenity::MessageId>,
+    #[serde(default)]
+    blocklist: HashSet<serenity::UserId>,
+}
+
+impl MessagesCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Reads the data file, migrating the legacy single-channel format into `channel_id`
+    pub fn from_reader(data_file: impl io::Read, channel_id: serenity::ChannelId) -> Result<Self, Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum DataFile {
+            Current(MessagesCache),
+            Legacy(LegacyMessagesCache),
+        }
+        let data_file = serde_json::from_reader(data_file).map_err(|e| format!("Failed to deserialize data file: {}", e))?;
+        Ok(match data_file {
+            DataFile::Current(messages_cache) => messages_cache,
+            DataFile::Legacy(legacy) => Self {
+                channels: HashMap::from([(channel_id, legacy.channel)]),
+                last_message_ids: legacy.last_message_id.map(|id| (channel_id, id)).into_iter().collect(),
+                blocklist: legacy.blocklist,
+                banned: HashSet::new(),
+                dead_letters: Vec::new(),
+                settings: BTreeMap::new(),
+                card_games: HashMap::new(),
+            },
+        })
+    }
+    /// The cache of `channel_id`, created empty if the channel was never seen before
+    pub fn channel(&mut self, channel_id: serenity::ChannelId) -> &mut ChannelCache {
+        self.channels.entry(channel_id).or_default()
+    }
+    /// Records `message_id` as the newest handled message of `channel_id`, unless a newer one
+    /// already was. Returns whether the message is new.
+    pub fn mark_handled(&mut self, channel_id: serenity::ChannelId, message_id: serenity::MessageId) -> bool {
+        match self.last_message_ids.get(&channel_id) {
+            Some(&last_message_id) if last_message_id >= message_id => false,
+            _ => {
+                self.last_message_ids.insert(channel_id, message_id);
+                true
+            }
+        }
+    }
+    /// Counts a successful deletion, or remembers a failed one
+    pub fn record_deletion(&mut self, scope: serenity::ChannelId, outcome: Outcome) {
+        match outcome {
+            Outcome::Deleted => self.channel(scope).record_deleted(Utc::now()),
+            Outcome::Gone | Outcome::Retrying => {}
+            Outcome::Failed(dead_letter) => {
+                self.dead_letters.push(dead_letter);
+                let excess = self.dead_letters.len().saturating_sub(MAX_DEAD_LETTERS);
+                self.dead_letters.drain(..excess);
+            }
+        }
+    }
+    /// Compacts the cache of every channel with its normalization, then drops channels (and their
+    /// failed deletions) that are left with nothing worth keeping
+    pub fn compact(&mut self, config: &Config, now: DateTime<Utc>) -> CompactionReport {
+        let mut report = CompactionReport::default();
+        for (scope, channel_cache) in self.channels.iter_mut() {
+            report += channel_cache.compact(&config.normalization_in(*scope), config.ttl.expire_after, now);
+        }
+        self.channels.retain(|_, channel_cache| !channel_cache.is_orphaned());
+        let channels = &self.channels;
+        self.dead_letters.retain(|dead_letter| channels.contains_key(&dead_letter.scope));
+        report
+    }
+    /// Drops the entries `user_id` introduced in any channel, returning how many were removed
+    pub fn forget_author(&mut self, user_id: serenity::UserId) -> usize {
+        self.channels.values_mut().map(|channel_cache| channel_cache.forget_author(user_id.get())).sum()
+    }
+    /// Number of entries across all channels
+    pub fn total_entries(&self) -> usize {
+        self.channels.values().map(|channel| channel.cache.len()).sum()
+    }
+}
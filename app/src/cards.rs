@@ -0,0 +1,74 @@
+use crate::{i18n::tr, response::{self, Tone}};
+use poise::serenity_prelude::{self as serenity, Mentionable};
+use set_core::cards::{Card, CardGame};
+use std::hash::BuildHasher;
+
+/// The symbol of a card by its shape (oval, diamond, squiggle) and color (red, green, purple)
+const SYMBOLS: [[&str; 3]; 3] = [["🔴", "🟢", "🟣"], ["🟥", "🟩", "🟪"], ["❤️", "💚", "💜"]];
+const SHADINGS: [&str; 3] = ["cards.solid", "cards.striped", "cards.open"];
+/// Cards shown per line of the board
+const ROW_LENGTH: usize = 3;
+
+/// The label of the card at `position` on the board, which players name it by
+pub fn label(position: usize) -> char {
+    (b'A' + position as u8) as char
+}
+
+/// The position on the board of the card labeled `label`, in either case
+pub fn position(label: &str) -> Option<usize> {
+    match label.trim().as_bytes() {
+        [letter] if letter.is_ascii_alphabetic() => Some((letter.to_ascii_uppercase() - b'A') as usize),
+        _ => None,
+    }
+}
+
+/// A card as its symbols, repeated as often as its number says, and its shading
+fn describe(locale: &str, card: Card) -> String {
+    let symbol = SYMBOLS[card.shape() as usize][card.color() as usize];
+    let shading = tr(locale, SHADINGS[card.shading() as usize], &[]);
+    format!("{} {}", symbol.repeat(card.number() as usize + 1), shading)
+}
+
+/// Cards in a row, as on the board but without labels
+pub fn describe_cards(locale: &str, cards: &[Card]) -> String {
+    cards.iter().map(|card| describe(locale, *card)).collect::<Vec<_>>().join(" · ")
+}
+
+/// The players of `game` and their points, best first, a line each
+pub fn standings(locale: &str, game: &CardGame) -> String {
+    let lines: Vec<String> = game
+        .standings()
+        .into_iter()
+        .enumerate()
+        .map(|(rank, (player, score))| {
+            tr(locale, "cards.standing", &[("rank", &(rank + 1)), ("player", &serenity::UserId::new(player).mention()), ("score", &score)])
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// The board as a grid of labeled cards, and how many cards are left in the deck
+pub fn board(locale: &str, game: &CardGame) -> serenity::CreateEmbed {
+    let rows: Vec<String> = game
+        .board
+        .chunks(ROW_LENGTH)
+        .enumerate()
+        .map(|(row, cards)| {
+            let cards: Vec<String> = cards
+                .iter()
+                .enumerate()
+                .map(|(column, card)| format!("`{}` {}", label(row * ROW_LENGTH + column), describe(locale, *card)))
+                .collect();
+            cards.join(" · ")
+        })
+        .collect();
+    response::embed(Tone::Info)
+        .title(tr(locale, "cards.board", &[]))
+        .description(rows.join("\n"))
+        .field(tr(locale, "cards.deck", &[]), game.deck.len().to_string(), true)
+}
+
+/// A seed to shuffle a new deck with, different every time
+pub fn seed() -> u64 {
+    std::collections::hash_map::RandomState::new().hash_one(std::time::SystemTime::now())
+}
@@ -0,0 +1,69 @@
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+/// A Discord bot that keeps every message in a channel unique
+#[derive(Parser)]
+#[command(version)]
+pub struct Cli {
+    /// Config file to use
+    #[arg(long, global = true, env = "SET_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Data file holding the cache, in the XDG data directory by default
+    #[arg(long = "data-path", visible_alias = "data", global = true, env = "SET_DATA_PATH")]
+    pub data: Option<PathBuf>,
+    /// Data directory holding a file per channel, the appeals and round archives
+    #[arg(long, global = true, env = "DATA_DIR", conflicts_with = "data")]
+    pub data_dir: Option<PathBuf>,
+    /// How much the bot logs (off, error, warn, info, debug or trace), overriding `RUST_LOG`
+    #[arg(long, global = true)]
+    pub log_level: Option<log::LevelFilter>,
+    /// What to do, runs the bot if omitted
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the bot
+    Run,
+    /// Validate the config file and show the settings it results in
+    CheckConfig,
+    /// Rewrite the stored data in the current format, or copy it to another storage backend
+    Migrate {
+        /// Backend to read from, the configured one if omitted
+        #[arg(long, value_enum)]
+        from: Option<crate::store::Backend>,
+        /// Backend to write to, the one read from if omitted
+        #[arg(long, value_enum)]
+        to: Option<crate::store::Backend>,
+    },
+    /// Re-normalize the cache with the current settings, merging entries that now collide
+    Compact,
+    /// Export all cached entries with their metadata
+    Export {
+        #[arg(long, value_enum, default_value = "csv")]
+        format: crate::export::Format,
+        /// File to write to, stdout if omitted
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Pre-seed the cache with entries from an export, a Discord data package or a text file
+    Import {
+        /// File to import, see the readme for the formats
+        path: PathBuf,
+        /// Channel whose cache the entries are added to, the watched channel if omitted
+        #[arg(long)]
+        channel: Option<u64>,
+    },
+    /// Simulate deduplicating an exported channel transcript, without connecting to Discord
+    Replay {
+        /// Transcript in DiscordChatExporter's JSON format
+        transcript: PathBuf,
+    },
+    /// Measure normalization, dedup and snapshot throughput on a synthetic channel
+    Bench {
+        /// How many messages the channel has
+        #[arg(long, default_value_t = 100_000)]
+        messages: usize,
+    },
+}
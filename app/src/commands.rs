@@ -1,5 +1,18 @@
-use crate::{Context, Error, get_the_channel_id};
-use poise::serenity_prelude::{self as serenity, all, model::permissions};
+use crate::{Context, Error, events, archive_the_data_file, commit_to_disk, describe_compaction, get_the_channel_id, get_the_round_archive_path, get_the_store, preflight};
+use crate::{cache::MessagesCache, config::{get_key, Config, ThemeKind}, export, import, deletion, i18n::{self, tr}, response::{self, Tone}, permissions::{is_admin, missing_permissions, required_permissions, LOG_CHANNEL_PERMISSIONS}};
+use poise::serenity_prelude::{self as serenity, Mentionable};
+use set_core::{cache::MessageRef, cards::{shuffled_deck, CardGame, ClaimError}, dedup, normalize::normalize_string};
+use std::{collections::BTreeMap, fs, time::Duration};
+
+/// The channel whose cache a command applies to: the one it was used in if that has a cache of
+/// its own, otherwise the watched channel
+fn command_scope(ctx: Context<'_>, messages_cache: &MessagesCache) -> serenity::ChannelId {
+    if messages_cache.channels.contains_key(&ctx.channel_id()) {
+        ctx.channel_id()
+    } else {
+        serenity::ChannelId::new(get_the_channel_id())
+    }
+}
 
 /// Show this help menu
 #[poise::command(prefix_command, track_edits, slash_command)]
@@ -9,59 +22,1297 @@ pub async fn help(
     #[autocomplete = "poise::builtins::autocomplete_command"]
     command: Option<String>,
 ) -> Result<(), Error> {
-    poise::builtins::help(
-        ctx,
-        command.as_deref(),
-        poise::builtins::HelpConfiguration {
-            extra_text_at_bottom: "This is an example bot made to showcase features of my custom Discord bot framework",
-            ..Default::default()
+    let locale = i18n::locale(ctx);
+    let commands = &ctx.framework().options().commands;
+    let reply = match command {
+        Some(name) => match commands.iter().find(|command| command.name == name || command.context_menu_name.as_deref() == Some(&name)) {
+            Some(command) => {
+                let help = match (command.description_localizations.get(&locale), &command.help_text, &command.description) {
+                    (Some(description), _, _) => description.clone(),
+                    (None, Some(help), _) | (None, None, Some(help)) => help.clone(),
+                    (None, None, None) => tr(&locale, "help.no_help", &[]),
+                };
+                let name = command.name_localizations.get(&locale).unwrap_or(&command.name);
+                poise::CreateReply::default().embed(response::embed(Tone::Info).title(name).description(help))
+            }
+            None => response::reply(Tone::Warning, tr(&locale, "help.unknown", &[("name", &name)])),
         },
-    )
-    .await?;
+        None => {
+            let lines: Vec<_> = commands
+                .iter()
+                .filter(|command| !command.hide_in_help)
+                .map(|command| {
+                    let name = command.name_localizations.get(&locale).unwrap_or(&command.name);
+                    let description = command.description_localizations.get(&locale).or(command.description.as_ref());
+                    let description = description.map(String::as_str).unwrap_or_default();
+                    match &command.context_menu_name {
+                        Some(menu_name) => {
+                            let menu_name = command.name_localizations.get(&locale).unwrap_or(menu_name);
+                            tr(&locale, "help.message_menu", &[("name", menu_name), ("description", &description)])
+                        }
+                        None => format!("`/{}`: {}", name, description),
+                    }
+                })
+                .collect();
+            let help = format!("{}\n\n{}", lines.join("\n"), tr(&locale, "help.footer", &[]));
+            poise::CreateReply::default().embed(response::embed(Tone::Info).title(tr(&locale, "help.title", &[])).description(help))
+        }
+    };
+    ctx.send(reply).await?;
     Ok(())
 }
 
-#[poise::command(prefix_command, track_edits, slash_command)]
+/// Outcome of one of `/check`'s checks: what is fine, or what is wrong
+type CheckResult = Result<String, String>;
+
+/// Check that the bot is set up correctly
+///
+/// Covers the permissions in every configured channel, the gateway intents and the data file.
+#[poise::command(prefix_command, track_edits, slash_command, check = "is_admin", ephemeral)]
 pub async fn check(
     ctx: Context<'_>,
     #[description = "Check required perms"]
     #[autocomplete = "poise::builtins::autocomplete_command"]
     _command: Option<String>,
 ) -> Result<(), Error> {
-    let channel_id = serenity::ChannelId::new(get_the_channel_id());
-    let channel = match channel_id.to_channel(&ctx).await {
+    ctx.defer_ephemeral().await?;
+    let locale = i18n::locale(ctx);
+    let watched = serenity::ChannelId::new(get_the_channel_id());
+    let mut results = vec![(
+        tr(&locale, "check.watched_channel", &[("channel", &watched)]),
+        check_channel(ctx, &locale, watched, true).await,
+    )];
+    if let Some(log_channel) = ctx.data().config().alerts.log_channel {
+        results.push((
+            tr(&locale, "check.log_channel", &[("channel", &log_channel)]),
+            check_channel(ctx, &locale, log_channel, false).await,
+        ));
+    }
+    results.push((tr(&locale, "check.intents", &[]), check_intents(ctx).await));
+    results.push((tr(&locale, "check.storage", &[("store", &get_the_store().describe())]), get_the_store().check().await));
+
+    let all_correct = results.iter().all(|(_, result)| result.is_ok());
+    let embed = response::embed(if all_correct { Tone::Success } else { Tone::Error })
+        .title(tr(&locale, if all_correct { "check.all_correct" } else { "check.needs_fixing" }, &[]))
+        .fields(results.into_iter().map(|(name, result)| match result {
+            Ok(message) => (format!("✅ {}", name), message, false),
+            Err(message) => (format!("❌ {}", name), message, false),
+        }));
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// Checks that a channel exists and the bot has the permissions it needs there
+async fn check_channel(ctx: Context<'_>, locale: &str, channel_id: serenity::ChannelId, watched: bool) -> CheckResult {
+    let channel = match channel_id.to_channel(ctx).await {
         Ok(serenity::Channel::Guild(channel)) => channel,
-        Ok(serenity::Channel::Private(_channel)) => {
-            ctx.say(format!("Channel {} is a private channel", channel_id)).await?;
-            return Ok(());
-        },
-        Ok(_) => {
-            ctx.say(format!("Channel {} is not a guild channel", channel_id)).await?;
-            return Ok(());
-        },
-        Err(e) => {
-            ctx.say(format!("Failed to get channel with ID {}: {}", channel_id, e)).await?;
-            return Ok(());
-        },
+        Ok(serenity::Channel::Private(_)) => return Err(tr(locale, "check.private_channel", &[])),
+        Ok(_) => return Err(tr(locale, "check.not_guild_channel", &[])),
+        Err(e) => return Err(tr(locale, "check.channel_unavailable", &[("error", &e)])),
+    };
+    let required = if watched {
+        match channel.kind {
+            serenity::ChannelType::Forum => {},
+            _ if channel.is_text_based() => {},
+            kind => return Err(tr(locale, "check.no_messages", &[("kind", &kind.name())])),
+        }
+        required_permissions(&channel)
+    } else {
+        LOG_CHANNEL_PERMISSIONS
+    };
+    let missing = missing_permissions(ctx.serenity_context(), &channel, required)
+        .await
+        .map_err(|e| tr(locale, "check.permissions_unknown", &[("error", &e)]))?;
+    if !missing.is_empty() {
+        let names: Vec<_> = missing.iter_names().map(|(name, _)| name).collect();
+        return Err(tr(locale, "check.missing_permissions", &[("permissions", &names.join(", ")), ("channel", &channel.mention())]));
+    }
+    Ok(tr(locale, "check.has_permissions", &[("channel", &channel.mention())]))
+}
+
+/// Checks that the bot is allowed to connect with the privileged intents it requests
+async fn check_intents(ctx: Context<'_>) -> CheckResult {
+    preflight::check_intents(ctx.http()).await
+}
+
+/// Wipe the cache and start a new round
+///
+/// The current cache file is archived to a timestamped snapshot before anything is cleared.
+#[poise::command(prefix_command, slash_command, check = "is_admin", ephemeral)]
+pub async fn purgecache(ctx: Context<'_>) -> Result<(), Error> {
+    let locale = i18n::locale(ctx);
+    let confirm_id = format!("{}-purgecache-confirm", ctx.id());
+    let cancel_id = format!("{}-purgecache-cancel", ctx.id());
+    let (scope, entry_count) = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        let scope = command_scope(ctx, &messages_cache);
+        (scope, messages_cache.channel(scope).cache.len())
+    };
+
+    let reply = response::reply(Tone::Warning, tr(&locale, "purgecache.confirm", &[("count", &entry_count)]))
+        .components(vec![serenity::CreateActionRow::Buttons(vec![
+            serenity::CreateButton::new(&confirm_id)
+                .style(serenity::ButtonStyle::Danger)
+                .label(tr(&locale, "purgecache.purge", &[])),
+            serenity::CreateButton::new(&cancel_id)
+                .style(serenity::ButtonStyle::Secondary)
+                .label(tr(&locale, "purgecache.cancel", &[])),
+        ])]);
+    let handle = ctx.send(reply).await?;
+
+    let filter_ids = [confirm_id.clone(), cancel_id];
+    let interaction = serenity::ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .channel_id(ctx.channel_id())
+        .timeout(Duration::from_secs(60))
+        .filter(move |mci| filter_ids.contains(&mci.data.custom_id))
+        .await;
+    let Some(interaction) = interaction else {
+        handle.edit(ctx, response::reply(Tone::Info, tr(&locale, "purgecache.timed_out", &[]))
+            .components(vec![])).await?;
+        return Ok(());
+    };
+
+    let (tone, response) = if interaction.data.custom_id == confirm_id {
+        let archive_path = archive_the_data_file().await?;
+        {
+            let mut messages_cache = ctx.data().messages_cache.lock().await;
+            messages_cache.channel(scope).start_new_round(chrono::Utc::now());
+            commit_to_disk(&messages_cache).await?;
+        }
+        let response = match archive_path {
+            Some(archive_path) => tr(&locale, "purgecache.purged_archived", &[("count", &entry_count), ("path", &archive_path.display())]),
+            None => tr(&locale, "purgecache.purged", &[("count", &entry_count)]),
+        };
+        (Tone::Success, response)
+    } else {
+        (Tone::Info, tr(&locale, "purgecache.cancelled", &[]))
+    };
+    interaction.create_response(ctx, serenity::CreateInteractionResponse::UpdateMessage(
+        serenity::CreateInteractionResponseMessage::new()
+            .embed(response::embed(tone).description(response))
+            .components(vec![]),
+    )).await?;
+    Ok(())
+}
+
+/// Most autocomplete choices Discord shows
+const MAX_AUTOCOMPLETE_CHOICES: usize = 25;
+/// Longest value an autocomplete choice may have, longer ones fail the whole response
+const MAX_AUTOCOMPLETE_LENGTH: usize = 100;
+
+/// Suggest cached entries starting with what has been typed so far, then those containing it
+async fn autocomplete_entry(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let mut messages_cache = ctx.data().messages_cache.lock().await;
+    let scope = command_scope(ctx, &messages_cache);
+    let partial = normalize_string(partial, &ctx.data().config().normalization_in(scope));
+    let mut matches: Vec<(bool, &String)> = messages_cache
+        .channel(scope)
+        .cache
+        .keys()
+        .filter(|entry| entry.chars().count() <= MAX_AUTOCOMPLETE_LENGTH && entry.contains(&partial))
+        .map(|entry| (!entry.starts_with(&partial), entry))
+        .collect();
+    let choices = MAX_AUTOCOMPLETE_CHOICES.min(matches.len());
+    if choices < matches.len() {
+        matches.select_nth_unstable(choices);
+        matches.truncate(choices);
+    }
+    matches.sort_unstable();
+    let choices = matches.into_iter().map(|(_, entry)| entry.clone()).collect();
+    choices
+}
+
+/// Remove a single entry from the cache
+///
+/// The entry is normalized the same way messages are before it is looked up.
+#[poise::command(prefix_command, slash_command, check = "is_admin", ephemeral)]
+pub async fn removeentry(
+    ctx: Context<'_>,
+    #[description = "Entry to remove"]
+    #[autocomplete = "autocomplete_entry"]
+    #[rest]
+    entry: String,
+) -> Result<(), Error> {
+    let (entry, removed) = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        let scope = command_scope(ctx, &messages_cache);
+        let entry = normalize_string(&entry, &ctx.data().config().normalization_in(scope));
+        let removed = messages_cache.channel(scope).cache.remove(&entry).is_some();
+        if removed {
+            commit_to_disk(&messages_cache).await?;
+        }
+        (entry, removed)
+    };
+    if removed {
+        ctx.send(response::reply(Tone::Success, tr(&i18n::locale(ctx), "removeentry.removed", &[("entry", &entry)]))).await?;
+    } else {
+        ctx.send(response::reply(Tone::Warning, tr(&i18n::locale(ctx), "removeentry.not_cached", &[("entry", &entry)]))).await?;
+    }
+    Ok(())
+}
+
+/// Check whether a message duplicates a cached entry, without deleting or caching anything
+#[poise::command(context_menu_command = "Check for duplicate", guild_only, required_permissions = "MANAGE_MESSAGES")]
+pub async fn checkduplicate(ctx: Context<'_>, message: serenity::Message) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+    let locale = i18n::locale(ctx);
+    let config = &ctx.data().config();
+    let scope = command_scope(ctx, &*ctx.data().messages_cache.lock().await);
+    let keys = crate::keys::message_keys(&message, config, scope).await;
+    let findings: Vec<String> = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        let cache = &messages_cache.channel(scope).cache;
+        keys.iter()
+            .filter_map(|key| {
+                let verdict = dedup::check(cache, key, &config.dedup_rules_in(scope), *message.timestamp);
+                let entry = match &verdict {
+                    dedup::Verdict::Unique => return None,
+                    dedup::Verdict::Duplicate => key.to_entry(),
+                    dedup::Verdict::NearDuplicate { closest, .. } | dedup::Verdict::Related { closest, .. } => closest.clone(),
+                };
+                let metadata = &cache[&entry];
+                // A cached message matches the entry it introduced itself
+                if verdict == dedup::Verdict::Duplicate && metadata.first_seen == *message.timestamp && metadata.author == Some(message.author.id.get()) {
+                    return Some(tr(&locale, "checkduplicate.first_seen_here", &[("entry", &entry)]));
+                }
+                let mut finding = match verdict {
+                    dedup::Verdict::NearDuplicate { similarity, .. } => {
+                        tr(&locale, "checkduplicate.similar", &[("similarity", &format!("{:.0}", similarity * 100.0)), ("entry", &entry)])
+                    }
+                    dedup::Verdict::Related { relation, .. } => {
+                        tr(&locale, "checkduplicate.related", &[("relation", &i18n::relation(&locale, relation)), ("entry", &entry)])
+                    }
+                    _ => tr(&locale, "checkduplicate.duplicate", &[("entry", &entry)]),
+                };
+                let time = format!("<t:{}:f>", metadata.first_seen.timestamp());
+                finding += &tr(&locale, "checkduplicate.first_seen", &[("time", &time)]);
+                if let Some(author) = metadata.author {
+                    finding += &tr(&locale, "checkduplicate.author", &[("author", &serenity::UserId::new(author).mention())]);
+                }
+                Some(finding)
+            })
+            .collect()
+    };
+    let reply = match findings.is_empty() {
+        true => response::reply(Tone::Success, tr(&locale, "checkduplicate.unique", &[])),
+        false => response::reply(Tone::Warning, findings.join("\n")),
+    };
+    ctx.send(reply.ephemeral(true)).await?;
+    Ok(())
+}
+
+/// Cache a message's content, e.g. from a channel the bot doesn't watch
+///
+/// Entries that are already cached keep when and by whom they were first seen.
+#[poise::command(context_menu_command = "Add to cache", guild_only, required_permissions = "MANAGE_MESSAGES")]
+pub async fn addtocache(ctx: Context<'_>, message: serenity::Message) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+    let scope = command_scope(ctx, &*ctx.data().messages_cache.lock().await);
+    let keys = crate::keys::message_keys(&message, &ctx.data().config(), scope).await;
+    let (added, already_cached) = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        let channel_cache = messages_cache.channel(scope);
+        let (cached, new): (Vec<_>, Vec<_>) = keys.into_iter().partition(|key| channel_cache.cache.contains_key(&key.to_entry()));
+        let first_posted = ctx.guild_id().map(|guild_id| MessageRef { guild_id: guild_id.get(), channel_id: message.channel_id.get(), message_id: message.id.get() });
+        channel_cache.insert_keys(&new, *message.timestamp, Some(message.author.id.get()), first_posted);
+        if !new.is_empty() {
+            commit_to_disk(&messages_cache).await?;
+        }
+        (new.len(), cached.len())
+    };
+    let response = tr(&i18n::locale(ctx), "addtocache.added", &[("added", &added), ("cached", &already_cached)]);
+    ctx.send(response::reply(Tone::Success, response).ephemeral(true)).await?;
+    Ok(())
+}
+
+/// Remove a message's content from the cache, so it may be posted again
+#[poise::command(context_menu_command = "Remove from cache", guild_only, required_permissions = "MANAGE_MESSAGES")]
+pub async fn removefromcache(ctx: Context<'_>, message: serenity::Message) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+    let scope = command_scope(ctx, &*ctx.data().messages_cache.lock().await);
+    let keys = crate::keys::message_keys(&message, &ctx.data().config(), scope).await;
+    let removed = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        let channel_cache = messages_cache.channel(scope);
+        let removed = keys.iter().filter(|key| channel_cache.cache.remove(&key.to_entry()).is_some()).count();
+        if removed > 0 {
+            commit_to_disk(&messages_cache).await?;
+        }
+        removed
+    };
+    let locale = i18n::locale(ctx);
+    let reply = match removed {
+        0 => response::reply(Tone::Warning, tr(&locale, "removefromcache.not_cached", &[])),
+        _ => response::reply(Tone::Success, tr(&locale, "removefromcache.removed", &[("count", &removed)])),
+    };
+    ctx.send(reply.ephemeral(true)).await?;
+    Ok(())
+}
+
+/// Block a user from using the bot
+///
+/// If `blocklist.ignore_messages` is set in the config, their messages are also ignored.
+#[poise::command(prefix_command, slash_command, guild_only, check = "is_admin", ephemeral)]
+pub async fn block(
+    ctx: Context<'_>,
+    #[description = "User to block"] user: serenity::User,
+) -> Result<(), Error> {
+    let newly_blocked = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        let newly_blocked = messages_cache.blocklist.insert(user.id);
+        if newly_blocked {
+            commit_to_disk(&messages_cache).await?;
+        }
+        newly_blocked
+    };
+    if newly_blocked {
+        ctx.send(response::reply(Tone::Success, tr(&i18n::locale(ctx), "block.blocked", &[("user", &user.name)]))).await?;
+    } else {
+        ctx.send(response::reply(Tone::Warning, tr(&i18n::locale(ctx), "block.already_blocked", &[("user", &user.name)]))).await?;
+    }
+    Ok(())
+}
+
+/// Unblock a previously blocked user
+#[poise::command(prefix_command, slash_command, guild_only, check = "is_admin", ephemeral)]
+pub async fn unblock(
+    ctx: Context<'_>,
+    #[description = "User to unblock"] user: serenity::User,
+) -> Result<(), Error> {
+    let unblocked = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        let unblocked = messages_cache.blocklist.remove(&user.id);
+        if unblocked {
+            commit_to_disk(&messages_cache).await?;
+        }
+        unblocked
+    };
+    if unblocked {
+        ctx.send(response::reply(Tone::Success, tr(&i18n::locale(ctx), "unblock.unblocked", &[("user", &user.name)]))).await?;
+    } else {
+        ctx.send(response::reply(Tone::Warning, tr(&i18n::locale(ctx), "unblock.not_blocked", &[("user", &user.name)]))).await?;
+    }
+    Ok(())
+}
+
+/// Delete every cached entry a user's messages introduced, e.g. to honor a data deletion request
+///
+/// Covers all channels. Entries cached before authors were recorded, imported ones and existing
+/// backups or round archives can't be attributed and are left as they are.
+#[poise::command(prefix_command, slash_command, check = "is_admin", ephemeral)]
+pub async fn forget(
+    ctx: Context<'_>,
+    #[description = "User whose entries to delete"] user: serenity::User,
+) -> Result<(), Error> {
+    let removed = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        let removed = messages_cache.forget_author(user.id);
+        if removed > 0 {
+            commit_to_disk(&messages_cache).await?;
+        }
+        removed
+    };
+    log::info!("Forgot {} entries of user {}", removed, user.id);
+    ctx.send(response::reply(Tone::Success, tr(&i18n::locale(ctx), "forget.forgotten", &[("count", &removed), ("user", &user.name)]))).await?;
+    Ok(())
+}
+
+/// Archive the current round and start a new one
+///
+/// The round's entries and statistics are written to a `set-bot-round-<channel>-<number>.json` archive.
+#[poise::command(prefix_command, slash_command, check = "is_admin", ephemeral)]
+pub async fn newround(ctx: Context<'_>) -> Result<(), Error> {
+    let (stats, entry_count) = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        // Create the archive before clearing anything, so a failure leaves the round untouched
+        let scope = command_scope(ctx, &messages_cache);
+        let channel_cache = messages_cache.channel(scope);
+        let archive_file = fs::File::create(get_the_round_archive_path(scope, channel_cache.round.number))?;
+        let archive = channel_cache.start_new_round(chrono::Utc::now());
+        serde_json::to_writer_pretty(&archive_file, &archive)?;
+        commit_to_disk(&messages_cache).await?;
+        (archive.stats, archive.cache.len())
+    };
+    let response = tr(&i18n::locale(ctx), "newround.over", &[
+        ("number", &stats.number),
+        ("start", &stats.started_at.format("%Y-%m-%d")),
+        ("end", &stats.ended_at.unwrap_or(stats.started_at).format("%Y-%m-%d")),
+        ("entries", &entry_count),
+        ("accepted", &stats.accepted),
+        ("deleted", &stats.deleted),
+        ("next", &(stats.number + 1)),
+    ]);
+    ctx.send(response::reply(Tone::Success, response)).await?;
+    Ok(())
+}
+
+/// Show statistics about the current and past rounds
+#[poise::command(prefix_command, track_edits, slash_command)]
+pub async fn stats(ctx: Context<'_>) -> Result<(), Error> {
+    let locale = i18n::locale(ctx);
+    let response = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        let scope = command_scope(ctx, &messages_cache);
+        let channel_cache = messages_cache.channel(scope);
+        let round = &channel_cache.round;
+        let mut response = tr(&locale, "stats.current", &[
+            ("number", &round.number),
+            ("start", &round.started_at.format("%Y-%m-%d")),
+            ("entries", &channel_cache.cache.len()),
+            ("accepted", &round.accepted),
+            ("deleted", &round.deleted),
+        ]);
+        for round in channel_cache.past_rounds.iter().rev().take(5) {
+            response += "\n";
+            response += &tr(&locale, "stats.past", &[
+                ("number", &round.number),
+                ("start", &round.started_at.format("%Y-%m-%d")),
+                ("end", &round.ended_at.unwrap_or(round.started_at).format("%Y-%m-%d")),
+                ("accepted", &round.accepted),
+                ("deleted", &round.deleted),
+            ]);
+        }
+        let pending = ctx.data().deletions.pending();
+        if pending > 0 {
+            response += "\n";
+            response += &tr(&locale, "stats.pending", &[("count", &pending)]);
+        }
+        let dead_letters: Vec<_> = messages_cache.dead_letters.iter().filter(|dead_letter| dead_letter.scope == scope).collect();
+        if !dead_letters.is_empty() {
+            response += "\n";
+            response += &tr(&locale, "stats.failed", &[("count", &dead_letters.len())]);
+            for dead_letter in dead_letters.iter().rev().take(3) {
+                let target = match dead_letter.target {
+                    deletion::Target::Message { channel_id, message_id } => {
+                        tr(&locale, "stats.failed_message", &[("message", &message_id), ("channel", &channel_id.mention())])
+                    }
+                    deletion::Target::Post(thread_id) => tr(&locale, "stats.failed_post", &[("thread", &thread_id.mention())]),
+                };
+                response += "\n";
+                response += &tr(&locale, "stats.dead_letter", &[
+                    ("failed_at", &dead_letter.failed_at.format("%Y-%m-%d %H:%M")),
+                    ("target", &target),
+                    ("error", &dead_letter.error),
+                ]);
+            }
+        }
+        response
+    };
+    let pressure = ctx.data().throttle.pressure().await;
+    let mut response = format!("{}\n{}", response, tr(&locale, "stats.pressure", &[
+        ("used", &pressure.used),
+        ("per_second", &pressure.per_second),
+        ("rate_limits", &pressure.rate_limits),
+    ]));
+    if let Some(paused_for) = pressure.paused_for {
+        response += &tr(&locale, "stats.paused", &[("seconds", &format!("{:.1}", paused_for.as_secs_f64()))]);
+    }
+    for (key, summary) in [("stats.requests", ctx.data().latencies.requests().await), ("stats.deletions", ctx.data().latencies.deletions().await)] {
+        if let Some(summary) = summary {
+            response += "\n";
+            response += &tr(&locale, key, &[
+                ("count", &summary.count),
+                ("p50", &summary.p50_ms),
+                ("p95", &summary.p95_ms),
+                ("errors", &summary.errors),
+            ]);
+        }
+    }
+    ctx.send(response::reply(Tone::Info, response)).await?;
+    Ok(())
+}
+
+/// Show how many days in a row someone had a message accepted
+#[poise::command(prefix_command, slash_command)]
+pub async fn streak(
+    ctx: Context<'_>,
+    #[description = "Whose streak to show, yours by default"] user: Option<serenity::User>,
+) -> Result<(), Error> {
+    let locale = i18n::locale(ctx);
+    let user = user.as_ref().unwrap_or(ctx.author());
+    let streak = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        let scope = command_scope(ctx, &messages_cache);
+        messages_cache.channel(scope).streaks.get(&user.id.get()).copied()
+    };
+    let today = chrono::Utc::now().date_naive();
+    let response = match streak {
+        Some(streak) => tr(&locale, "streak.streak", &[
+            ("user", &user.mention()),
+            ("current", &streak.current_on(today)),
+            ("longest", &streak.longest),
+        ]),
+        None => tr(&locale, "streak.none", &[("user", &user.mention())]),
+    };
+    ctx.send(response::reply(Tone::Info, response)).await?;
+    Ok(())
+}
+
+/// Most users the streak leaderboard lists
+const MAX_STREAK_LEADERS: usize = 10;
+
+/// Show who has the longest ongoing streaks of days with an accepted message
+#[poise::command(prefix_command, slash_command)]
+pub async fn streaks(ctx: Context<'_>) -> Result<(), Error> {
+    let locale = i18n::locale(ctx);
+    let today = chrono::Utc::now().date_naive();
+    let mut leaders: Vec<(u64, u32, u32)> = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        let scope = command_scope(ctx, &messages_cache);
+        messages_cache
+            .channel(scope)
+            .streaks
+            .iter()
+            .map(|(user, streak)| (*user, streak.current_on(today), streak.longest))
+            .filter(|(_, current, _)| *current > 0)
+            .collect()
+    };
+    leaders.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(b.2.cmp(&a.2)).then(a.0.cmp(&b.0)));
+    let lines: Vec<_> = leaders
+        .into_iter()
+        .take(MAX_STREAK_LEADERS)
+        .enumerate()
+        .map(|(i, (user, current, longest))| {
+            tr(&locale, "streak.leader", &[
+                ("rank", &(i + 1)),
+                ("user", &serenity::UserId::new(user).mention()),
+                ("current", &current),
+                ("longest", &longest),
+            ])
+        })
+        .collect();
+    let embed = match lines.is_empty() {
+        true => response::embed(Tone::Info).description(tr(&locale, "streak.no_leaders", &[])),
+        false => response::embed(Tone::Info).title(tr(&locale, "streak.leaderboard", &[])).description(lines.join("\n")),
+    };
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// Days `/activity` charts
+const ACTIVITY_CHART_DAYS: u64 = 30;
+/// Characters the longest bar of the activity chart takes up
+const ACTIVITY_BAR_WIDTH: u32 = 20;
+
+/// Chart how many messages were accepted and duplicates deleted each day of the last 30
+#[poise::command(prefix_command, slash_command)]
+pub async fn activity(ctx: Context<'_>) -> Result<(), Error> {
+    let locale = i18n::locale(ctx);
+    let today = chrono::Utc::now().date_naive();
+    let first_day = today - chrono::Days::new(ACTIVITY_CHART_DAYS - 1);
+    let days: Vec<_> = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        let scope = command_scope(ctx, &messages_cache);
+        let activity = &messages_cache.channel(scope).activity;
+        first_day
+            .iter_days()
+            .take(ACTIVITY_CHART_DAYS as usize)
+            .map(|day| (day, activity.get(&day).copied().unwrap_or_default()))
+            .collect()
+    };
+    let busiest = days.iter().map(|(_, counts)| counts.accepted + counts.deleted).max().unwrap_or(0);
+    if busiest == 0 {
+        ctx.send(response::reply(Tone::Info, tr(&locale, "activity.none", &[("days", &ACTIVITY_CHART_DAYS)]))).await?;
+        return Ok(());
+    }
+    // Bars are scaled to the busiest day, rounding up so that no activity is left out
+    let bar = |count: u32| (count * ACTIVITY_BAR_WIDTH).div_ceil(busiest) as usize;
+    let lines: Vec<_> = days
+        .iter()
+        .map(|(day, counts)| {
+            format!(
+                "{} {}{}{} {}/{}",
+                day.format("%m-%d"),
+                "█".repeat(bar(counts.accepted)),
+                "░".repeat(bar(counts.deleted)),
+                " ".repeat((ACTIVITY_BAR_WIDTH as usize + 1).saturating_sub(bar(counts.accepted) + bar(counts.deleted))),
+                counts.accepted,
+                counts.deleted,
+            )
+        })
+        .collect();
+    let embed = response::embed(Tone::Info)
+        .title(tr(&locale, "activity.title", &[("days", &ACTIVITY_CHART_DAYS)]))
+        .description(format!("```\n{}\n```\n{}", lines.join("\n"), tr(&locale, "activity.legend", &[])));
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// Most matches `/search` lists
+const MAX_SEARCH_RESULTS: usize = 10;
+
+/// Search the cache for entries containing or resembling something, to see whether it was used
+///
+/// The query is normalized like messages are before it is searched for.
+#[poise::command(prefix_command, slash_command)]
+pub async fn search(
+    ctx: Context<'_>,
+    #[description = "What to search for"]
+    #[autocomplete = "autocomplete_entry"]
+    #[rest]
+    query: String,
+) -> Result<(), Error> {
+    let locale = i18n::locale(ctx);
+    let findings: Vec<String> = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        let scope = command_scope(ctx, &messages_cache);
+        let query = normalize_string(&query, &ctx.data().config().normalization_in(scope));
+        let cache = &messages_cache.channel(scope).cache;
+        dedup::search(cache, &query, MAX_SEARCH_RESULTS)
+            .into_iter()
+            .map(|(entry, similarity)| {
+                let metadata = &cache[entry];
+                let similarity = format!("{:.0}", similarity * 100.0);
+                let time = format!("<t:{}:f>", metadata.first_seen.timestamp());
+                match metadata.author {
+                    Some(author) => tr(&locale, "search.match", &[
+                        ("entry", &entry),
+                        ("similarity", &similarity),
+                        ("time", &time),
+                        ("author", &serenity::UserId::new(author).mention()),
+                    ]),
+                    None => tr(&locale, "search.match_unknown_author", &[("entry", &entry), ("similarity", &similarity), ("time", &time)]),
+                }
+            })
+            .collect()
+    };
+    let reply = match findings.is_empty() {
+        true => response::reply(Tone::Success, tr(&locale, "search.none", &[("query", &query)])),
+        false => response::reply(Tone::Info, findings.join("\n")),
+    };
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+/// Entries listed per page of `/recent`
+const RECENT_PER_PAGE: usize = 10;
+/// Entries listed per page by `/whitelist list` and `/banned list`
+const WHITELIST_PER_PAGE: usize = 20;
+
+/// List the most recent unique entries with who posted them and when
+#[poise::command(prefix_command, slash_command)]
+pub async fn recent(
+    ctx: Context<'_>,
+    #[description = "How many entries to list, 50 by default"]
+    #[min = 1]
+    #[max = 500]
+    count: Option<usize>,
+) -> Result<(), Error> {
+    let locale = i18n::locale(ctx);
+    let lines: Vec<String> = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        let scope = command_scope(ctx, &messages_cache);
+        messages_cache
+            .channel(scope)
+            .recent(count.unwrap_or(50).min(500))
+            .into_iter()
+            .map(|(entry, metadata)| {
+                let time = format!("<t:{}:R>", metadata.first_seen.timestamp());
+                match metadata.author {
+                    Some(author) => tr(&locale, "recent.entry", &[("entry", &entry), ("time", &time), ("author", &serenity::UserId::new(author).mention())]),
+                    None => tr(&locale, "recent.entry_unknown_author", &[("entry", &entry), ("time", &time)]),
+                }
+            })
+            .collect()
+    };
+    if lines.is_empty() {
+        ctx.send(response::reply(Tone::Info, tr(&locale, "recent.empty", &[]))).await?;
+        return Ok(());
+    }
+    let pages: Vec<String> = lines.chunks(RECENT_PER_PAGE).map(|page| page.join("\n")).collect();
+    let pages: Vec<&str> = pages.iter().map(String::as_str).collect();
+    poise::builtins::paginate(ctx, &pages).await?;
+    Ok(())
+}
+
+/// Re-normalize the cache with the current settings
+///
+/// Entries that now collide are merged and expired ones dropped. The cache file is archived first.
+#[poise::command(prefix_command, slash_command, check = "is_admin", ephemeral)]
+pub async fn compact(ctx: Context<'_>) -> Result<(), Error> {
+    let config = &ctx.data().config();
+    let archive_path = archive_the_data_file().await?;
+    let response = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        let before = messages_cache.total_entries();
+        let report = messages_cache.compact(config, chrono::Utc::now());
+        commit_to_disk(&messages_cache).await?;
+        describe_compaction(before, messages_cache.total_entries(), report)
+    };
+    let response = match archive_path {
+        Some(archive_path) => tr(&i18n::locale(ctx), "compact.archived", &[("report", &response), ("path", &archive_path.display())]),
+        None => response,
+    };
+    ctx.send(response::reply(Tone::Success, response)).await?;
+    Ok(())
+}
+/// Uploads are kept comfortably below Discord's limit for servers without boosts
+const MAX_EXPORT_FILE_SIZE: usize = 8 * 1024 * 1024;
+/// Most attachments a single message can have
+const MAX_ATTACHMENTS_PER_MESSAGE: usize = 10;
+
+/// Export all cached entries with their metadata as files
+///
+/// Large caches are split into several files.
+#[poise::command(prefix_command, slash_command, guild_only, check = "is_admin", ephemeral)]
+pub async fn export(
+    ctx: Context<'_>,
+    #[description = "File format, CSV by default"] format: Option<export::Format>,
+) -> Result<(), Error> {
+    let format = format.unwrap_or(export::Format::Csv);
+    ctx.defer_ephemeral().await?;
+    let (files, entry_count) = {
+        let messages_cache = ctx.data().messages_cache.lock().await;
+        (export::export(&messages_cache, format, MAX_EXPORT_FILE_SIZE), messages_cache.total_entries())
+    };
+    let file_count = files.len();
+    let attachments: Vec<_> = files
+        .into_iter()
+        .enumerate()
+        .map(|(i, contents)| {
+            let filename = match file_count {
+                1 => format!("set-bot-export.{}", format.extension()),
+                _ => format!("set-bot-export-{}-of-{}.{}", i + 1, file_count, format.extension()),
+            };
+            serenity::CreateAttachment::bytes(contents, filename)
+        })
+        .collect();
+    for (i, chunk) in attachments.chunks(MAX_ATTACHMENTS_PER_MESSAGE).enumerate() {
+        let mut reply = poise::CreateReply::default();
+        if i == 0 {
+            reply = response::reply(Tone::Success, tr(&i18n::locale(ctx), "export.exported", &[("entries", &entry_count), ("files", &file_count)]));
+        }
+        for attachment in chunk {
+            reply = reply.attachment(attachment.clone());
+        }
+        ctx.send(reply).await?;
+    }
+    Ok(())
+}
+/// Pre-seed the cache with entries from an attached file
+///
+/// Accepts the bot's own exports, `messages.csv`/`messages.json` from a Discord data package and
+/// text files with one entry per line.
+#[poise::command(prefix_command, slash_command, check = "is_admin", ephemeral)]
+pub async fn import(
+    ctx: Context<'_>,
+    #[description = "File with the entries to import"] file: serenity::Attachment,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+    let contents = String::from_utf8(file.download().await?)?;
+    let archive_path = archive_the_data_file().await?;
+    let report = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        let scope = command_scope(ctx, &messages_cache);
+        let report = import::import(messages_cache.channel(scope), &contents, &ctx.data().config().normalization_in(scope))?;
+        commit_to_disk(&messages_cache).await?;
+        report
+    };
+    let response = match archive_path {
+        Some(archive_path) => tr(&i18n::locale(ctx), "compact.archived", &[("report", &report), ("path", &archive_path.display())]),
+        None => report.to_string(),
+    };
+    ctx.send(response::reply(Tone::Success, response)).await?;
+    Ok(())
+}
+
+/// View or change settings of the config without restarting the bot
+#[poise::command(prefix_command, slash_command, subcommands("config_view", "config_set", "config_reset"), subcommand_required, check = "is_admin", ephemeral)]
+pub async fn config(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show a setting, or every setting changed with `/config set` if none is given
+#[poise::command(prefix_command, slash_command, rename = "view", check = "is_admin", ephemeral)]
+pub async fn config_view(
+    ctx: Context<'_>,
+    #[description = "Dotted key of the setting, e.g. enforcement.mode"] key: Option<String>,
+) -> Result<(), Error> {
+    let locale = i18n::locale(ctx);
+    let Some(key) = key else {
+        let messages_cache = ctx.data().messages_cache.lock().await;
+        let response = if messages_cache.settings.is_empty() {
+            tr(&locale, "config.no_changes", &[])
+        } else {
+            let settings: Vec<_> = messages_cache.settings
+                .iter()
+                .map(|(key, value)| format!("`{}` = `{}`", key, value))
+                .collect();
+            format!("{}\n{}", tr(&locale, "config.changes", &[]), settings.join("\n"))
+        };
+        drop(messages_cache);
+        ctx.send(response::reply(Tone::Info, response)).await?;
+        return Ok(());
+    };
+    let config = ctx.data().config();
+    let response = match get_key(&config.source, &key) {
+        Some(value) => format!("`{}` = `{}`", key, value),
+        None => tr(&locale, "config.unset", &[("key", &key)]),
+    };
+    ctx.send(response::reply(Tone::Info, response)).await?;
+    Ok(())
+}
+
+/// Change a setting, which is kept over restarts and takes precedence over the config file
+///
+/// The value is written in TOML, e.g. `"react"` for a string or `[1, 2]` for a list. Settings
+/// that are only read at startup, such as the storage, backups and summaries, apply after a
+/// restart.
+#[poise::command(prefix_command, slash_command, rename = "set", check = "is_admin", ephemeral)]
+pub async fn config_set(
+    ctx: Context<'_>,
+    #[description = "Dotted key of the setting, e.g. enforcement.mode"] key: String,
+    #[description = "New value, written in TOML"] value: String,
+) -> Result<(), Error> {
+    change_settings(ctx, |settings| {
+        settings.insert(key.clone(), value.clone());
+    })
+    .await?;
+    log::info!("{} set {} to {}", ctx.author().id, key, value);
+    ctx.send(response::reply(Tone::Success, tr(&i18n::locale(ctx), "config.set", &[("key", &key), ("value", &value)]))).await?;
+    Ok(())
+}
+
+/// Changes the settings kept like those of `/config set` and applies them
+///
+/// Only settings that make for a valid config are kept, otherwise nothing changes.
+async fn change_settings(ctx: Context<'_>, change: impl FnOnce(&mut BTreeMap<String, String>)) -> Result<(), Error> {
+    let mut messages_cache = ctx.data().messages_cache.lock().await;
+    let mut settings = messages_cache.settings.clone();
+    change(&mut settings);
+    let config = Config::try_load_with(&ctx.data().config().path, &settings)?;
+    messages_cache.settings = settings;
+    commit_to_disk(&messages_cache).await?;
+    ctx.data().set_config(config);
+    Ok(())
+}
+
+/// Undo `/config set` for a setting, so the config file applies to it again
+#[poise::command(prefix_command, slash_command, rename = "reset", check = "is_admin", ephemeral)]
+pub async fn config_reset(
+    ctx: Context<'_>,
+    #[description = "Dotted key of the setting, e.g. enforcement.mode"] key: String,
+) -> Result<(), Error> {
+    let reset = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        let mut settings = messages_cache.settings.clone();
+        let reset = settings.remove(&key).is_some();
+        if reset {
+            let config = Config::try_load_with(&ctx.data().config().path, &settings)?;
+            messages_cache.settings = settings;
+            commit_to_disk(&messages_cache).await?;
+            ctx.data().set_config(config);
+        }
+        reset
+    };
+    if reset {
+        log::info!("{} reset {}", ctx.author().id, key);
+        ctx.send(response::reply(Tone::Success, tr(&i18n::locale(ctx), "config.reset", &[("key", &key)]))).await?;
+    } else {
+        ctx.send(response::reply(Tone::Warning, tr(&i18n::locale(ctx), "config.not_changed", &[("key", &key)]))).await?;
+    }
+    Ok(())
+}
+
+/// Manage the entries that may be repeated freely, e.g. "gg"
+#[poise::command(prefix_command, slash_command, subcommands("whitelist_add", "whitelist_remove", "whitelist_list"), subcommand_required, check = "is_admin", ephemeral)]
+pub async fn whitelist(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Let an entry be repeated freely
+///
+/// The entry is normalized the same way messages are.
+#[poise::command(prefix_command, slash_command, rename = "add", check = "is_admin", ephemeral)]
+pub async fn whitelist_add(
+    ctx: Context<'_>,
+    #[description = "Entry to whitelist"]
+    #[rest]
+    entry: String,
+) -> Result<(), Error> {
+    let scope = command_scope(ctx, &*ctx.data().messages_cache.lock().await);
+    let entry = normalize_string(&entry, &ctx.data().config().normalization_in(scope));
+    let locale = i18n::locale(ctx);
+    if ctx.data().appeals.whitelist(scope, entry.clone()).await? {
+        log::info!("{} whitelisted {:?}", ctx.author().id, entry);
+        ctx.send(response::reply(Tone::Success, tr(&locale, "whitelist.added", &[("entry", &entry)]))).await?;
+    } else {
+        ctx.send(response::reply(Tone::Warning, tr(&locale, "whitelist.already_added", &[("entry", &entry)]))).await?;
+    }
+    Ok(())
+}
+
+/// Make a whitelisted entry count as a duplicate again
+#[poise::command(prefix_command, slash_command, rename = "remove", check = "is_admin", ephemeral)]
+pub async fn whitelist_remove(
+    ctx: Context<'_>,
+    #[description = "Entry to remove from the whitelist"]
+    #[rest]
+    entry: String,
+) -> Result<(), Error> {
+    let scope = command_scope(ctx, &*ctx.data().messages_cache.lock().await);
+    let entry = normalize_string(&entry, &ctx.data().config().normalization_in(scope));
+    let locale = i18n::locale(ctx);
+    if ctx.data().appeals.unwhitelist(scope, &entry).await? {
+        log::info!("{} removed {:?} from the whitelist", ctx.author().id, entry);
+        ctx.send(response::reply(Tone::Success, tr(&locale, "whitelist.removed", &[("entry", &entry)]))).await?;
+    } else {
+        ctx.send(response::reply(Tone::Warning, tr(&locale, "whitelist.not_added", &[("entry", &entry)]))).await?;
+    }
+    Ok(())
+}
+
+/// List the whitelisted entries, including those approved on appeal
+#[poise::command(prefix_command, slash_command, rename = "list", check = "is_admin", ephemeral)]
+pub async fn whitelist_list(ctx: Context<'_>) -> Result<(), Error> {
+    let scope = command_scope(ctx, &*ctx.data().messages_cache.lock().await);
+    let locale = i18n::locale(ctx);
+    let entries = ctx.data().appeals.whitelisted(scope).await;
+    if entries.is_empty() {
+        ctx.send(response::reply(Tone::Info, tr(&locale, "whitelist.empty", &[]))).await?;
+        return Ok(());
+    }
+    let lines: Vec<String> = entries.iter().map(|entry| format!("- `{}`", entry)).collect();
+    let pages: Vec<String> = lines
+        .chunks(WHITELIST_PER_PAGE)
+        .map(|page| format!("{}\n{}", tr(&locale, "whitelist.list", &[]), page.join("\n")))
+        .collect();
+    let pages: Vec<&str> = pages.iter().map(String::as_str).collect();
+    poise::builtins::paginate(ctx, &pages).await?;
+    Ok(())
+}
+
+/// Manage the entries that are always deleted, even the first time they are posted
+#[poise::command(prefix_command, slash_command, subcommands("banned_add", "banned_remove", "banned_list"), subcommand_required, check = "is_admin", ephemeral)]
+pub async fn banned(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Ban an entry, so messages with it are always deleted
+///
+/// The entry is normalized the same way messages are.
+#[poise::command(prefix_command, slash_command, rename = "add", check = "is_admin", ephemeral)]
+pub async fn banned_add(
+    ctx: Context<'_>,
+    #[description = "Entry to ban"]
+    #[rest]
+    entry: String,
+) -> Result<(), Error> {
+    let (entry, added) = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        let scope = command_scope(ctx, &messages_cache);
+        let entry = normalize_string(&entry, &ctx.data().config().normalization_in(scope));
+        let added = messages_cache.banned.insert(entry.clone());
+        if added {
+            commit_to_disk(&messages_cache).await?;
+        }
+        (entry, added)
     };
-    let bot_user = ctx.http().get_current_user().await?;
-    let permissions = channel.permissions_for_user(&ctx, bot_user.id)?;
+    let locale = i18n::locale(ctx);
+    if added {
+        log::info!("{} banned {:?}", ctx.author().id, entry);
+        ctx.send(response::reply(Tone::Success, tr(&locale, "banned.added", &[("entry", &entry)]))).await?;
+    } else {
+        ctx.send(response::reply(Tone::Warning, tr(&locale, "banned.already_added", &[("entry", &entry)]))).await?;
+    }
+    Ok(())
+}
 
-    let mut all_correct = true;
-    if !permissions.contains(serenity::Permissions::MANAGE_MESSAGES) {
-        all_correct = false;
-        ctx.say(format!("Bot user does not have the MANAGE_MESSAGES permission for Channel {}", channel_id)).await?;
+/// Lift the ban of an entry
+#[poise::command(prefix_command, slash_command, rename = "remove", check = "is_admin", ephemeral)]
+pub async fn banned_remove(
+    ctx: Context<'_>,
+    #[description = "Entry to lift the ban of"]
+    #[rest]
+    entry: String,
+) -> Result<(), Error> {
+    let (entry, removed) = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        let scope = command_scope(ctx, &messages_cache);
+        let entry = normalize_string(&entry, &ctx.data().config().normalization_in(scope));
+        let removed = messages_cache.banned.remove(&entry);
+        if removed {
+            commit_to_disk(&messages_cache).await?;
+        }
+        (entry, removed)
+    };
+    let locale = i18n::locale(ctx);
+    if removed {
+        log::info!("{} lifted the ban of {:?}", ctx.author().id, entry);
+        ctx.send(response::reply(Tone::Success, tr(&locale, "banned.removed", &[("entry", &entry)]))).await?;
+    } else {
+        ctx.send(response::reply(Tone::Warning, tr(&locale, "banned.not_added", &[("entry", &entry)]))).await?;
     }
-    if !permissions.contains(serenity::Permissions::READ_MESSAGE_HISTORY) {
-        all_correct = false;
-        ctx.say(format!("Bot user does not have the READ_MESSAGE_HISTORY permission for Channel {}", channel_id)).await?;
+    Ok(())
+}
+
+/// List the banned entries
+#[poise::command(prefix_command, slash_command, rename = "list", check = "is_admin", ephemeral)]
+pub async fn banned_list(ctx: Context<'_>) -> Result<(), Error> {
+    let locale = i18n::locale(ctx);
+    let mut entries: Vec<String> = ctx.data().messages_cache.lock().await.banned.iter().cloned().collect();
+    if entries.is_empty() {
+        ctx.send(response::reply(Tone::Info, tr(&locale, "banned.empty", &[]))).await?;
+        return Ok(());
     }
-    if all_correct {
-        ctx.say("No incorrect settings for bot user were detected.").await?;
+    entries.sort_unstable();
+    let lines: Vec<String> = entries.iter().map(|entry| format!("- `{}`", entry)).collect();
+    let pages: Vec<String> = lines
+        .chunks(WHITELIST_PER_PAGE)
+        .map(|page| format!("{}\n{}", tr(&locale, "banned.list", &[]), page.join("\n")))
+        .collect();
+    let pages: Vec<&str> = pages.iter().map(String::as_str).collect();
+    poise::builtins::paginate(ctx, &pages).await?;
+    Ok(())
+}
+
+/// Set or show the theme entries must follow, e.g. a theme of the day
+#[poise::command(prefix_command, slash_command, subcommands("theme_set", "theme_clear", "theme_show"), subcommand_required, ephemeral)]
+pub async fn theme(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Set the theme, which applies on top of the channels' other rules
+///
+/// The theme is kept like settings changed with `/config set`, until it is cleared.
+#[poise::command(prefix_command, slash_command, rename = "set", check = "is_admin", ephemeral)]
+pub async fn theme_set(
+    ctx: Context<'_>,
+    #[description = "How entries must match the theme"] kind: ThemeKind,
+    #[description = "Prefix, word or regular expression"]
+    #[rest]
+    value: String,
+) -> Result<(), Error> {
+    change_settings(ctx, |settings| {
+        settings.insert("theme.kind".to_owned(), toml::Value::from(kind.as_str()).to_string());
+        settings.insert("theme.value".to_owned(), toml::Value::from(value.as_str()).to_string());
+    })
+    .await?;
+    log::info!("{} set the theme to {:?} ({})", ctx.author().id, value, kind.as_str());
+    let locale = i18n::locale(ctx);
+    let theme = crate::theme::describe(&locale, kind, &value);
+    ctx.send(response::reply(Tone::Success, tr(&locale, "theme.set", &[("theme", &theme)]))).await?;
+    Ok(())
+}
+
+/// Clear the theme, so any entry goes again
+#[poise::command(prefix_command, slash_command, rename = "clear", check = "is_admin", ephemeral)]
+pub async fn theme_clear(ctx: Context<'_>) -> Result<(), Error> {
+    change_settings(ctx, |settings| {
+        // Also overrides a theme in the config file
+        settings.remove("theme.kind");
+        settings.insert("theme.value".to_owned(), "\"\"".to_owned());
+    })
+    .await?;
+    log::info!("{} cleared the theme", ctx.author().id);
+    ctx.send(response::reply(Tone::Success, tr(&i18n::locale(ctx), "theme.cleared", &[]))).await?;
+    Ok(())
+}
+
+/// Show the current theme
+#[poise::command(prefix_command, slash_command, rename = "show", ephemeral)]
+pub async fn theme_show(ctx: Context<'_>) -> Result<(), Error> {
+    let locale = i18n::locale(ctx);
+    let config = ctx.data().config();
+    let response = match &config.theme.value {
+        Some(value) => crate::theme::describe(&locale, config.theme.kind, value),
+        None => tr(&locale, "theme.none", &[]),
+    };
+    ctx.send(response::reply(Tone::Info, response)).await?;
+    Ok(())
+}
+
+/// Play SET: find three cards whose every attribute is the same on all of them or different on each
+#[poise::command(prefix_command, slash_command, subcommands("set_start", "set_claim", "set_board"), subcommand_required)]
+pub async fn set(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Start a game of SET in this channel, dealing a new board
+///
+/// The game is kept with the cache, so it survives restarts. Only one game runs per channel.
+#[poise::command(prefix_command, slash_command, rename = "start")]
+pub async fn set_start(ctx: Context<'_>) -> Result<(), Error> {
+    let locale = i18n::locale(ctx);
+    if !ctx.data().config().cards.channels.contains(&ctx.channel_id()) {
+        ctx.send(response::reply(Tone::Warning, tr(&locale, "cards.not_here", &[])).ephemeral(true)).await?;
+        return Ok(());
+    }
+    let (game, started) = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        match messages_cache.card_games.get(&ctx.channel_id()).filter(|game| !game.is_over()) {
+            Some(game) => (game.clone(), false),
+            None => {
+                let game = CardGame::new(shuffled_deck(crate::cards::seed()), chrono::Utc::now());
+                messages_cache.card_games.insert(ctx.channel_id(), game.clone());
+                commit_to_disk(&messages_cache).await?;
+                (game, true)
+            }
+        }
+    };
+    let board = crate::cards::board(&locale, &game);
+    if started {
+        log::info!("{} started a game of SET in {}", ctx.author().id, ctx.channel_id());
+        ctx.send(poise::CreateReply::default().embed(board)).await?;
+    } else {
+        let reply = poise::CreateReply::default().content(tr(&locale, "cards.running", &[])).embed(board);
+        ctx.send(reply.ephemeral(true)).await?;
+    }
+    Ok(())
+}
+
+/// Claim three cards of the board as a SET, naming them by their labels
+///
+/// Finding a SET scores a point and replaces its cards. Claiming cards that don't form one costs
+/// `cards.penalty` points.
+#[poise::command(prefix_command, slash_command, rename = "claim")]
+pub async fn set_claim(
+    ctx: Context<'_>,
+    #[description = "Label of the first card"] a: String,
+    #[description = "Label of the second card"] b: String,
+    #[description = "Label of the third card"] c: String,
+) -> Result<(), Error> {
+    let locale = i18n::locale(ctx);
+    let positions = [&a, &b, &c].map(|label| crate::cards::position(label));
+    let [Some(first), Some(second), Some(third)] = positions else {
+        let label = [&a, &b, &c].into_iter().zip(positions).find(|(_, position)| position.is_none()).map(|(label, _)| label.trim());
+        ctx.send(response::reply(Tone::Warning, tr(&locale, "cards.not_a_label", &[("label", &label.unwrap_or_default())])).ephemeral(true)).await?;
+        return Ok(());
+    };
+    let player = ctx.author().id;
+    let penalty = ctx.data().config().cards.penalty;
+    let claimed = {
+        let mut messages_cache = ctx.data().messages_cache.lock().await;
+        match messages_cache.card_games.get_mut(&ctx.channel_id()).filter(|game| !game.is_over()) {
+            Some(game) => {
+                let result = game.claim([first, second, third], player.get());
+                if result == Err(ClaimError::NotASet) {
+                    game.penalize(player.get(), penalty);
+                }
+                let score = game.scores.get(&player.get()).copied().unwrap_or_default();
+                let game = game.clone();
+                if matches!(result, Ok(_) | Err(ClaimError::NotASet)) {
+                    commit_to_disk(&messages_cache).await?;
+                }
+                Some((result, score, game))
+            }
+            None => None,
+        }
+    };
+    let Some((result, score, game)) = claimed else {
+        ctx.send(response::reply(Tone::Warning, tr(&locale, "cards.none", &[])).ephemeral(true)).await?;
+        return Ok(());
+    };
+    match result {
+        Ok(cards) => {
+            log::info!("{} found a SET in {}", player, ctx.channel_id());
+            let cards = crate::cards::describe_cards(&locale, &cards);
+            let found = tr(&locale, "cards.found", &[("player", &player.mention()), ("cards", &cards), ("score", &score)]);
+            let reply = if game.is_over() {
+                let standings = crate::cards::standings(&locale, &game);
+                response::reply(Tone::Success, format!("{}\n\n{}\n{}", found, tr(&locale, "cards.over", &[]), standings))
+            } else {
+                response::reply(Tone::Success, found).embed(crate::cards::board(&locale, &game))
+            };
+            ctx.send(reply).await?;
+        }
+        Err(ClaimError::NotASet) => {
+            let response = tr(&locale, "cards.not_a_set", &[("player", &player.mention()), ("penalty", &penalty), ("score", &score)]);
+            ctx.send(response::reply(Tone::Warning, response)).await?;
+        }
+        Err(ClaimError::NotOnBoard(position)) => {
+            let label = crate::cards::label(position);
+            ctx.send(response::reply(Tone::Warning, tr(&locale, "cards.not_on_board", &[("label", &label)])).ephemeral(true)).await?;
+        }
+        Err(ClaimError::SameCard) => {
+            ctx.send(response::reply(Tone::Warning, tr(&locale, "cards.same_card", &[])).ephemeral(true)).await?;
+        }
     }
     Ok(())
 }
 
+/// Show the board of this channel's game of SET
+#[poise::command(prefix_command, slash_command, rename = "board")]
+pub async fn set_board(ctx: Context<'_>) -> Result<(), Error> {
+    let locale = i18n::locale(ctx);
+    let game = ctx.data().messages_cache.lock().await.card_games.get(&ctx.channel_id()).cloned();
+    match game {
+        Some(game) => ctx.send(poise::CreateReply::default().embed(crate::cards::board(&locale, &game))).await?,
+        None => ctx.send(response::reply(Tone::Warning, tr(&locale, "cards.none", &[])).ephemeral(true)).await?,
+    };
+    Ok(())
+}
+
+/// Show how long the bot has been running
+#[poise::command(prefix_command, slash_command)]
+pub async fn uptime(ctx: Context<'_>) -> Result<(), Error> {
+    let locale = i18n::locale(ctx);
+    let uptime = Duration::from_secs(ctx.data().started_at.elapsed().as_secs());
+    let mut response = tr(&locale, "about.uptime", &[("uptime", &humantime::format_duration(uptime))]);
+    let connection = &ctx.data().connection;
+    response.push(' ');
+    response.push_str(&tr(&locale, "about.connection", &[("reconnects", &connection.reconnects()), ("resumes", &connection.resumes())]));
+    ctx.send(response::reply(Tone::Info, response)).await?;
+    Ok(())
+}
+
+/// Show which build of the bot is running and where its source is
+#[poise::command(prefix_command, slash_command)]
+pub async fn about(ctx: Context<'_>) -> Result<(), Error> {
+    let locale = i18n::locale(ctx);
+    let built_at = format!("<t:{}:f>", env!("SET_BUILT_AT"));
+    let uptime = Duration::from_secs(ctx.data().started_at.elapsed().as_secs());
+    let embed = response::embed(Tone::Info)
+        .title(concat!("set v", env!("CARGO_PKG_VERSION")))
+        .field(tr(&locale, "about.commit", &[]), format!("`{}`", env!("SET_GIT_HASH")), true)
+        .field(tr(&locale, "about.built", &[]), built_at, true)
+        .field(tr(&locale, "about.running_for", &[]), humantime::format_duration(uptime).to_string(), true)
+        .field(tr(&locale, "about.storage", &[]), get_the_store().name(), true)
+        .field(tr(&locale, "about.source", &[]), env!("CARGO_PKG_REPOSITORY"), false);
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// Save the cache and shut the bot down
+#[poise::command(prefix_command, slash_command, owners_only, hide_in_help, ephemeral)]
+pub async fn shutdown(ctx: Context<'_>) -> Result<(), Error> {
+    let locale = i18n::locale(ctx);
+    log::info!("{} asked to shut down", ctx.author().id);
+    // Waits for a running catch-up, which would otherwise be cut off before saving what it handled
+    let _catching_up = ctx.data().catching_up.lock().await;
+    commit_to_disk(&*ctx.data().messages_cache.lock().await).await?;
+    let pending = ctx.data().deletions.pending();
+    let response = match pending {
+        0 => tr(&locale, "shutdown.done", &[]),
+        _ => tr(&locale, "shutdown.pending", &[("count", &pending)]),
+    };
+    ctx.send(response::reply(Tone::Success, response)).await?;
+    log::info!("Shutting down");
+    ctx.framework().shard_manager().shutdown_all().await;
+    Ok(())
+}
+
+/// Catch up on the messages sent since the bot last saw them, as done at startup
+#[poise::command(prefix_command, slash_command, owners_only, hide_in_help, ephemeral)]
+pub async fn restartcatchup(ctx: Context<'_>) -> Result<(), Error> {
+    let locale = i18n::locale(ctx);
+    ctx.defer_ephemeral().await?;
+    log::info!("{} asked to catch up again", ctx.author().id);
+    events::catch_up_on_everything(ctx.serenity_context(), ctx.data()).await?;
+    ctx.send(response::reply(Tone::Success, tr(&locale, "restartcatchup.done", &[]))).await?;
+    Ok(())
+}
+
+/// Register or unregister the slash commands, globally or in this guild
+///
+/// Commands are registered at startup, so this is only needed after they changed while the bot
+/// runs or to clean up after `DEV_GUILD_ID`.
+#[poise::command(prefix_command, owners_only, hide_in_help)]
+pub async fn register(ctx: Context<'_>) -> Result<(), Error> {
+    poise::builtins::register_application_commands_buttons(ctx).await?;
+    Ok(())
+}
 
 ///// Vote for something
 /////
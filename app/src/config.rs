@@ -0,0 +1,981 @@
+#[cfg(feature = "scripting")]
+use crate::script;
+use crate::{
+    dictionary, i18n,
+    store::{Backend, Compression},
+};
+use poise::serenity_prelude as serenity;
+use regex::Regex;
+use serde::Deserialize;
+use set_core::{
+    config::{AttachmentConfig, FuzzyConfig, MatchingConfig, NormalizationConfig, StemmingLanguage, TtlConfig},
+    dedup,
+};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashSet},
+    env, fs, path,
+    time::Duration,
+};
+
+/// Bot configuration, read from `config.toml`
+///
+/// Every field has a default, so the file (and any section in it) may be omitted.
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct Config {
+    pub blocklist: BlocklistConfig,
+    pub banned: BannedConfig,
+    pub fuzzy: FuzzyConfig,
+    pub matching: MatchingConfig,
+    pub normalization: NormalizationConfig,
+    pub attachments: AttachmentConfig,
+    pub embeds: EmbedConfig,
+    pub ignore: IgnoreConfig,
+    pub ttl: TtlConfig,
+    pub milestones: MilestoneConfig,
+    pub presence: PresenceConfig,
+    pub threads: ThreadConfig,
+    pub alerts: AlertConfig,
+    pub catch_up: CatchUpConfig,
+    pub storage: StorageConfig,
+    pub backup: BackupConfig,
+    pub summary: SummaryConfig,
+    pub theme: ThemeConfig,
+    pub once_per_day: OncePerDayConfig,
+    pub heartbeat: HeartbeatConfig,
+    pub appeals: AppealConfig,
+    pub quarantine: QuarantineConfig,
+    pub webhook: WebhookConfig,
+    pub api: ApiConfig,
+    pub telemetry: TelemetryConfig,
+    pub enforcement: EnforcementConfig,
+    pub cooldown: CooldownConfig,
+    pub command_cooldown: CommandCooldownConfig,
+    pub rate_limit: RateLimitConfig,
+    pub repeats: RepeatConfig,
+    pub length: LengthConfig,
+    pub dictionary: DictionaryConfig,
+    pub games: GamesConfig,
+    pub cards: CardsConfig,
+    pub rules: RulesConfig,
+    pub lines: LinesConfig,
+    pub admin: AdminConfig,
+    pub i18n: I18nConfig,
+    /// Where the config was read from
+    #[serde(skip)]
+    pub path: path::PathBuf,
+    /// The config file's settings, with those changed by `/config set` applied
+    #[serde(skip)]
+    pub source: toml::Table,
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct BlocklistConfig {
+    /// Also ignore messages from blocked users, so they are neither cached nor deleted
+    pub ignore_messages: bool,
+}
+
+/// Entries banned with `/banned add` are deleted regardless of the channel's rules
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct BannedConfig {
+    /// Tell the author why their message was deleted
+    pub notify: bool,
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct EmbedConfig {
+    /// Deduplicate embeds by their URL (or title, if they have none)
+    pub enabled: bool,
+}
+
+/// Messages matching any of these rules are neither cached nor deleted
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct IgnoreConfig {
+    /// Ignore messages starting with any of these, e.g. other bots' command prefixes
+    pub prefixes: Vec<String>,
+    /// Ignore messages sent by bots
+    pub bots: bool,
+    /// Ignore messages sent by webhooks
+    pub webhooks: bool,
+    /// Ignore replies to other messages
+    pub replies: bool,
+    /// Ignore messages matching any of these regular expressions
+    pub patterns: Vec<String>,
+    #[serde(skip)]
+    pub compiled_patterns: Vec<Regex>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MilestoneConfig {
+    /// Numbers of unique entries in a round that get a celebratory message
+    pub counts: Vec<u64>,
+    /// Announcement text, `{count}` is replaced by the milestone reached
+    pub message: String,
+    /// Pin the announcement in the channel
+    pub pin: bool,
+}
+impl Default for MilestoneConfig {
+    fn default() -> Self {
+        Self {
+            counts: vec![100, 1_000, 10_000],
+            message: "🎉 This round just reached {count} unique entries!".to_owned(),
+            pin: false,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PresenceConfig {
+    /// Show the number of unique entries as the bot's activity
+    pub enabled: bool,
+    /// Activity text after "Watching", `{count}` is replaced by the number of entries
+    pub template: String,
+    /// How often the activity is refreshed
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+}
+impl Default for PresenceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            template: "{count} unique entries".to_owned(),
+            interval: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ThreadMode {
+    /// Threads share the cache of the channel they were created in
+    #[default]
+    Shared,
+    /// Every thread has a cache of its own
+    Separate,
+    /// Messages in threads are not handled
+    Ignore,
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ThreadConfig {
+    pub mode: ThreadMode,
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct AlertConfig {
+    /// Channel that problems found by the bot (e.g. missing permissions) are reported in.
+    /// Without one, the bot's owner is sent a DM instead.
+    pub log_channel: Option<serenity::ChannelId>,
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct AdminConfig {
+    /// Roles whose members may use the administrative commands, besides those who can manage the
+    /// server
+    pub roles: Vec<serenity::RoleId>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct I18nConfig {
+    /// Discord locale of what the bot says in channels, and in answers to prefix commands. Slash
+    /// commands are answered in the user's own locale when there is a translation for it.
+    pub locale: String,
+}
+impl Default for I18nConfig {
+    fn default() -> Self {
+        Self {
+            locale: i18n::DEFAULT_LOCALE.to_owned(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CatchUpConfig {
+    /// Pause between deletion requests while catching up, to stay clear of rate limits
+    #[serde(with = "humantime_serde")]
+    pub delete_interval: Duration,
+}
+impl Default for CatchUpConfig {
+    fn default() -> Self {
+        Self {
+            delete_interval: Duration::from_millis(500),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// Where the cache is persisted. The JSON file's path is set on the command line instead.
+    pub backend: Backend,
+    /// Compression of the JSON file
+    pub compression: Compression,
+    pub sqlite_path: path::PathBuf,
+    pub redis_url: String,
+    /// Prepended to every Redis key, so one server can hold several bots' data
+    pub redis_prefix: String,
+    /// Size of the PostgreSQL connection pool. The database itself is set by `DATABASE_URL`.
+    pub postgres_max_connections: u32,
+    /// Directory of the sled database
+    pub sled_path: path::PathBuf,
+}
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: Backend::default(),
+            compression: Compression::default(),
+            sqlite_path: "set-bot-cache.sqlite".into(),
+            redis_url: "redis://127.0.0.1/".to_owned(),
+            redis_prefix: "set".to_owned(),
+            postgres_max_connections: 5,
+            sled_path: "set-bot-cache.sled".into(),
+        }
+    }
+}
+
+/// Uploads of the cache to an S3-compatible bucket, in the format of the JSON file
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BackupConfig {
+    /// Upload backups, which needs the `s3` feature. The credentials are read from
+    /// `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`.
+    pub enabled: bool,
+    pub bucket: String,
+    pub region: String,
+    /// URL of the S3-compatible service, AWS itself if omitted
+    pub endpoint: Option<String>,
+    /// Prepended to the name of every backup, e.g. a directory like `set/`
+    pub prefix: String,
+    /// How often a backup is uploaded. Another one is uploaded when the bot shuts down.
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    /// How many of the newest backups are kept, all of them if 0
+    pub keep: usize,
+    /// Backups older than this are deleted, even if fewer than `keep` are left
+    #[serde(with = "humantime_serde")]
+    pub max_age: Option<Duration>,
+}
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bucket: String::new(),
+            region: "us-east-1".to_owned(),
+            endpoint: None,
+            prefix: String::new(),
+            interval: Duration::from_secs(24 * 3600),
+            keep: 30,
+            max_age: None,
+        }
+    }
+}
+
+/// Summaries of the activity in the watched channel, posted on a schedule
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SummaryConfig {
+    pub enabled: bool,
+    /// When summaries are posted, as a cron expression with seconds in UTC, e.g.
+    /// `0 0 9 * * *` for daily at 9:00 or `0 0 9 * * Mon` for weekly
+    pub schedule: String,
+    #[serde(skip)]
+    pub compiled_schedule: Option<cron::Schedule>,
+    /// Channel the summaries are posted in, the watched channel if omitted
+    pub channel: Option<serenity::ChannelId>,
+    /// How many of the most active contributors are listed
+    pub top_contributors: usize,
+}
+impl Default for SummaryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            schedule: "0 0 9 * * *".to_owned(),
+            compiled_schedule: None,
+            channel: None,
+            top_contributors: 3,
+        }
+    }
+}
+
+/// A sign of life every `interval`, so operators notice when the bot dies or loses the gateway
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HeartbeatConfig {
+    pub enabled: bool,
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    /// Channel with a status message that is edited on every heartbeat, e.g. a private one
+    pub channel: Option<serenity::ChannelId>,
+    /// Health check pinged with a GET request on every heartbeat, e.g. on healthchecks.io
+    pub url: Option<String>,
+}
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: Duration::from_secs(5 * 60),
+            channel: None,
+            url: None,
+        }
+    }
+}
+
+/// Appeals of deleted duplicates, decided by moderators
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AppealConfig {
+    /// Let authors of deleted duplicates appeal
+    pub enabled: bool,
+    /// Channel appeals are sent to for moderators to approve or deny
+    pub moderator_channel: Option<serenity::ChannelId>,
+    /// Offer the appeal by DM, falling back to the channel if the author doesn't accept DMs
+    pub dm: bool,
+}
+impl Default for AppealConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            moderator_channel: None,
+            dm: true,
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct QuarantineConfig {
+    /// Channel deleted duplicates are forwarded to, with their author and why they were deleted
+    pub channel: Option<serenity::ChannelId>,
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct WebhookConfig {
+    /// Where deleted duplicates are posted to as JSON, e.g. for a moderation dashboard
+    pub url: Option<String>,
+    /// Sent as a bearer token, so the receiver can tell the requests come from the bot
+    pub token: Option<String>,
+}
+
+/// A read-only HTTP API over the cache, e.g. for a website showing the entries live
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ApiConfig {
+    /// Serve the API, which needs the `api` feature
+    pub enabled: bool,
+    /// Where the API listens
+    pub address: std::net::SocketAddr,
+    /// Bearer token every request must have
+    pub token: String,
+}
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: ([127, 0, 0, 1], 8080).into(),
+            token: String::new(),
+        }
+    }
+}
+
+/// Export of traces over OTLP, e.g. to Jaeger or Grafana Tempo
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    /// Export traces, which needs the `otel` feature
+    pub enabled: bool,
+    /// The collector's gRPC endpoint
+    pub endpoint: String,
+    /// What the traces are attributed to
+    pub service_name: String,
+}
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: "http://localhost:4317".to_owned(),
+            service_name: "set".to_owned(),
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LinesAction {
+    /// The whole message is deleted
+    #[default]
+    Delete,
+    /// The message is left in place, and a reply lists its duplicate lines
+    Reply,
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct LinesConfig {
+    /// Treat each non-empty line of a message as an entry of its own
+    pub split: bool,
+    /// What happens to a message with a duplicate line
+    pub action: LinesAction,
+}
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EnforcementMode {
+    /// Duplicates are deleted
+    #[default]
+    Delete,
+    /// Duplicates are flagged with a reaction and left in place
+    React,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct EnforcementConfig {
+    pub mode: EnforcementMode,
+    /// Reaction flagging duplicates in the `react` mode, a Unicode emoji or a custom one like `<:name:id>`
+    pub emoji: String,
+    /// Also reply to flagged duplicates saying what they duplicate
+    pub note: bool,
+}
+impl Default for EnforcementConfig {
+    fn default() -> Self {
+        Self {
+            mode: EnforcementMode::default(),
+            emoji: "♻️".to_owned(),
+            note: false,
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CooldownAction {
+    /// The moderators are alerted
+    #[default]
+    Alert,
+    /// The user is timed out, and the moderators alerted
+    Timeout,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CooldownConfig {
+    /// Act on users who have more than `max_deletions` duplicates deleted within `window`
+    pub enabled: bool,
+    pub max_deletions: usize,
+    #[serde(with = "humantime_serde")]
+    pub window: Duration,
+    pub action: CooldownAction,
+    /// How long users are timed out for with the `timeout` action, at most 28 days
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+}
+impl Default for CooldownConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_deletions: 5,
+            window: Duration::from_secs(10 * 60),
+            action: CooldownAction::default(),
+            timeout: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+/// Cooldowns of the commands that only look things up, so they can't be spammed
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CommandCooldownConfig {
+    /// How long each user waits between uses of each of `commands`, zero for no limit
+    #[serde(with = "humantime_serde")]
+    pub per_user: Duration,
+    /// How long everyone waits between uses of each of `commands` in the same channel
+    #[serde(with = "humantime_serde")]
+    pub per_channel: Duration,
+    /// The commands the cooldowns apply to, by their English names
+    pub commands: Vec<String>,
+}
+impl Default for CommandCooldownConfig {
+    fn default() -> Self {
+        Self {
+            per_user: Duration::from_secs(5),
+            per_channel: Duration::ZERO,
+            commands: ["search", "recent", "checkduplicate", "stats", "streak", "streaks", "activity"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// Most message fetches and deletions sent per second, across all channels. Discord's global
+    /// limit is 50 requests per second for all of the bot's requests.
+    pub requests_per_second: usize,
+}
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self { requests_per_second: 25 }
+    }
+}
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RepeatPolicy {
+    /// Deleted like any other duplicate
+    #[default]
+    Delete,
+    /// Left alone
+    Ignore,
+    /// Left alone, and its author is told they already posted it
+    Warn,
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct RepeatConfig {
+    /// What happens to duplicates whose originals were all posted by the same author
+    pub same_author: RepeatPolicy,
+}
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    /// Messages breaking the rule are deleted
+    #[default]
+    Delete,
+    /// Messages breaking the rule are left in place, and their authors told why they don't count
+    Warn,
+}
+
+/// Rules for the text of entries. Messages breaking them are never cached.
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct LengthConfig {
+    /// Only accept entries without whitespace
+    pub single_word: bool,
+    /// Fewest characters an entry may have
+    pub min_length: usize,
+    /// Most characters an entry may have
+    pub max_length: Option<usize>,
+    pub action: RuleAction,
+}
+
+/// Only accept entries made of real words. Messages breaking the rule are never cached.
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct DictionaryConfig {
+    /// Wordlist with one word per line. Without one, any word is accepted.
+    pub path: Option<path::PathBuf>,
+    pub action: RuleAction,
+    /// The wordlist's words, normalized like entries
+    #[serde(skip)]
+    pub words: HashSet<String>,
+}
+
+/// A theme entries must follow, e.g. a theme of the day. Messages breaking it are never cached.
+///
+/// Admins usually change it with `/theme set`, which keeps it like `/config set`.
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ThemeConfig {
+    /// What entries must match, no theme if unset
+    pub value: Option<String>,
+    pub kind: ThemeKind,
+    pub action: RuleAction,
+    /// `value` compiled, for the `regex` kind
+    #[serde(skip)]
+    pub compiled_pattern: Option<Regex>,
+    /// When the theme is announced, as a cron expression with seconds in UTC, e.g. `0 0 9 * * *`
+    /// for daily at 9:00. Never if unset.
+    pub announce: Option<String>,
+    #[serde(skip)]
+    pub compiled_announce: Option<cron::Schedule>,
+    /// Channel the theme is announced in, the watched channel if omitted
+    pub channel: Option<serenity::ChannelId>,
+}
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeKind {
+    /// Entries must start with the value, e.g. "s"
+    #[default]
+    Prefix,
+    /// Entries must contain the value as a word
+    Word,
+    /// Messages must match the value as a regular expression
+    Regex,
+}
+
+impl ThemeKind {
+    /// The kind as written in the config
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ThemeKind::Prefix => "prefix",
+            ThemeKind::Word => "word",
+            ThemeKind::Regex => "regex",
+        }
+    }
+}
+
+/// Limits every user to one accepted entry a day, for the `once_per_day` rule. Messages breaking
+/// it are never cached.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct OncePerDayConfig {
+    pub window: DayWindow,
+    /// Time zone whose calendar days count in the `calendar` window, e.g. `Europe/Berlin`
+    pub timezone: String,
+    #[serde(skip)]
+    pub compiled_timezone: Option<chrono_tz::Tz>,
+    pub action: RuleAction,
+}
+impl Default for OncePerDayConfig {
+    fn default() -> Self {
+        Self {
+            window: DayWindow::Rolling,
+            timezone: "UTC".to_owned(),
+            compiled_timezone: None,
+            action: RuleAction::Delete,
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DayWindow {
+    /// Another entry is accepted 24 hours after the last one
+    #[default]
+    Rolling,
+    /// Another entry is accepted once the calendar day of the last one is over
+    Calendar,
+}
+
+/// Shorthands for the rule chains of the games other than deduplication
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct GamesConfig {
+    /// Channels, or threads with a cache of their own, that play the counting game instead
+    pub counting: Vec<serenity::ChannelId>,
+    /// Channels, or threads with a cache of their own, that play the word-chain game instead
+    pub word_chain: Vec<serenity::ChannelId>,
+    /// Channels, or threads with a cache of their own, that play the alphabet game instead
+    pub alphabet: Vec<serenity::ChannelId>,
+}
+
+/// The SET card game, played with `/set`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CardsConfig {
+    /// Channels where games can be started
+    pub channels: Vec<serenity::ChannelId>,
+    /// Points a player loses for claiming cards that don't form a SET
+    pub penalty: u32,
+}
+impl Default for CardsConfig {
+    fn default() -> Self {
+        Self {
+            channels: Vec::new(),
+            penalty: 1,
+        }
+    }
+}
+
+/// A rule messages must follow to be accepted
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleKind {
+    /// Entries must not be duplicates
+    Unique,
+    /// Entries must follow `[length]`
+    Length,
+    /// Entries must be made of words of `[dictionary]`
+    Dictionary,
+    /// Entries must follow `[theme]`, if there is a theme
+    Theme,
+    /// Users get one entry accepted a day, see `[once_per_day]`
+    OncePerDay,
+    /// Entries must start with the last letter of the previous one
+    WordChain,
+    /// Entries must start with the letter after the one the previous entry started with
+    Alphabet,
+    /// Messages must be the previous number plus one, by someone else than the last counter
+    Counting,
+    /// Messages must be accepted by the channel's script
+    Script,
+}
+
+/// The rules of a channel, or of a thread with a cache of its own
+#[derive(Deserialize, Debug, Clone)]
+pub struct ChannelRules {
+    pub channel: serenity::ChannelId,
+    pub rules: Vec<RuleKind>,
+    /// Anagram and substring matching in this channel, instead of `[matching]`
+    pub matching: Option<MatchingConfig>,
+    /// Language words are stemmed in for this channel, instead of `normalization.stemming`
+    pub stemming: Option<StemmingLanguage>,
+    /// Rhai script deciding on messages, for the `script` rule
+    pub script: Option<path::PathBuf>,
+    #[cfg(feature = "scripting")]
+    #[serde(skip)]
+    pub compiled_script: Option<rhai::AST>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RulesConfig {
+    /// Rules of the channels without any of their own
+    pub default: Vec<RuleKind>,
+    pub channels: Vec<ChannelRules>,
+}
+impl Default for RulesConfig {
+    fn default() -> Self {
+        Self {
+            default: vec![RuleKind::Length, RuleKind::Dictionary, RuleKind::Theme, RuleKind::Unique],
+            channels: Vec::new(),
+        }
+    }
+}
+
+/// Where the config is read from unless the command line says otherwise
+pub fn get_the_default_config_path() -> path::PathBuf {
+    env::current_dir()
+        .expect("Failed to get current directory")
+        .join("config.toml")
+}
+
+impl Config {
+    /// The settings duplicates are decided by
+    pub fn dedup_rules(&self) -> dedup::Rules<'_> {
+        dedup::Rules {
+            fuzzy: &self.fuzzy,
+            matching: &self.matching,
+            max_image_distance: self.attachments.max_distance,
+            expire_after: self.ttl.expire_after,
+        }
+    }
+    /// The settings duplicates are decided by in the channel whose cache is `scope`
+    pub fn dedup_rules_in(&self, scope: serenity::ChannelId) -> dedup::Rules<'_> {
+        let matching = self.rules.channels
+            .iter()
+            .find(|channel| channel.channel == scope)
+            .and_then(|channel| channel.matching.as_ref());
+        dedup::Rules {
+            matching: matching.unwrap_or(&self.matching),
+            ..self.dedup_rules()
+        }
+    }
+    /// How messages are normalized in the channel whose cache is `scope`
+    pub fn normalization_in(&self, scope: serenity::ChannelId) -> Cow<'_, NormalizationConfig> {
+        let stemming = self.rules.channels
+            .iter()
+            .find(|channel| channel.channel == scope)
+            .and_then(|channel| channel.stemming);
+        match stemming {
+            Some(stemming) => Cow::Owned(NormalizationConfig { stemming: Some(stemming), ..self.normalization.clone() }),
+            None => Cow::Borrowed(&self.normalization),
+        }
+    }
+    /// The rules that messages in the channel whose cache is `scope` must follow
+    pub fn rule_chain(&self, scope: serenity::ChannelId) -> &[RuleKind] {
+        if let Some(channel) = self.rules.channels.iter().find(|channel| channel.channel == scope) {
+            return &channel.rules;
+        }
+        if self.games.counting.contains(&scope) {
+            return &[RuleKind::Counting];
+        }
+        if self.games.word_chain.contains(&scope) {
+            return &[RuleKind::Length, RuleKind::Dictionary, RuleKind::Theme, RuleKind::WordChain, RuleKind::Unique];
+        }
+        if self.games.alphabet.contains(&scope) {
+            return &[RuleKind::Length, RuleKind::Dictionary, RuleKind::Theme, RuleKind::Alphabet, RuleKind::Unique];
+        }
+        &self.rules.default
+    }
+    /// Reads the config, panicking if it is invalid
+    pub fn load(config_path: &path::Path) -> Self {
+        Self::try_load(config_path).unwrap_or_else(|e| panic!("{}", e))
+    }
+    /// Reads the config, falling back to the defaults if there is no config file
+    pub fn try_load(config_path: &path::Path) -> Result<Self, String> {
+        Self::try_load_with(config_path, &BTreeMap::new())
+    }
+    /// Reads the config with `overrides` applied over the config file, as set by `/config set`
+    ///
+    /// Overrides are TOML values by their dotted key, e.g. `"react"` for `enforcement.mode`.
+    pub fn try_load_with(config_path: &path::Path, overrides: &BTreeMap<String, String>) -> Result<Self, String> {
+        let mut source: toml::Table = match fs::read_to_string(config_path) {
+            Ok(contents) => contents.parse()
+                .map_err(|e| format!("Failed to parse config file {}: {}", config_path.display(), e))?,
+            Err(_) => {
+                log::info!("No config file found at {}, using defaults", config_path.display());
+                toml::Table::new()
+            }
+        };
+        for (key, value) in overrides {
+            set_key(&mut source, key, value)?;
+        }
+        let mut config: Self = toml::Value::Table(source.clone()).try_into()
+            .map_err(|e| format!("Failed to parse config file {}: {}", config_path.display(), e))?;
+        config.path = config_path.to_owned();
+        config.source = source;
+        if config.backup.enabled && config.backup.bucket.is_empty() {
+            return Err("Backups are enabled, but `backup.bucket` isn't set".to_owned());
+        }
+        if config.api.enabled && config.api.token.is_empty() {
+            return Err("The API is enabled, but `api.token` isn't set".to_owned());
+        }
+        if config.appeals.enabled && config.appeals.moderator_channel.is_none() {
+            return Err("Appeals are enabled, but `appeals.moderator_channel` isn't set".to_owned());
+        }
+        if config.length.max_length.is_some_and(|max_length| max_length < config.length.min_length) {
+            return Err("`length.max_length` is smaller than `length.min_length`".to_owned());
+        }
+        if config.rate_limit.requests_per_second == 0 {
+            return Err("`rate_limit.requests_per_second` must be at least 1".to_owned());
+        }
+        if config.cooldown.timeout > Duration::from_secs(28 * 24 * 3600) {
+            return Err("`cooldown.timeout` can't be longer than Discord's limit of 28 days".to_owned());
+        }
+        if config.webhook.url.as_ref().is_some_and(|url| !url.starts_with("https://") && !url.starts_with("http://")) {
+            return Err("`webhook.url` must be an HTTP or HTTPS URL".to_owned());
+        }
+        if config.heartbeat.enabled {
+            if config.heartbeat.channel.is_none() && config.heartbeat.url.is_none() {
+                return Err("The heartbeat is enabled, but has neither a `channel` nor a `url`".to_owned());
+            }
+            if config.heartbeat.url.as_ref().is_some_and(|url| !url.starts_with("https://") && !url.starts_with("http://")) {
+                return Err("`heartbeat.url` must be an HTTP or HTTPS URL".to_owned());
+            }
+            if config.heartbeat.interval < Duration::from_secs(60) {
+                return Err("`heartbeat.interval` must be at least a minute".to_owned());
+            }
+        }
+        if !i18n::is_supported(&config.i18n.locale) {
+            return Err(format!("There are no translations for the locale `{}`", config.i18n.locale));
+        }
+        serenity::ReactionType::try_from(config.enforcement.emoji.as_str())
+            .map_err(|_| format!("Invalid enforcement emoji {:?}", config.enforcement.emoji))?;
+        if config.summary.enabled {
+            let schedule = config.summary.schedule.parse()
+                .map_err(|e| format!("Invalid summary schedule {:?}: {}", config.summary.schedule, e))?;
+            config.summary.compiled_schedule = Some(schedule);
+        }
+        // `/theme clear` empties the value, so it also overrides a theme in the config file
+        if config.theme.value.as_deref() == Some("") {
+            config.theme.value = None;
+        }
+        if let (ThemeKind::Regex, Some(value)) = (config.theme.kind, &config.theme.value) {
+            let pattern = Regex::new(value).map_err(|e| format!("Invalid theme pattern {:?}: {}", value, e))?;
+            config.theme.compiled_pattern = Some(pattern);
+        }
+        if let Some(announce) = &config.theme.announce {
+            let schedule = announce.parse().map_err(|e| format!("Invalid theme announcement schedule {:?}: {}", announce, e))?;
+            config.theme.compiled_announce = Some(schedule);
+        }
+        let timezone = config.once_per_day.timezone.parse()
+            .map_err(|e| format!("Invalid `once_per_day.timezone` {:?}: {}", config.once_per_day.timezone, e))?;
+        config.once_per_day.compiled_timezone = Some(timezone);
+        if let Some(path) = &config.dictionary.path {
+            let contents = fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read the wordlist {}: {}", path.display(), e))?;
+            config.dictionary.words = dictionary::load_words(&contents, &config.normalization).collect();
+            log::info!("Loaded {} words from {}", config.dictionary.words.len(), path.display());
+        }
+        if config.rules.default.contains(&RuleKind::Script) {
+            return Err("Only `rules.channels` can use the `script` rule".to_owned());
+        }
+        for channel in &mut config.rules.channels {
+            if !channel.rules.contains(&RuleKind::Script) {
+                continue;
+            }
+            let Some(path) = &channel.script else {
+                return Err(format!("The rules of channel {} use `script`, but have no `script` file", channel.channel));
+            };
+            let source = fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read the script {}: {}", path.display(), e))?;
+            #[cfg(feature = "scripting")]
+            {
+                channel.compiled_script = Some(
+                    script::compile(&source).map_err(|e| format!("Invalid script {}: {}", path.display(), e))?,
+                );
+            }
+            #[cfg(not(feature = "scripting"))]
+            {
+                let _ = source;
+                return Err("The `script` rule is used, but the bot was built without the `scripting` feature".to_owned());
+            }
+        }
+        config.ignore.compiled_patterns = config
+            .ignore
+            .patterns
+            .iter()
+            .map(|pattern| Regex::new(pattern).map_err(|e| format!("Invalid ignore pattern {:?}: {}", pattern, e)))
+            .collect::<Result<_, _>>()?;
+        Ok(config)
+    }
+}
+
+/// The value at a dotted `key` of `table`, if it is set
+pub fn get_key<'a>(table: &'a toml::Table, key: &str) -> Option<&'a toml::Value> {
+    let (sections, name) = match key.rsplit_once('.') {
+        Some((sections, name)) => (Some(sections), name),
+        None => (None, key),
+    };
+    let mut table = table;
+    for section in sections.into_iter().flat_map(|sections| sections.split('.')) {
+        table = table.get(section)?.as_table()?;
+    }
+    table.get(name)
+}
+
+/// Sets a dotted `key` of `table` to `value`, written in TOML, creating the sections on the way
+fn set_key(table: &mut toml::Table, key: &str, value: &str) -> Result<(), String> {
+    // A lone value isn't a TOML document, an assignment is
+    let mut parsed: toml::Table = format!("value = {}", value)
+        .parse()
+        .map_err(|e| format!("Invalid value {} for `{}`: {}", value, key, e))?;
+    let value = parsed.remove("value").unwrap_or_else(|| unreachable!("The value was just assigned"));
+    let mut parts: Vec<_> = key.split('.').collect();
+    let name = parts.pop().unwrap_or_default();
+    let mut table = table;
+    for section in parts {
+        table = table
+            .entry(section)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .ok_or_else(|| format!("`{}` isn't a section, so `{}` can't be set", section, key))?;
+    }
+    table.insert(name.to_owned(), value);
+    Ok(())
+}
+
+/// Every setting of `table` by its dotted key, with lists and other values that aren't sections
+/// kept whole
+pub fn flatten(table: &toml::Table) -> BTreeMap<String, &toml::Value> {
+    let mut settings = BTreeMap::new();
+    for (key, value) in table {
+        match value.as_table() {
+            Some(section) => settings.extend(
+                flatten(section).into_iter().map(|(name, value)| (format!("{}.{}", key, name), value)),
+            ),
+            None => {
+                settings.insert(key.clone(), value);
+            }
+        }
+    }
+    settings
+}
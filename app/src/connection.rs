@@ -0,0 +1,54 @@
+use poise::serenity_prelude as serenity;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// How often the bot's shards lost their gateway connection, and since when it is lost
+#[derive(Default)]
+pub struct Connection {
+    /// Shards that started a session before, so their next Ready is a reconnect
+    started: Mutex<HashSet<serenity::ShardId>>,
+    /// New sessions after the first, where the events in between were missed
+    reconnects: AtomicU32,
+    /// Resumed sessions, where Discord replayed the events in between
+    resumes: AtomicU32,
+    /// When shards lost the connection, while it is lost
+    lost_at: Mutex<HashMap<serenity::ShardId, Instant>>,
+}
+
+impl Connection {
+    /// Records a new session of `shard_id`, returning whether it replaced an earlier one
+    pub fn on_ready(&self, shard_id: serenity::ShardId) -> bool {
+        let reconnected = !self.started.lock().expect("The connection lock was poisoned").insert(shard_id);
+        if reconnected {
+            self.reconnects.fetch_add(1, Ordering::SeqCst);
+        }
+        reconnected
+    }
+    pub fn on_resume(&self) {
+        self.resumes.fetch_add(1, Ordering::SeqCst);
+    }
+    /// Records a shard changing its stage, returning how long it lost the connection for once it is back
+    pub fn on_stage_change(&self, shard_id: serenity::ShardId, old: serenity::ConnectionStage, new: serenity::ConnectionStage) -> Option<Duration> {
+        let mut lost_at = self.lost_at.lock().expect("The connection lock was poisoned");
+        match (old, new) {
+            (serenity::ConnectionStage::Connected, _) => {
+                lost_at.entry(shard_id).or_insert_with(Instant::now);
+                None
+            }
+            (_, serenity::ConnectionStage::Connected) => lost_at.remove(&shard_id).map(|lost_at| lost_at.elapsed()),
+            _ => None,
+        }
+    }
+    pub fn reconnects(&self) -> u32 {
+        self.reconnects.load(Ordering::SeqCst)
+    }
+    pub fn resumes(&self) -> u32 {
+        self.resumes.load(Ordering::SeqCst)
+    }
+}
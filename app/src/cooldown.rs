@@ -0,0 +1,77 @@
+use crate::{alert_owner, config::CooldownAction, i18n::tr, Data, Error};
+use poise::serenity_prelude::{self as serenity, Mentionable};
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Instant,
+};
+use tokio::sync::Mutex;
+
+/// When each user's duplicates were deleted, within the configured window
+#[derive(Default)]
+pub struct DeletionTracker {
+    deletions: Mutex<HashMap<serenity::UserId, VecDeque<Instant>>>,
+}
+
+impl DeletionTracker {
+    /// Records a deletion of one of `user_id`'s messages, returning whether it took them over the
+    /// limit. Their deletions are forgotten once they are, so they aren't punished twice for them.
+    async fn record(&self, user_id: serenity::UserId, data: &Data) -> bool {
+        let config = data.config();
+        let config = &config.cooldown;
+        let now = Instant::now();
+        let mut deletions = self.deletions.lock().await;
+        // Users with nothing in the window don't need an entry
+        deletions.retain(|_, times| {
+            while times.front().is_some_and(|time| now.duration_since(*time) > config.window) {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+        let times = deletions.entry(user_id).or_default();
+        times.push_back(now);
+        if times.len() > config.max_deletions {
+            deletions.remove(&user_id);
+            return true;
+        }
+        false
+    }
+}
+
+/// Counts a deleted duplicate against its author, timing them out or alerting the moderators when
+/// they have had too many deleted lately
+pub async fn on_deletion(
+    ctx: &serenity::Context,
+    data: &Data,
+    guild_id: Option<serenity::GuildId>,
+    user_id: serenity::UserId,
+) -> Result<(), Error> {
+    let config = data.config();
+    let config = &config.cooldown;
+    if !config.enabled || !data.deletion_tracker.record(user_id, data).await {
+        return Ok(());
+    }
+    let window = humantime::format_duration(config.window);
+    log::info!("{} had more than {} duplicates deleted within {}", user_id, config.max_deletions, window);
+    let locale = data.config().i18n.locale.clone();
+    let mut alert = tr(&locale, "alerts.too_many_deletions", &[
+        ("user", &user_id.mention()),
+        ("max", &config.max_deletions),
+        ("window", &window),
+    ]);
+    if let (CooldownAction::Timeout, Some(guild_id)) = (config.action, guild_id) {
+        let until = chrono::Duration::from_std(config.timeout)
+            .map(|timeout| chrono::Utc::now() + timeout)
+            .map_err(|e| format!("Invalid timeout: {}", e))?;
+        let timeout = serenity::EditMember::new()
+            .disable_communication_until_datetime(until.into())
+            .audit_log_reason("Posted too many duplicates");
+        match guild_id.edit_member(ctx, user_id, timeout).await {
+            Ok(_) => alert += &tr(&locale, "alerts.timed_out", &[("timeout", &humantime::format_duration(config.timeout))]),
+            Err(error) => {
+                log::warn!("Failed to time out {}: {:?}", user_id, error);
+                alert += &tr(&locale, "alerts.timeout_failed", &[]);
+            }
+        }
+    }
+    alert_owner(ctx, data, &alert).await
+}
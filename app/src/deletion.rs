@@ -0,0 +1,315 @@
+use crate::{cache::MessagesCache, commit_to_disk, gateway::Gateway, throttle::Throttle};
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude as serenity;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{atomic, Arc},
+    time::Duration,
+};
+use tokio::sync::Mutex;
+
+/// How often a transiently failing deletion is attempted in total
+const MAX_ATTEMPTS: u32 = 5;
+/// Delay before the first retry, doubled after every further failure
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+/// Discord only bulk-deletes messages younger than two weeks. An hour is left as a margin so
+/// messages don't age out while a batch is being sent.
+const BULK_DELETE_MAX_AGE: i64 = 14 * 24 * 3600 - 3600;
+/// Most messages a single bulk delete accepts
+const BULK_DELETE_MAX_COUNT: usize = 100;
+
+/// Something the bot deletes for being a duplicate
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum Target {
+    Message {
+        channel_id: serenity::ChannelId,
+        message_id: serenity::MessageId,
+    },
+    /// A forum post, deleted together with its thread
+    Post(serenity::ChannelId),
+}
+
+impl Target {
+    async fn delete(self, gateway: &dyn Gateway, throttle: &Throttle) -> serenity::Result<()> {
+        throttle.acquire().await;
+        match self {
+            Target::Message { channel_id, message_id } => gateway.delete_message(channel_id, message_id).await,
+            Target::Post(thread_id) => gateway.delete_channel(thread_id).await,
+        }
+    }
+}
+
+/// A deletion that failed for good, kept so it can be looked into
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeadLetter {
+    pub target: Target,
+    /// Channel whose cache the target was checked against
+    pub scope: serenity::ChannelId,
+    pub error: String,
+    pub failed_at: DateTime<Utc>,
+}
+
+/// Result of asking for a deletion
+pub enum Outcome {
+    Deleted,
+    /// Already deleted by someone else
+    Gone,
+    /// Failed for now, it is retried in the background
+    Retrying,
+    Failed(DeadLetter),
+}
+
+enum Failure {
+    Transient,
+    Gone,
+    Permanent,
+}
+
+/// Rate limits, server errors and network problems may go away, anything else won't
+fn classify(error: &serenity::Error) -> Failure {
+    let serenity::Error::Http(error) = error else {
+        return Failure::Permanent;
+    };
+    match error.status_code() {
+        Some(status) if status.as_u16() == 404 => Failure::Gone,
+        Some(status) if status.as_u16() == 429 || status.is_server_error() => Failure::Transient,
+        Some(_) => Failure::Permanent,
+        None if matches!(error, serenity::HttpError::Request(_)) => Failure::Transient,
+        None => Failure::Permanent,
+    }
+}
+
+/// Deletes duplicates, retrying transient failures with exponential backoff
+pub struct DeletionQueue {
+    gateway: Arc<dyn Gateway>,
+    messages_cache: Arc<Mutex<MessagesCache>>,
+    /// Deletions currently waiting for a retry
+    pending: Arc<atomic::AtomicUsize>,
+    throttle: Arc<Throttle>,
+}
+
+impl DeletionQueue {
+    pub fn new(gateway: Arc<dyn Gateway>, messages_cache: Arc<Mutex<MessagesCache>>, throttle: Arc<Throttle>) -> Self {
+        Self {
+            gateway,
+            messages_cache,
+            pending: Arc::new(atomic::AtomicUsize::new(0)),
+            throttle,
+        }
+    }
+    /// Number of deletions waiting for a retry
+    pub fn pending(&self) -> usize {
+        self.pending.load(atomic::Ordering::SeqCst)
+    }
+    /// Tries to delete `target` once, queueing a retry if that failed transiently
+    ///
+    /// The caller records the immediate outcome, since it may already hold the cache. Retries
+    /// record theirs themselves.
+    #[cfg_attr(feature = "otel", tracing::instrument(skip(self)))]
+    pub async fn delete(&self, target: Target, scope: serenity::ChannelId) -> Outcome {
+        let error = match target.delete(&*self.gateway, &self.throttle).await {
+            Ok(()) => return Outcome::Deleted,
+            Err(error) => error,
+        };
+        match classify(&error) {
+            Failure::Gone => Outcome::Gone,
+            Failure::Permanent => {
+                log::warn!("Failed to delete {}: {:?}", target, error);
+                Outcome::Failed(dead_letter(target, scope, &error))
+            }
+            Failure::Transient => {
+                log::warn!("Failed to delete {}, retrying: {:?}", target, error);
+                self.pending.fetch_add(1, atomic::Ordering::SeqCst);
+                tokio::spawn(retry(self.gateway.clone(), self.messages_cache.clone(), self.pending.clone(), self.throttle.clone(), target, scope));
+                Outcome::Retrying
+            }
+        }
+    }
+    /// Deletes many messages of a channel, in as few requests as possible, returning the outcome
+    /// of each in the order of `message_ids`
+    ///
+    /// Messages young enough are bulk-deleted, the rest are deleted one by one `interval` apart,
+    /// so a long catch-up doesn't run into rate limits.
+    #[cfg_attr(feature = "otel", tracing::instrument(skip(self, message_ids), fields(count = message_ids.len())))]
+    pub async fn delete_many(
+        &self,
+        channel_id: serenity::ChannelId,
+        message_ids: &[serenity::MessageId],
+        scope: serenity::ChannelId,
+        interval: Duration,
+    ) -> Vec<Outcome> {
+        let cutoff = Utc::now().timestamp() - BULK_DELETE_MAX_AGE;
+        // Positions in `message_ids`, so outcomes end up where their messages are
+        let (recent, old): (Vec<usize>, Vec<usize>) = (0..message_ids.len())
+            .partition(|&position| message_ids[position].created_at().unix_timestamp() > cutoff);
+        let mut outcomes: Vec<Option<Outcome>> = message_ids.iter().map(|_| None).collect();
+        let mut one_by_one = old;
+        for batch in recent.chunks(BULK_DELETE_MAX_COUNT) {
+            // Bulk deletes need at least two messages
+            if batch.len() < 2 {
+                one_by_one.extend_from_slice(batch);
+                continue;
+            }
+            let batch_ids: Vec<_> = batch.iter().map(|&position| message_ids[position]).collect();
+            self.throttle.acquire().await;
+            match self.gateway.delete_messages(channel_id, &batch_ids).await {
+                Ok(()) => {
+                    for &position in batch {
+                        outcomes[position] = Some(Outcome::Deleted);
+                    }
+                }
+                Err(error) => {
+                    log::warn!("Failed to bulk delete {} messages, deleting them one by one: {:?}", batch.len(), error);
+                    one_by_one.extend_from_slice(batch);
+                }
+            }
+            tokio::time::sleep(interval).await;
+        }
+        for position in one_by_one {
+            let target = Target::Message { channel_id, message_id: message_ids[position] };
+            outcomes[position] = Some(self.delete(target, scope).await);
+            tokio::time::sleep(interval).await;
+        }
+        outcomes
+            .into_iter()
+            .map(|outcome| outcome.unwrap_or_else(|| unreachable!("Every message is deleted in bulk or alone")))
+            .collect()
+    }
+}
+
+fn dead_letter(target: Target, scope: serenity::ChannelId, error: &serenity::Error) -> DeadLetter {
+    DeadLetter {
+        target,
+        scope,
+        error: error.to_string(),
+        failed_at: Utc::now(),
+    }
+}
+
+async fn retry(
+    gateway: Arc<dyn Gateway>,
+    messages_cache: Arc<Mutex<MessagesCache>>,
+    pending: Arc<atomic::AtomicUsize>,
+    throttle: Arc<Throttle>,
+    target: Target,
+    scope: serenity::ChannelId,
+) {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    let outcome = loop {
+        tokio::time::sleep(backoff).await;
+        attempt += 1;
+        let error = match target.delete(&*gateway, &throttle).await {
+            Ok(()) => break Outcome::Deleted,
+            Err(error) => error,
+        };
+        match classify(&error) {
+            Failure::Gone => break Outcome::Gone,
+            Failure::Transient if attempt < MAX_ATTEMPTS => backoff *= 2,
+            _ => {
+                log::error!("Giving up on deleting {} after {} attempts: {:?}", target, attempt, error);
+                break Outcome::Failed(dead_letter(target, scope, &error));
+            }
+        }
+    };
+    pending.fetch_sub(1, atomic::Ordering::SeqCst);
+    let messages_cache = &mut *messages_cache.lock().await;
+    messages_cache.record_deletion(scope, outcome);
+    if let Err(error) = commit_to_disk(messages_cache).await {
+        log::error!("Failed to commit messages to disk: {:?}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gateway::fake::{message_id, Recording, Request};
+
+    fn channel() -> serenity::ChannelId {
+        serenity::ChannelId::new(10)
+    }
+
+    fn queue(gateway: Arc<Recording>) -> DeletionQueue {
+        DeletionQueue::new(gateway, Arc::new(Mutex::new(MessagesCache::default())), Arc::new(Throttle::new(25)))
+    }
+
+    /// IDs of `count` messages sent a second apart, starting `age` ago
+    fn message_ids(age: chrono::Duration, count: i64) -> Vec<serenity::MessageId> {
+        let start = Utc::now() - age;
+        (0..count).map(|i| message_id(start + chrono::Duration::seconds(i))).collect()
+    }
+
+    #[tokio::test]
+    async fn deletes_messages_and_posts() {
+        let gateway = Arc::new(Recording::default());
+        let deletions = queue(gateway.clone());
+        let message_id = message_ids(chrono::Duration::minutes(1), 1)[0];
+        let post = serenity::ChannelId::new(20);
+        let deleted = deletions.delete(Target::Message { channel_id: channel(), message_id }, channel()).await;
+        assert!(matches!(deleted, Outcome::Deleted));
+        let deleted = deletions.delete(Target::Post(post), channel()).await;
+        assert!(matches!(deleted, Outcome::Deleted));
+        assert_eq!(gateway.requests(), vec![Request::DeleteMessage(message_id), Request::DeleteChannel(post)]);
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_permanent_failures() {
+        let message_id = message_ids(chrono::Duration::minutes(1), 1)[0];
+        let gateway = Arc::new(Recording::default().undeletable([message_id]));
+        let deletions = queue(gateway.clone());
+        let target = Target::Message { channel_id: channel(), message_id };
+        let Outcome::Failed(dead_letter) = deletions.delete(target, channel()).await else {
+            panic!("A deletion without permission can't succeed");
+        };
+        assert!(matches!(dead_letter.target, Target::Message { message_id: failed, .. } if failed == message_id));
+        assert_eq!(dead_letter.scope, channel());
+        assert_eq!(dead_letter.error, "Missing Permissions");
+        // Only transient failures are retried
+        assert_eq!(deletions.pending(), 0);
+        assert!(gateway.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn bulk_deletes_recent_messages_only() {
+        let gateway = Arc::new(Recording::default());
+        let deletions = queue(gateway.clone());
+        let old = message_ids(chrono::Duration::days(20), 1);
+        let recent = message_ids(chrono::Duration::minutes(1), 3);
+        let message_ids = [old.clone(), recent.clone()].concat();
+        let outcomes = deletions.delete_many(channel(), &message_ids, channel(), Duration::ZERO).await;
+        assert_eq!(outcomes.len(), 4);
+        assert!(outcomes.iter().all(|outcome| matches!(outcome, Outcome::Deleted)));
+        assert_eq!(gateway.requests(), vec![Request::DeleteMessages(recent), Request::DeleteMessage(old[0])]);
+    }
+
+    #[tokio::test]
+    async fn returns_outcomes_in_the_order_of_the_messages() {
+        let recent = message_ids(chrono::Duration::minutes(1), 3);
+        let old = message_ids(chrono::Duration::days(20), 1);
+        // The old message comes between recent ones, and the bulk delete fails for the last one
+        let message_ids = [recent[0], old[0], recent[1], recent[2]];
+        let gateway = Arc::new(Recording::default().undeletable([recent[2]]));
+        let deletions = queue(gateway.clone());
+        let outcomes = deletions.delete_many(channel(), &message_ids, channel(), Duration::ZERO).await;
+        assert_eq!(outcomes.len(), 4);
+        assert!(matches!(outcomes[0], Outcome::Deleted));
+        assert!(matches!(outcomes[1], Outcome::Deleted));
+        assert!(matches!(outcomes[2], Outcome::Deleted));
+        let Outcome::Failed(dead_letter) = &outcomes[3] else {
+            panic!("The undeletable message can't be deleted");
+        };
+        assert!(matches!(dead_letter.target, Target::Message { message_id, .. } if message_id == recent[2]));
+        assert_eq!(gateway.deleted(), vec![old[0], recent[0], recent[1]]);
+    }
+
+    #[tokio::test]
+    async fn deletes_one_by_one_when_bulk_deletes_fail() {
+        let message_ids = message_ids(chrono::Duration::minutes(1), 3);
+        let gateway = Arc::new(Recording::default().undeletable([message_ids[1]]));
+        let deletions = queue(gateway.clone());
+        let outcomes = deletions.delete_many(channel(), &message_ids, channel(), Duration::ZERO).await;
+        assert!(matches!(outcomes[..], [Outcome::Deleted, Outcome::Failed(_), Outcome::Deleted]));
+        assert_eq!(gateway.deleted(), vec![message_ids[0], message_ids[2]]);
+        assert!(gateway.requests().iter().all(|request| matches!(request, Request::DeleteMessage(_))));
+    }
+}
@@ -0,0 +1,25 @@
+use crate::{config::DictionaryConfig, i18n::tr};
+use set_core::{config::NormalizationConfig, normalize::normalize_string};
+
+/// Why a message's content breaks the dictionary rule, i.e. the first of its words missing from
+/// the wordlist
+///
+/// Punctuation around words is ignored. Without a wordlist, every word is accepted.
+pub fn violation(locale: &str, content: &str, config: &DictionaryConfig, normalization: &NormalizationConfig) -> Option<String> {
+    config.path.as_ref()?;
+    let content = normalize_string(content, normalization);
+    let unknown = content
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|word| !word.is_empty())
+        .find(|word| !config.words.contains(*word))?;
+    Some(tr(locale, "rules.not_in_dictionary", &[("word", &unknown)]))
+}
+
+/// Reads a wordlist with one word per line, normalized like entries so they can be looked up
+pub fn load_words(contents: &str, normalization: &NormalizationConfig) -> impl Iterator<Item = String> {
+    contents
+        .lines()
+        .map(|line| normalize_string(line, normalization))
+        .filter(|word| !word.is_empty())
+}
@@ -0,0 +1,1017 @@
+use crate::{
+    alert_owner, announce_milestone, appeals, commit_to_disk, config::{EnforcementMode, LinesAction, RepeatPolicy, RuleKind, ThreadMode}, cooldown, deletion, get_the_channel_id, get_the_store, ignore,
+    i18n::{self, tr}, keys, permissions, rules, supervisor, update_presence, feed, Data, Error,
+};
+#[cfg(feature = "systemd")]
+use crate::systemd;
+use poise::serenity_prelude::{self as serenity, Mentionable};
+use set_core::{cache::{Entry, MessageRef}, dedup, normalize::normalize_string};
+use chrono::{DateTime, Utc};
+use std::{
+    collections::HashMap,
+    sync::{atomic, Arc},
+    time::{Duration, Instant},
+};
+
+#[cfg_attr(feature = "otel", tracing::instrument(skip_all, fields(event = event.snake_case_name())))]
+pub async fn event_handler(
+    ctx: &serenity::Context,
+    event: &serenity::FullEvent,
+    framework: poise::FrameworkContext<'_, Data, Error>,
+    data: &Data,
+) -> Result<(), Error> {
+    match event {
+        serenity::FullEvent::Ready { data_about_bot: _ } => on_ready(ctx, framework.shard_manager(), data).await,
+        serenity::FullEvent::Resume { event: _ } => {
+            data.connection.on_resume();
+            log::info!("Shard {} resumed its gateway session", ctx.shard_id);
+            if !hosts_the_watched_channel(ctx, data).await? {
+                return Ok(());
+            }
+            // Discord replays the events missed while disconnected, but catching up costs little and
+            // also covers those it didn't
+            supervisor::restarting("catch-up", &reporter(ctx, data), || catch_up_since_disconnect(ctx, data)).await
+        }
+        serenity::FullEvent::ShardStageUpdate { event: update } => {
+            log::info!("Shard {} went from {} to {}", update.shard_id, update.old, update.new);
+            if let Some(lost_for) = data.connection.on_stage_change(update.shard_id, update.old, update.new) {
+                log::warn!("Shard {} was disconnected for {}", update.shard_id, humantime::format_duration(Duration::from_secs(lost_for.as_secs())));
+            }
+            Ok(())
+        }
+        serenity::FullEvent::Message { new_message } => on_message(ctx, new_message, data).await,
+        serenity::FullEvent::MessageUpdate { event: update, .. } => on_message_update(ctx, update, data).await,
+        serenity::FullEvent::ThreadCreate { thread } => on_thread_create(ctx, thread, data).await,
+        serenity::FullEvent::Ratelimit { data: info } => {
+            log::warn!("Hit a rate limit on {:?} {}, waiting {:?}", info.method, info.path, info.timeout);
+            data.throttle.on_rate_limit(info.timeout).await;
+            Ok(())
+        }
+        serenity::FullEvent::InteractionCreate { interaction: serenity::Interaction::Component(interaction) } => {
+            // Buttons of commands are handled by their collectors, only the appeal buttons outlive them
+            appeals::on_component(ctx, data, interaction).await?;
+            Ok(())
+        }
+        _ => {
+            log::debug!("Got an event: {:?}", event.snake_case_name());
+            Ok(())
+        }
+    }
+}
+
+/// Finds the channel whose cache a message in `channel_id` belongs to, if it is watched at all
+async fn resolve_scope(ctx: &serenity::Context, data: &Data, channel_id: serenity::ChannelId) -> Option<serenity::ChannelId> {
+    let watched = serenity::ChannelId::new(get_the_channel_id());
+    if channel_id == watched {
+        return Some(watched);
+    }
+    if thread_mode(data) == ThreadMode::Ignore {
+        return None;
+    }
+    if thread_parent(ctx, data, channel_id).await? != watched {
+        return None;
+    }
+    thread_scope(data, watched, channel_id)
+}
+
+/// How threads are handled, where forum posts can't be ignored since they are the only content
+fn thread_mode(data: &Data) -> ThreadMode {
+    match data.config().threads.mode {
+        ThreadMode::Ignore if data.watching_forum.load(atomic::Ordering::SeqCst) => ThreadMode::Shared,
+        mode => mode,
+    }
+}
+
+/// The channel whose cache a thread of the watched channel uses
+fn thread_scope(data: &Data, watched: serenity::ChannelId, thread_id: serenity::ChannelId) -> Option<serenity::ChannelId> {
+    match thread_mode(data) {
+        ThreadMode::Shared => Some(watched),
+        ThreadMode::Separate => Some(thread_id),
+        ThreadMode::Ignore => None,
+    }
+}
+
+/// The message ID before a thread's first message
+///
+/// A thread's ID is the ID of the message it was started from (or, in forums, of its first
+/// message), so this catches up on the whole thread.
+fn thread_start(thread_id: serenity::ChannelId) -> serenity::MessageId {
+    serenity::MessageId::new(thread_id.get() - 1)
+}
+
+/// The channel a thread was created in, or `None` if `channel_id` isn't a thread
+async fn thread_parent(ctx: &serenity::Context, data: &Data, channel_id: serenity::ChannelId) -> Option<serenity::ChannelId> {
+    if let Some(parent) = data.thread_parents.lock().await.get(&channel_id) {
+        return *parent;
+    }
+    let parent = match channel_id.to_channel(ctx).await {
+        Ok(serenity::Channel::Guild(channel)) if channel.thread_metadata.is_some() => channel.parent_id,
+        Ok(_) => None,
+        Err(error) => {
+            // Not remembered, so the lookup is retried with the next message
+            log::warn!("Failed to look up channel {}: {:?}", channel_id, error);
+            return None;
+        }
+    };
+    data.thread_parents.lock().await.insert(channel_id, parent);
+    parent
+}
+
+async fn on_ready(ctx: &serenity::Context, shard_manager: &Arc<serenity::ShardManager>, data: &Data) -> Result<(), Error> {
+    // Ready fires for every shard and again after reconnecting, but one presence task is enough
+    if data.config().presence.enabled && !data.presence_task_started.swap(true, atomic::Ordering::SeqCst) {
+        let (shard_manager, messages_cache, presence) = (shard_manager.clone(), data.messages_cache.clone(), data.config().presence.clone());
+        supervisor::spawn("presence", reporter(ctx, data), move || update_presence(shard_manager.clone(), messages_cache.clone(), presence.clone()));
+    }
+    let reconnected = data.connection.on_ready(ctx.shard_id);
+    let watched_shard = watched_shard(ctx, data).await?;
+    if watched_shard != ctx.shard_id.0 {
+        log::info!("Shard {} is ready, the watched channel is on another shard", ctx.shard_id);
+        // Processes without the watched channel's shard have nothing to catch up on
+        #[cfg(feature = "systemd")]
+        if !crate::get_the_sharding().runs(watched_shard) {
+            systemd::ready(shard_manager.clone());
+        }
+        return Ok(());
+    }
+    let caught_up = if reconnected {
+        log::warn!("Shard {} started a new gateway session, catching up on the messages missed in between", ctx.shard_id);
+        supervisor::restarting("catch-up", &reporter(ctx, data), || catch_up_since_disconnect(ctx, data)).await
+    } else {
+        supervisor::restarting("catch-up", &reporter(ctx, data), || catch_up_on_everything(ctx, data)).await
+    };
+    #[cfg(feature = "systemd")]
+    if caught_up.is_ok() {
+        systemd::ready(shard_manager.clone());
+    }
+    caught_up
+}
+
+/// Where panics of tasks started by events are reported
+fn reporter(ctx: &serenity::Context, data: &Data) -> supervisor::Reporter {
+    supervisor::Reporter::new(ctx.http.clone(), data.config.clone())
+}
+
+/// Whether the guild of the watched channel is on the shard of `ctx`
+///
+/// Only that shard gets the channel's events, so only it catches up. Other processes running
+/// other shards would catch up with an outdated copy of the cache otherwise.
+async fn hosts_the_watched_channel(ctx: &serenity::Context, data: &Data) -> Result<bool, Error> {
+    Ok(watched_shard(ctx, data).await? == ctx.shard_id.0)
+}
+
+/// The shard the guild of the watched channel is on
+async fn watched_shard(ctx: &serenity::Context, data: &Data) -> Result<u32, Error> {
+    let guild_id = data
+        .watched_guild
+        .get_or_try_init(|| async {
+            match serenity::ChannelId::new(get_the_channel_id()).to_channel(ctx).await? {
+                serenity::Channel::Guild(channel) => Ok::<_, Error>(channel.guild_id),
+                _ => Err("Channel is of the wrong type".into()),
+            }
+        })
+        .await?;
+    Ok(serenity::utils::shard_id(*guild_id, ctx.cache.shard_count()))
+}
+
+/// Catches up on the watched channel and the threads seen since the start, from the last handled
+/// message of each
+///
+/// Unlike `catch_up_on_everything`, this doesn't look for threads, so it is cheap enough for every
+/// reconnect. Messages in threads created while disconnected are only caught up on by `/restartcatchup`.
+async fn catch_up_since_disconnect(ctx: &serenity::Context, data: &Data) -> Result<(), Error> {
+    let watched = serenity::ChannelId::new(get_the_channel_id());
+    let _catching_up = data.catching_up.lock().await;
+    // Forums have no messages of their own
+    if !data.watching_forum.load(atomic::Ordering::SeqCst) {
+        catch_up(data, watched, watched, None).await?;
+    }
+    let threads: Vec<_> = data
+        .thread_parents
+        .lock()
+        .await
+        .iter()
+        .filter(|(_, parent)| **parent == Some(watched))
+        .map(|(thread_id, _)| *thread_id)
+        .collect();
+    for thread_id in threads {
+        let Some(scope) = thread_scope(data, watched, thread_id) else {
+            continue;
+        };
+        catch_up(data, thread_id, scope, Some(thread_start(thread_id))).await?;
+    }
+    commit_to_disk(&*data.messages_cache.lock().await).await?;
+    Ok(())
+}
+
+/// Catches up on the watched channel and its active threads, then saves the cache
+///
+/// A catch-up that is already running is waited for first, and this one resumes where it ended.
+pub async fn catch_up_on_everything(ctx: &serenity::Context, data: &Data) -> Result<(), Error> {
+    let channel_id = get_the_channel_id();
+    let channel = serenity::ChannelId::new(channel_id).to_channel(ctx).await;
+    let channel = match channel {
+        Ok(serenity::Channel::Guild(channel)) => channel,
+        _ => {
+            log::error!("Channel is of the wrong type");
+            return Err("Channel is of the wrong type".into());
+        }
+    };
+    check_permissions(ctx, data, &channel).await;
+    // Ready also fires when a reconnect needs a new session, if that happens during a catch-up the
+    // next one waits and resumes where it ended
+    let _catching_up = data.catching_up.lock().await;
+    match channel.kind {
+        serenity::ChannelType::Forum => {
+            // Forums have no messages of their own, only posts, which are threads
+            data.watching_forum.store(true, atomic::Ordering::SeqCst);
+            if data.config().threads.mode == ThreadMode::Ignore {
+                log::warn!("Watching a forum channel, handling its posts even though `threads.mode` is \"ignore\"");
+            }
+        }
+        kind if channel.is_text_based() => {
+            log::info!("Catching up on {} channel {}", kind.name(), channel.name);
+            catch_up(data, channel.id, channel.id, None).await?;
+        }
+        kind => {
+            log::error!("Channel is of the wrong type");
+            return Err(format!("Channel {} is a {} channel, which has no messages to watch", channel.name, kind.name()).into());
+        }
+    }
+    if thread_mode(data) != ThreadMode::Ignore {
+        let active_threads = channel.guild_id.get_active_threads(ctx).await?;
+        for thread in active_threads.threads.iter().filter(|thread| thread.parent_id == Some(channel.id)) {
+            data.thread_parents.lock().await.insert(thread.id, Some(channel.id));
+            let Some(scope) = thread_scope(data, channel.id, thread.id) else {
+                continue;
+            };
+            if channel.kind == serenity::ChannelType::Forum {
+                check_post_title(ctx, data, thread, scope).await?;
+            }
+            catch_up(data, thread.id, scope, Some(thread_start(thread.id))).await?;
+        }
+    }
+    announce_milestone(ctx, channel.id, channel.id, data).await?;
+    log::debug!("Committing messages to disk");
+    commit_to_disk(&*data.messages_cache.lock().await).await?;
+    Ok(())
+}
+
+/// Alerts the owner if the bot lacks permissions it needs in the watched channel
+///
+/// Failures are only logged, so a broken alert doesn't keep the bot from catching up.
+async fn check_permissions(ctx: &serenity::Context, data: &Data, channel: &serenity::GuildChannel) {
+    let missing = match permissions::missing_permissions(ctx, channel, permissions::required_permissions(channel)).await {
+        Ok(missing) => missing,
+        Err(error) => {
+            log::error!("Failed to check permissions: {:?}", error);
+            return;
+        }
+    };
+    if missing.is_empty() {
+        return;
+    }
+    let names: Vec<_> = missing.iter_names().map(|(name, _)| name).collect();
+    let alert = tr(&data.config().i18n.locale, "alerts.missing_permissions", &[
+        ("permissions", &names.join(", ")),
+        ("channel", &channel.mention()),
+    ]);
+    if let Err(error) = alert_owner(ctx, data, &alert).await {
+        log::error!("Failed to alert the owner: {:?}", error);
+    }
+}
+
+/// Handles the messages sent to `channel_id` since the bot last saw it, using the cache of `scope`
+///
+/// Without a last seen message, catch-up starts after `start` if given, or at the latest messages.
+#[cfg_attr(feature = "otel", tracing::instrument(skip(data, start)))]
+async fn catch_up(
+    data: &Data,
+    channel_id: serenity::ChannelId,
+    scope: serenity::ChannelId,
+    start: Option<serenity::MessageId>,
+) -> Result<(), Error> {
+    let mut messages_cache = data.messages_cache.lock().await;
+    let mut last_message_id = messages_cache.last_message_ids.get(&channel_id).copied().or(start);
+    // Deleted once every message is handled, without holding up events and commands meanwhile
+    let mut duplicates = Vec::new();
+    // Sent to the webhook and the feed once the duplicates are deleted
+    let mut notifications = HashMap::new();
+    loop {
+        data.throttle.acquire().await;
+        let msgs = data.gateway.messages(channel_id, last_message_id).await?;
+        if msgs.is_empty() {
+            break;
+        }
+        // Oldest first, so the earliest of duplicates is kept and numbers are counted in order
+        for message in msgs.iter().rev() {
+            if data.config().blocklist.ignore_messages && messages_cache.blocklist.contains(&message.author.id) {
+                continue;
+            }
+            if ignore::is_ignored(data.bot_id, message, &data.config().ignore) {
+                continue;
+            }
+            log::debug!("Catching up on msg from {}: {}", message.author.name, message.content);
+            let config = data.config();
+            let chain = config.rule_chain(scope);
+            let candidate = rules::Candidate::new(message, &data.config().normalization_in(scope));
+            if messages_cache.banned.contains(&candidate.entry) {
+                log::debug!("Message {} is banned", message.id);
+                duplicates.push(message.id);
+                continue;
+            }
+            match rules::judge(chain, &data.config(), scope, &candidate, messages_cache.channel(scope)) {
+                rules::Judgement::Accept => {}
+                rules::Judgement::Reject(reason) => {
+                    log::debug!("Message {} breaks a rule: {}", message.id, reason);
+                    duplicates.push(message.id);
+                    continue;
+                }
+                // Old messages aren't worth a warning
+                rules::Judgement::Warn(_) => continue,
+            }
+            if !chain.contains(&RuleKind::Unique) {
+                let channel_cache = messages_cache.channel(scope);
+                rules::accept(chain, &data.config(), scope, &candidate, channel_cache);
+                channel_cache.record_accepted(Some(message.author.id.get()), *message.timestamp);
+                feed::accepted(data, feed::Acceptance::of_message(message, vec![candidate.entry.clone()]));
+                continue;
+            }
+            let keys = keys::message_keys(message, &data.config(), scope).await;
+            let keys = data.appeals.without_whitelisted(scope, keys).await;
+            let channel_cache = messages_cache.channel(scope);
+            let verdict = dedup::check_all(&channel_cache.cache, &keys, &data.config().dedup_rules_in(scope), *message.timestamp);
+            let verdict = claim(verdict, data, scope, message.id, &keys, *message.timestamp).await?;
+            if verdict == dedup::Verdict::Unique {
+                channel_cache.insert_keys(&keys, *message.timestamp, Some(message.author.id.get()), message_ref(data, message));
+                channel_cache.record_accepted(Some(message.author.id.get()), *message.timestamp);
+                rules::accept(chain, &data.config(), scope, &candidate, channel_cache);
+                feed::accepted(data, feed::Acceptance::of_message(message, keys.iter().map(dedup::Key::to_entry).collect()));
+            } else {
+                if let dedup::Verdict::NearDuplicate { closest, similarity } = &verdict {
+                    log::debug!("Message is {:.0}% similar to existing entry {:?}", similarity * 100.0, closest);
+                }
+                if let Some(policy) = own_repeat(data, scope, &channel_cache.cache, &keys, *message.timestamp, Some(message.author.id)) {
+                    spare_own_repeat(data, message.channel_id, message.id, message.author.id, policy).await?;
+                    continue;
+                }
+                if data.config().enforcement.mode == EnforcementMode::React {
+                    let first_posted = first_posted(&verdict, &keys, &channel_cache.cache);
+                    flag_duplicate(data, message.channel_id, message.id, &verdict, first_posted).await?;
+                    continue;
+                }
+                // Old messages aren't worth a reply
+                if data.config().lines.split && data.config().lines.action == LinesAction::Reply {
+                    continue;
+                }
+                forward_to_quarantine(data, message, &verdict).await;
+                let matched = feed::matched_entry(&verdict, &keys, &messages_cache.channel(scope).cache);
+                notifications.insert(message.id, feed::Deletion::of_message(message, &verdict, matched));
+                duplicates.push(message.id);
+            }
+        }
+        last_message_id = Some(msgs.first().unwrap().id); // messages are returned in reverse order (bottom to top)
+    }
+    if let Some(last_message_id) = last_message_id {
+        messages_cache.mark_handled(channel_id, last_message_id);
+    }
+    if duplicates.is_empty() {
+        return Ok(());
+    }
+    // Deleting old messages one by one takes a while. Every message was marked as handled, so
+    // events for new ones can go ahead in the meantime.
+    drop(messages_cache);
+    log::info!("Deleting {} duplicate messages", duplicates.len());
+    let outcomes = data.deletions.delete_many(channel_id, &duplicates, scope, data.config().catch_up.delete_interval).await;
+    messages_cache = data.messages_cache.lock().await;
+    for (message_id, outcome) in duplicates.iter().zip(outcomes) {
+        if matches!(outcome, deletion::Outcome::Deleted | deletion::Outcome::Retrying) {
+            if let Some(notification) = notifications.remove(message_id) {
+                feed::deleted(data, notification);
+            }
+        }
+        messages_cache.record_deletion(scope, outcome);
+    }
+    Ok(())
+}
+
+#[cfg_attr(feature = "otel", tracing::instrument(skip_all, fields(message_id = %new_message.id, channel_id = %new_message.channel_id)))]
+async fn on_message(ctx: &serenity::Context, new_message: &serenity::Message, data: &Data) -> Result<(), Error> {
+    let received_at = Instant::now();
+    let Some(scope) = resolve_scope(ctx, data, new_message.channel_id).await else {
+        log::debug!("Got an event \"message\" for channel {:?}, ignoring", new_message.channel_id);
+        return Ok(());
+    };
+    if ignore::is_ignored(data.bot_id, new_message, &data.config().ignore) {
+        log::debug!("Ignoring message {} as configured", new_message.id);
+        return Ok(());
+    }
+    log::debug!("Handling message from {:?}: {}", new_message.author_nick(ctx).await, new_message.content);
+    let config = data.config();
+    let chain = config.rule_chain(scope);
+    let candidate = rules::Candidate::new(new_message, &data.config().normalization_in(scope));
+    let judgement = {
+        let mut messages_cache = data.messages_cache.lock().await;
+        // A message sent during a catch-up may have been handled by it already
+        if !messages_cache.mark_handled(new_message.channel_id, new_message.id) {
+            log::debug!("Message {} was already handled", new_message.id);
+            return Ok(());
+        }
+        if data.config().blocklist.ignore_messages && messages_cache.blocklist.contains(&new_message.author.id) {
+            log::debug!("Ignoring message from blocked user {}", new_message.author.id);
+            return Ok(());
+        }
+        if messages_cache.banned.contains(&candidate.entry) {
+            drop(messages_cache);
+            return remove_banned(data, new_message, scope).await;
+        }
+        let channel_cache = messages_cache.channel(scope);
+        let judgement = rules::judge(chain, &data.config(), scope, &candidate, channel_cache);
+        if judgement == rules::Judgement::Accept && !chain.contains(&RuleKind::Unique) {
+            rules::accept(chain, &data.config(), scope, &candidate, channel_cache);
+            channel_cache.record_accepted(Some(new_message.author.id.get()), *new_message.timestamp);
+            feed::accepted(data, feed::Acceptance::of_message(new_message, vec![candidate.entry.clone()]));
+        }
+        judgement
+    };
+    if judgement != rules::Judgement::Accept {
+        enforce_rule(data, new_message, scope, judgement).await?;
+        commit_to_disk(&*data.messages_cache.lock().await).await?;
+        return Ok(());
+    }
+    if !chain.contains(&RuleKind::Unique) {
+        announce_milestone(ctx, new_message.channel_id, scope, data).await?;
+        commit_to_disk(&*data.messages_cache.lock().await).await?;
+        return Ok(());
+    }
+    if !new_message.embeds.is_empty() {
+        data.embeds_checked.lock().await.insert(new_message.id);
+    }
+    let keys = keys::message_keys(new_message, &data.config(), scope).await;
+    let keys = data.appeals.without_whitelisted(scope, keys).await;
+    let (verdict, own_repeat, first_posted) = {
+        let mut messages_cache = data.messages_cache.lock().await;
+        let channel_cache = messages_cache.channel(scope);
+        let verdict = dedup::check_all(&channel_cache.cache, &keys, &data.config().dedup_rules_in(scope), *new_message.timestamp);
+        let verdict = claim(verdict, data, scope, new_message.id, &keys, *new_message.timestamp).await?;
+        if verdict == dedup::Verdict::Unique {
+            channel_cache.insert_keys(&keys, *new_message.timestamp, Some(new_message.author.id.get()), message_ref(data, new_message));
+            channel_cache.record_accepted(Some(new_message.author.id.get()), *new_message.timestamp);
+            rules::accept(chain, &data.config(), scope, &candidate, channel_cache);
+            feed::accepted(data, feed::Acceptance::of_message(new_message, keys.iter().map(dedup::Key::to_entry).collect()));
+        }
+        let own_repeat = match verdict {
+            dedup::Verdict::Unique => None,
+            _ => own_repeat(data, scope, &channel_cache.cache, &keys, *new_message.timestamp, Some(new_message.author.id)),
+        };
+        let first_posted = first_posted(&verdict, &keys, &channel_cache.cache);
+        (verdict, own_repeat, first_posted)
+    };
+    if let Some(policy) = own_repeat {
+        spare_own_repeat(data, new_message.channel_id, new_message.id, new_message.author.id, policy).await?;
+    } else if verdict != dedup::Verdict::Unique && data.config().enforcement.mode == EnforcementMode::React {
+        flag_duplicate(data, new_message.channel_id, new_message.id, &verdict, first_posted).await?;
+    } else if verdict != dedup::Verdict::Unique && data.config().lines.split && data.config().lines.action == LinesAction::Reply {
+        list_duplicate_lines(data, new_message, scope, &keys).await?;
+    } else if verdict != dedup::Verdict::Unique {
+        forward_to_quarantine(data, new_message, &verdict).await;
+        log::info!("Deleting duplicate message");
+        let target = deletion::Target::Message { channel_id: new_message.channel_id, message_id: new_message.id };
+        let outcome = data.deletions.delete(target, scope).await;
+        let removed = matches!(outcome, deletion::Outcome::Deleted | deletion::Outcome::Retrying);
+        data.latencies.record_deletion(received_at.elapsed(), matches!(outcome, deletion::Outcome::Deleted | deletion::Outcome::Gone)).await;
+        {
+            let mut messages_cache = data.messages_cache.lock().await;
+            if removed {
+                let matched = feed::matched_entry(&verdict, &keys, &messages_cache.channel(scope).cache);
+                feed::deleted(data, feed::Deletion::of_message(new_message, &verdict, matched));
+            }
+            messages_cache.record_deletion(scope, outcome);
+        }
+        if removed {
+            let locale = data.config().i18n.locale.clone();
+            let author = new_message.author.mention();
+            let notice = match &verdict {
+                dedup::Verdict::NearDuplicate { closest, similarity } => {
+                    let similarity = format!("{:.0}", similarity * 100.0);
+                    tr(&locale, "notices.similar_removed", &[("author", &author), ("similarity", &similarity), ("entry", closest)])
+                }
+                dedup::Verdict::Related { closest, relation } => {
+                    let relation = i18n::relation(&locale, *relation);
+                    tr(&locale, "notices.related_removed", &[("author", &author), ("relation", &relation), ("entry", closest)])
+                }
+                _ => tr(&locale, "notices.duplicate_removed", &[("author", &author)]),
+            };
+            let notice = with_jump_link(&locale, notice, first_posted);
+            data.gateway.send_message(new_message.channel_id, serenity::CreateMessage::new().content(notice)).await?;
+        }
+        if removed && data.config().appeals.enabled {
+            let entries = keys.iter().map(dedup::Key::to_entry).collect();
+            let reason = describe_verdict(&data.config().i18n.locale, &verdict);
+            appeals::offer(ctx, data, new_message, scope, entries, reason).await?;
+        }
+        if removed {
+            cooldown::on_deletion(ctx, data, new_message.guild_id, new_message.author.id).await?;
+        }
+    }
+    if verdict == dedup::Verdict::Unique {
+        announce_milestone(ctx, new_message.channel_id, scope, data).await?;
+    }
+    //let ct = data.uncommitted_count.fetch_add(1, atomic::Ordering::SeqCst);
+    //if ct >= 9 {
+    log::debug!("Committing messages to disk");
+    commit_to_disk(&*data.messages_cache.lock().await).await?;
+    //    data.uncommitted_count.store(0, atomic::Ordering::SeqCst);
+    //}
+    Ok(())
+}
+
+async fn on_message_update(ctx: &serenity::Context, update: &serenity::MessageUpdateEvent, data: &Data) -> Result<(), Error> {
+    // Link previews are usually attached in an update shortly after the message is sent
+    if update.embeds.as_ref().is_none_or(|embeds| embeds.is_empty()) || !data.config().embeds.enabled {
+        return Ok(());
+    }
+    let Some(scope) = resolve_scope(ctx, data, update.channel_id).await else {
+        return Ok(());
+    };
+    if !data.config().rule_chain(scope).contains(&RuleKind::Unique) {
+        return Ok(());
+    }
+    {
+        let mut embeds_checked = data.embeds_checked.lock().await;
+        // Only the first set of embeds is checked, later ones would collide with themselves
+        if !embeds_checked.insert(update.id) {
+            return Ok(());
+        }
+        let an_hour_ago = serenity::Timestamp::from_unix_timestamp(serenity::Timestamp::now().unix_timestamp() - 3600)?;
+        embeds_checked.retain(|id| id.created_at() > an_hour_ago);
+    }
+    // The update is partial, so fetch the whole message to apply the ignore rules
+    data.throttle.acquire().await;
+    let message = update.channel_id.message(ctx, update.id).await?;
+    if ignore::is_ignored(data.bot_id, &message, &data.config().ignore) {
+        return Ok(());
+    }
+    let keys = keys::embed_keys(&message.embeds, &data.config(), scope);
+    let keys = data.appeals.without_whitelisted(scope, keys).await;
+    let (verdict, own_repeat, first_posted) = {
+        let mut messages_cache = data.messages_cache.lock().await;
+        if data.config().blocklist.ignore_messages && messages_cache.blocklist.contains(&message.author.id) {
+            return Ok(());
+        }
+        let channel_cache = messages_cache.channel(scope);
+        let verdict = dedup::check_all(&channel_cache.cache, &keys, &data.config().dedup_rules_in(scope), *message.timestamp);
+        let verdict = claim(verdict, data, scope, message.id, &keys, *message.timestamp).await?;
+        if verdict == dedup::Verdict::Unique {
+            channel_cache.insert_keys(&keys, *message.timestamp, Some(message.author.id.get()), message_ref(data, &message));
+        }
+        let own_repeat = match verdict {
+            dedup::Verdict::Unique => None,
+            _ => own_repeat(data, scope, &channel_cache.cache, &keys, *message.timestamp, Some(message.author.id)),
+        };
+        let first_posted = first_posted(&verdict, &keys, &channel_cache.cache);
+        (verdict, own_repeat, first_posted)
+    };
+    if let Some(policy) = own_repeat {
+        spare_own_repeat(data, message.channel_id, message.id, message.author.id, policy).await?;
+    } else if verdict != dedup::Verdict::Unique && data.config().enforcement.mode == EnforcementMode::React {
+        flag_duplicate(data, message.channel_id, message.id, &verdict, first_posted).await?;
+    } else if verdict != dedup::Verdict::Unique {
+        forward_to_quarantine(data, &message, &verdict).await;
+        log::info!("Deleting message with a duplicate embed");
+        let target = deletion::Target::Message { channel_id: message.channel_id, message_id: message.id };
+        let outcome = data.deletions.delete(target, scope).await;
+        let removed = matches!(outcome, deletion::Outcome::Deleted | deletion::Outcome::Retrying);
+        {
+            let mut messages_cache = data.messages_cache.lock().await;
+            if removed {
+                let matched = feed::matched_entry(&verdict, &keys, &messages_cache.channel(scope).cache);
+                feed::deleted(data, feed::Deletion::of_message(&message, &verdict, matched));
+            }
+            messages_cache.record_deletion(scope, outcome);
+        }
+        if removed {
+            cooldown::on_deletion(ctx, data, update.guild_id, message.author.id).await?;
+        }
+    }
+    log::debug!("Committing messages to disk");
+    commit_to_disk(&*data.messages_cache.lock().await).await?;
+    Ok(())
+}
+
+async fn on_thread_create(ctx: &serenity::Context, thread: &serenity::GuildChannel, data: &Data) -> Result<(), Error> {
+    // Remembered so messages in the new thread don't need a channel lookup
+    data.thread_parents.lock().await.insert(thread.id, thread.parent_id);
+    let watched = serenity::ChannelId::new(get_the_channel_id());
+    if thread.parent_id != Some(watched) {
+        return Ok(());
+    }
+    log::info!("Thread {:?} was created in the watched channel", thread.name);
+    if data.watching_forum.load(atomic::Ordering::SeqCst) {
+        if let Some(scope) = thread_scope(data, watched, thread.id) {
+            check_post_title(ctx, data, thread, scope).await?;
+            log::debug!("Committing messages to disk");
+            commit_to_disk(&*data.messages_cache.lock().await).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Treats the title of a forum post as an entry, deleting the whole post if it is a duplicate
+///
+/// Titles are only checked the first time a post is seen.
+async fn check_post_title(ctx: &serenity::Context, data: &Data, thread: &serenity::GuildChannel, scope: serenity::ChannelId) -> Result<(), Error> {
+    let (verdict, own_repeat, matched, first_posted) = {
+        let mut messages_cache = data.messages_cache.lock().await;
+        if messages_cache.last_message_ids.contains_key(&thread.id) {
+            return Ok(());
+        }
+        // Marks the post as seen, and makes catch-up start at its first message
+        messages_cache.last_message_ids.insert(thread.id, thread_start(thread.id));
+        if let Some(owner_id) = thread.owner_id {
+            if data.config().blocklist.ignore_messages && messages_cache.blocklist.contains(&owner_id) {
+                return Ok(());
+            }
+        }
+        let keys = vec![dedup::Key::Text(normalize_string(&thread.name, &data.config().normalization_in(scope)))];
+        let keys = data.appeals.without_whitelisted(scope, keys).await;
+        // Posts are created together with the thread, which is as old as its ID
+        let created_at = *thread.id.created_at();
+        let channel_cache = messages_cache.channel(scope);
+        let verdict = dedup::check_all(&channel_cache.cache, &keys, &data.config().dedup_rules_in(scope), created_at);
+        // The post's starter message has the thread's ID
+        let verdict = claim(verdict, data, scope, serenity::MessageId::new(thread.id.get()), &keys, created_at).await?;
+        if verdict == dedup::Verdict::Unique {
+            let starter = MessageRef { guild_id: thread.guild_id.get(), channel_id: thread.id.get(), message_id: thread.id.get() };
+            channel_cache.insert_keys(&keys, created_at, thread.owner_id.map(serenity::UserId::get), Some(starter));
+            channel_cache.record_accepted(thread.owner_id.map(serenity::UserId::get), created_at);
+            feed::accepted(data, feed::Acceptance::of_post(thread, keys.iter().map(dedup::Key::to_entry).collect()));
+        }
+        let own_repeat = match verdict {
+            dedup::Verdict::Unique => None,
+            _ => own_repeat(data, scope, &channel_cache.cache, &keys, created_at, thread.owner_id).zip(thread.owner_id),
+        };
+        let matched = feed::matched_entry(&verdict, &keys, &channel_cache.cache);
+        let first_posted = first_posted(&verdict, &keys, &channel_cache.cache);
+        (verdict, own_repeat, matched, first_posted)
+    };
+    if let Some((policy, owner_id)) = own_repeat {
+        spare_own_repeat(data, thread.id, serenity::MessageId::new(thread.id.get()), owner_id, policy).await?;
+    } else if verdict != dedup::Verdict::Unique && data.config().enforcement.mode == EnforcementMode::React {
+        // The post's starter message has the thread's ID
+        flag_duplicate(data, thread.id, serenity::MessageId::new(thread.id.get()), &verdict, first_posted).await?;
+    } else if verdict != dedup::Verdict::Unique {
+        log::info!("Deleting forum post {:?} with a duplicate title", thread.name);
+        let outcome = data.deletions.delete(deletion::Target::Post(thread.id), scope).await;
+        let removed = matches!(outcome, deletion::Outcome::Deleted | deletion::Outcome::Retrying);
+        data.messages_cache.lock().await.record_deletion(scope, outcome);
+        if removed {
+            feed::deleted(data, feed::Deletion::of_post(thread, &verdict, matched));
+        }
+        if let (true, Some(owner_id)) = (removed, thread.owner_id) {
+            cooldown::on_deletion(ctx, data, Some(thread.guild_id), owner_id).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Where `message` is, for linking to it once its entries are reposted
+///
+/// Messages fetched from the REST API don't say which guild they are in, which is the watched
+/// channel's then.
+fn message_ref(data: &Data, message: &serenity::Message) -> Option<MessageRef> {
+    let guild_id = message.guild_id.or_else(|| data.watched_guild.get().copied())?;
+    Some(MessageRef { guild_id: guild_id.get(), channel_id: message.channel_id.get(), message_id: message.id.get() })
+}
+
+/// Where the entry a duplicate with `verdict` matched was first posted, unless that's unknown
+fn first_posted(verdict: &dedup::Verdict, keys: &[dedup::Key], cache: &HashMap<String, Entry>) -> Option<MessageRef> {
+    let matched = feed::matched_entry(verdict, keys, cache)?;
+    cache.get(&matched)?.message
+}
+
+/// Adds a jump link to the message that first posted an entry to a notice about its duplicate
+fn with_jump_link(locale: &str, notice: String, first_posted: Option<MessageRef>) -> String {
+    match first_posted {
+        Some(first_posted) => format!("{}\n{}", notice, tr(locale, "notices.first_posted", &[("link", &first_posted.url())])),
+        None => notice,
+    }
+}
+
+/// Why a message with `verdict` is deleted, for humans
+fn describe_verdict(locale: &str, verdict: &dedup::Verdict) -> String {
+    match verdict {
+        dedup::Verdict::NearDuplicate { closest, similarity } => {
+            tr(locale, "verdicts.similar", &[("similarity", &format!("{:.0}", similarity * 100.0)), ("entry", closest)])
+        }
+        dedup::Verdict::Related { closest, relation } => {
+            capitalize(&tr(locale, "verdicts.related", &[("relation", &i18n::relation(locale, *relation)), ("entry", closest)]))
+        }
+        _ => tr(locale, "verdicts.duplicate", &[]),
+    }
+}
+
+/// `text` with its first letter in uppercase
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Deletes or warns about a message that breaks one of its channel's rules
+async fn enforce_rule(
+    data: &Data,
+    message: &serenity::Message,
+    scope: serenity::ChannelId,
+    judgement: rules::Judgement,
+) -> Result<(), Error> {
+    match judgement {
+        rules::Judgement::Accept => {}
+        rules::Judgement::Reject(violation) => {
+            log::info!("Deleting message {}, which breaks a rule: {}", message.id, violation);
+            let target = deletion::Target::Message { channel_id: message.channel_id, message_id: message.id };
+            let outcome = data.deletions.delete(target, scope).await;
+            let removed = matches!(outcome, deletion::Outcome::Deleted | deletion::Outcome::Retrying);
+            data.messages_cache.lock().await.record_deletion(scope, outcome);
+            if removed {
+                let notice = tr(&data.config().i18n.locale, "notices.rule_broken", &[("author", &message.author.mention()), ("violation", &violation)]);
+                data.gateway.send_message(message.channel_id, serenity::CreateMessage::new().content(notice)).await?;
+            }
+        }
+        rules::Judgement::Warn(violation) => {
+            log::info!("Warning about message {}, which breaks a rule: {}", message.id, violation);
+            let warning = serenity::CreateMessage::new()
+                .content(tr(&data.config().i18n.locale, "notices.rule_warning", &[("violation", &violation)]))
+                .reference_message(message);
+            data.gateway.send_message(message.channel_id, warning).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Deletes a message whose entry is banned, telling its author why if configured
+async fn remove_banned(data: &Data, message: &serenity::Message, scope: serenity::ChannelId) -> Result<(), Error> {
+    log::info!("Deleting message {}, whose entry is banned", message.id);
+    let target = deletion::Target::Message { channel_id: message.channel_id, message_id: message.id };
+    let outcome = data.deletions.delete(target, scope).await;
+    let removed = matches!(outcome, deletion::Outcome::Deleted | deletion::Outcome::Retrying);
+    {
+        let mut messages_cache = data.messages_cache.lock().await;
+        messages_cache.record_deletion(scope, outcome);
+        commit_to_disk(&messages_cache).await?;
+    }
+    if removed && data.config().banned.notify {
+        let notice = tr(&data.config().i18n.locale, "notices.banned", &[("author", &message.author.mention())]);
+        data.gateway.send_message(message.channel_id, serenity::CreateMessage::new().content(notice)).await?;
+    }
+    Ok(())
+}
+
+/// The policy for a duplicate by `author` whose originals are all their own, unless that is to
+/// delete it like any other
+fn own_repeat(
+    data: &Data,
+    scope: serenity::ChannelId,
+    cache: &HashMap<String, Entry>,
+    keys: &[dedup::Key],
+    sent_at: DateTime<Utc>,
+    author: Option<serenity::UserId>,
+) -> Option<RepeatPolicy> {
+    let policy = data.config().repeats.same_author;
+    let author = author?;
+    let repeats_own = dedup::repeats_own(cache, keys, &data.config().dedup_rules_in(scope), sent_at, author.get());
+    (policy != RepeatPolicy::Delete && repeats_own).then_some(policy)
+}
+
+/// Leaves a duplicate of its author's own entry in place, warning them if configured
+async fn spare_own_repeat(
+    data: &Data,
+    channel_id: serenity::ChannelId,
+    message_id: serenity::MessageId,
+    author: serenity::UserId,
+    policy: RepeatPolicy,
+) -> Result<(), Error> {
+    log::info!("Keeping message {}, which repeats its author's own entry", message_id);
+    if policy == RepeatPolicy::Warn {
+        let warning = serenity::CreateMessage::new()
+            .content(tr(&data.config().i18n.locale, "notices.own_repeat", &[("author", &author.mention())]))
+            .reference_message((channel_id, message_id));
+        data.gateway.send_message(channel_id, warning).await?;
+    }
+    Ok(())
+}
+
+/// Flags a duplicate with the configured reaction, and a reply saying why if configured, instead
+/// of deleting it
+async fn flag_duplicate(
+    data: &Data,
+    channel_id: serenity::ChannelId,
+    message_id: serenity::MessageId,
+    verdict: &dedup::Verdict,
+    first_posted: Option<MessageRef>,
+) -> Result<(), Error> {
+    log::info!("Flagging duplicate message {}", message_id);
+    let reaction = serenity::ReactionType::try_from(data.config().enforcement.emoji.as_str())?;
+    data.gateway.create_reaction(channel_id, message_id, reaction).await?;
+    if data.config().enforcement.note {
+        let locale = data.config().i18n.locale.clone();
+        let note = match verdict {
+            dedup::Verdict::NearDuplicate { closest, similarity } => {
+                tr(&locale, "notices.similar", &[("similarity", &format!("{:.0}", similarity * 100.0)), ("entry", closest)])
+            }
+            dedup::Verdict::Related { closest, relation } => {
+                tr(&locale, "notices.related", &[("relation", &i18n::relation(&locale, *relation)), ("entry", closest)])
+            }
+            _ => tr(&locale, "notices.duplicate", &[]),
+        };
+        let reply = serenity::CreateMessage::new()
+            .content(with_jump_link(&locale, note, first_posted))
+            .reference_message((channel_id, message_id))
+            .allowed_mentions(serenity::CreateAllowedMentions::new());
+        data.gateway.send_message(channel_id, reply).await?;
+    }
+    Ok(())
+}
+
+/// Replies to a message with the lines that duplicate cached entries, instead of deleting it
+async fn list_duplicate_lines(
+    data: &Data,
+    message: &serenity::Message,
+    scope: serenity::ChannelId,
+    keys: &[dedup::Key],
+) -> Result<(), Error> {
+    log::info!("Listing the duplicate lines of message {}", message.id);
+    let lines: Vec<String> = {
+        let mut messages_cache = data.messages_cache.lock().await;
+        let cache = &messages_cache.channel(scope).cache;
+        let config = data.config();
+        let rules = config.dedup_rules_in(scope);
+        keys.iter()
+            .filter(|key| matches!(key, dedup::Key::Text(_)))
+            .filter_map(|key| {
+                let verdict = dedup::check(cache, key, &rules, *message.timestamp);
+                match (&verdict, first_posted(&verdict, std::slice::from_ref(key), cache)) {
+                    (dedup::Verdict::Unique, _) => None,
+                    (_, Some(first_posted)) => Some(format!("- `{}` ({})", key.to_entry(), first_posted.url())),
+                    (_, None) => Some(format!("- `{}`", key.to_entry())),
+                }
+            })
+            .collect()
+    };
+    // A sticker, upload or embed may be what was already posted
+    let locale = data.config().i18n.locale.clone();
+    let content = match lines.is_empty() {
+        true => tr(&locale, "notices.lines_duplicate", &[]),
+        false => format!("{}\n{}", tr(&locale, "notices.lines", &[]), lines.join("\n")),
+    };
+    let reply = serenity::CreateMessage::new()
+        .content(content)
+        .reference_message(message)
+        .allowed_mentions(serenity::CreateAllowedMentions::new());
+    data.gateway.send_message(message.channel_id, reply).await?;
+    Ok(())
+}
+
+/// Forwards a duplicate about to be deleted to the quarantine channel, if there is one
+///
+/// Failures are only logged, so duplicates are still deleted while the channel is unavailable.
+async fn forward_to_quarantine(data: &Data, message: &serenity::Message, verdict: &dedup::Verdict) {
+    let Some(channel_id) = data.config().quarantine.channel else {
+        return;
+    };
+    let locale = data.config().i18n.locale.clone();
+    if let Err(error) = crate::quarantine::forward(&*data.gateway, channel_id, message, &describe_verdict(&locale, verdict), &locale).await {
+        log::error!("Failed to forward message {} to the quarantine channel: {:?}", message.id, error);
+    }
+}
+
+/// Claims the keys of a message found unique in this instance's cache, so bot instances sharing
+/// the store can't both accept the same entry. The message counts as a duplicate if another
+/// message claimed any of them first.
+async fn claim(
+    verdict: dedup::Verdict,
+    data: &Data,
+    scope: serenity::ChannelId,
+    message_id: serenity::MessageId,
+    keys: &[dedup::Key],
+    sent_at: DateTime<Utc>,
+) -> Result<dedup::Verdict, Error> {
+    if verdict != dedup::Verdict::Unique {
+        return Ok(verdict);
+    }
+    let entries: Vec<String> = keys.iter().map(dedup::Key::to_entry).collect();
+    let expired_before = data.config().ttl.expire_after.map(|ttl| sent_at - ttl);
+    if get_the_store().claim(scope, message_id, &entries, expired_before).await? {
+        Ok(verdict)
+    } else {
+        log::info!("Another instance already claimed the entries of message {}", message_id);
+        Ok(dedup::Verdict::Duplicate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        gateway::fake::{self, message_id, Recording, Request},
+    };
+
+    fn channel() -> serenity::ChannelId {
+        serenity::ChannelId::new(10)
+    }
+
+    /// Messages of `contents` sent a second apart a minute ago, by a different user each, newest
+    /// first like Discord returns them
+    fn page(contents: &[&str]) -> Vec<serenity::Message> {
+        let start = Utc::now() - chrono::Duration::minutes(1);
+        let mut messages: Vec<_> = contents
+            .iter()
+            .enumerate()
+            .map(|(i, content)| {
+                let sent_at = start + chrono::Duration::seconds(i as i64);
+                fake::message(message_id(sent_at), channel(), serenity::UserId::new(100 + i as u64), content)
+            })
+            .collect();
+        messages.reverse();
+        messages
+    }
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.catch_up.delete_interval = Duration::ZERO;
+        config
+    }
+
+    #[tokio::test]
+    async fn catch_up_deletes_duplicates_and_caches_the_rest() {
+        let messages = page(&["apple", "banana", "apple", "apple"]);
+        let gateway = Arc::new(Recording::new(vec![messages.clone()]));
+        let data = Data::for_tests(config(), gateway.clone()).await;
+        catch_up(&data, channel(), channel(), None).await.unwrap();
+        // The first of the duplicates is kept, the later ones are deleted at once
+        assert_eq!(gateway.requests(), vec![Request::DeleteMessages(vec![messages[1].id, messages[0].id])]);
+        assert_eq!(gateway.fetched_after(), vec![None, Some(messages[0].id)]);
+        let messages_cache = &mut *data.messages_cache.lock().await;
+        assert_eq!(messages_cache.last_message_ids[&channel()], messages[0].id);
+        assert!(messages_cache.dead_letters.is_empty());
+        let channel_cache = messages_cache.channel(channel());
+        assert_eq!(channel_cache.cache.len(), 2);
+        assert_eq!(channel_cache.round.deleted, 2);
+    }
+
+    #[tokio::test]
+    async fn catch_up_resumes_after_the_last_handled_message() {
+        let messages = page(&["cherry"]);
+        let last_handled = serenity::MessageId::new(messages[0].id.get() - 1);
+        let gateway = Arc::new(Recording::new(vec![messages.clone()]));
+        let data = Data::for_tests(config(), gateway.clone()).await;
+        data.messages_cache.lock().await.mark_handled(channel(), last_handled);
+        catch_up(&data, channel(), channel(), None).await.unwrap();
+        assert_eq!(gateway.fetched_after(), vec![Some(last_handled), Some(messages[0].id)]);
+        assert!(gateway.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn catch_up_saves_failed_deletions_as_dead_letters() {
+        let messages = page(&["apple", "banana", "apple"]);
+        let duplicate = messages[0].id;
+        let gateway = Arc::new(Recording::new(vec![messages.clone()]).undeletable([duplicate]));
+        let data = Data::for_tests(config(), gateway.clone()).await;
+        catch_up(&data, channel(), channel(), None).await.unwrap();
+        assert!(gateway.deleted().is_empty());
+        // Like the catch-ups that call it do when they are done
+        commit_to_disk(&*data.messages_cache.lock().await).await.unwrap();
+        let saved = get_the_store().load(channel()).await.unwrap().expect("The cache was saved");
+        let [dead_letter] = &saved.dead_letters[..] else {
+            panic!("Expected one dead letter, got {}", saved.dead_letters.len());
+        };
+        assert!(matches!(dead_letter.target, deletion::Target::Message { message_id, .. } if message_id == duplicate));
+        assert_eq!(dead_letter.scope, channel());
+        assert_eq!(saved.last_message_ids[&channel()], messages[0].id);
+        assert_eq!(saved.channels[&channel()].cache.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn catch_up_deletes_one_by_one_when_bulk_deletes_fail() {
+        let messages = page(&["apple", "apple", "apple"]);
+        let gateway = Arc::new(Recording::new(vec![messages.clone()]).bulk_deletes_fail());
+        let data = Data::for_tests(config(), gateway.clone()).await;
+        catch_up(&data, channel(), channel(), None).await.unwrap();
+        assert_eq!(gateway.requests(), vec![Request::DeleteMessage(messages[1].id), Request::DeleteMessage(messages[0].id)]);
+        assert_eq!(data.messages_cache.lock().await.channel(channel()).round.deleted, 2);
+    }
+
+    #[tokio::test]
+    async fn catch_up_flags_duplicates_in_the_react_mode() {
+        let messages = page(&["apple", "apple"]);
+        let gateway = Arc::new(Recording::new(vec![messages.clone()]));
+        let mut config = config();
+        config.enforcement.mode = EnforcementMode::React;
+        let data = Data::for_tests(config, gateway.clone()).await;
+        catch_up(&data, channel(), channel(), None).await.unwrap();
+        assert_eq!(gateway.requests(), vec![Request::CreateReaction(messages[0].id)]);
+    }
+}
@@ -0,0 +1,100 @@
+use crate::cache::MessagesCache;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use set_core::dedup::Key;
+
+/// File formats the cache can be exported as
+#[derive(Debug, Clone, Copy, poise::ChoiceParameter, clap::ValueEnum)]
+pub enum Format {
+    #[name = "CSV"]
+    Csv,
+    #[name = "JSON"]
+    Json,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Json => "json",
+        }
+    }
+}
+
+/// A cached entry as it is exported
+#[derive(Serialize)]
+struct ExportedEntry<'a> {
+    channel_id: u64,
+    kind: &'static str,
+    entry: &'a str,
+    first_seen: DateTime<Utc>,
+}
+
+fn kind(key: &Key) -> &'static str {
+    match key {
+        Key::Text(_) => "text",
+        Key::File(_) => "file",
+        Key::Image(_) => "image",
+        Key::Sticker(_) => "sticker",
+        Key::Embed(_) => "embed",
+    }
+}
+
+/// Serializes every cached entry, split into files of at most `max_size` bytes each
+///
+/// Every file is complete on its own, i.e. each CSV file has a header and each JSON file is an
+/// array. Entries are sorted by channel and then by when they were first seen.
+pub fn export(messages_cache: &MessagesCache, format: Format, max_size: usize) -> Vec<Vec<u8>> {
+    let mut entries: Vec<ExportedEntry> = messages_cache.channels
+        .iter()
+        .flat_map(|(channel_id, channel_cache)| {
+            channel_cache.cache.iter().map(move |(entry, metadata)| ExportedEntry {
+                channel_id: channel_id.get(),
+                kind: kind(&Key::from_entry(entry)),
+                entry,
+                first_seen: metadata.first_seen,
+            })
+        })
+        .collect();
+    entries.sort_by(|a, b| (a.channel_id, a.first_seen, a.entry).cmp(&(b.channel_id, b.first_seen, b.entry)));
+    let (header, separator, footer): (&[u8], &[u8], &[u8]) = match format {
+        Format::Csv => (b"channel_id,kind,entry,first_seen\n", b"", b""),
+        Format::Json => (b"[\n", b",\n", b"\n]\n"),
+    };
+    let mut files = Vec::new();
+    let mut file = header.to_vec();
+    let mut is_empty = true;
+    for entry in &entries {
+        let row = match format {
+            Format::Csv => format!(
+                "{},{},{},{}\n",
+                entry.channel_id, entry.kind, csv_field(entry.entry), entry.first_seen.to_rfc3339(),
+            )
+            .into_bytes(),
+            Format::Json => serde_json::to_vec(entry).expect("Entries always serialize"),
+        };
+        let size = file.len() + separator.len() + row.len() + footer.len();
+        if !is_empty && size > max_size {
+            file.extend_from_slice(footer);
+            files.push(std::mem::replace(&mut file, header.to_vec()));
+            is_empty = true;
+        }
+        if !is_empty {
+            file.extend_from_slice(separator);
+        }
+        file.extend_from_slice(&row);
+        is_empty = false;
+    }
+    file.extend_from_slice(footer);
+    files.push(file);
+    files
+}
+
+/// Quotes a CSV field if it contains anything that would otherwise break the row
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
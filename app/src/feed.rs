@@ -0,0 +1,154 @@
+use crate::{webhook, Data};
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude as serenity;
+use serde::Serialize;
+use set_core::{cache::Entry, dedup};
+use std::collections::HashMap;
+use tokio::sync::broadcast;
+
+/// Events kept for subscribers that fall behind, older ones are skipped
+const CAPACITY: usize = 256;
+
+/// Something the bot did, as sent to the webhook and streamed by the API
+///
+/// Snowflakes are strings, since they don't fit into JavaScript's numbers.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    EntryAccepted(Acceptance),
+    DuplicateDeleted(Deletion),
+}
+
+/// A message, or forum post title, that was accepted as unique
+#[derive(Serialize, Debug, Clone)]
+pub struct Acceptance {
+    channel_id: String,
+    message_id: String,
+    author: Option<Author>,
+    /// The entries it added to the cache
+    entries: Vec<String>,
+    accepted_at: DateTime<Utc>,
+}
+
+/// A deleted duplicate
+#[derive(Serialize, Debug, Clone)]
+pub struct Deletion {
+    channel_id: String,
+    /// The deleted message, or the starter message of a deleted forum post
+    message_id: String,
+    author: Option<Author>,
+    /// The message's text, or a forum post's title
+    content: String,
+    /// The cached entry the message duplicated, if it can be told
+    matched: Option<String>,
+    /// How similar the message was to `matched`, for near duplicates
+    similarity: Option<f64>,
+    /// How the message related to `matched`, for anagrams, substrings and superstrings
+    relation: Option<String>,
+    deleted_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct Author {
+    id: String,
+    /// Unknown for forum posts, whose owners are only known by their ID
+    name: Option<String>,
+}
+
+impl Author {
+    fn of_message(message: &serenity::Message) -> Self {
+        Self { id: message.author.id.to_string(), name: Some(message.author.name.clone()) }
+    }
+    fn of_post(thread: &serenity::GuildChannel) -> Option<Self> {
+        thread.owner_id.map(|owner_id| Self { id: owner_id.to_string(), name: None })
+    }
+}
+
+impl Acceptance {
+    pub fn of_message(message: &serenity::Message, entries: Vec<String>) -> Self {
+        Self {
+            channel_id: message.channel_id.to_string(),
+            message_id: message.id.to_string(),
+            author: Some(Author::of_message(message)),
+            entries,
+            accepted_at: Utc::now(),
+        }
+    }
+    pub fn of_post(thread: &serenity::GuildChannel, entries: Vec<String>) -> Self {
+        Self {
+            channel_id: thread.id.to_string(),
+            // The post's starter message has the thread's ID
+            message_id: thread.id.to_string(),
+            author: Author::of_post(thread),
+            entries,
+            accepted_at: Utc::now(),
+        }
+    }
+}
+
+impl Deletion {
+    fn new(channel_id: serenity::ChannelId, message_id: serenity::MessageId, author: Option<Author>, content: String, verdict: &dedup::Verdict, matched: Option<String>) -> Self {
+        let (similarity, relation) = match verdict {
+            dedup::Verdict::NearDuplicate { similarity, .. } => (Some(*similarity), None),
+            dedup::Verdict::Related { relation, .. } => (None, Some(relation.to_string())),
+            _ => (None, None),
+        };
+        Self {
+            channel_id: channel_id.to_string(),
+            message_id: message_id.to_string(),
+            author,
+            content,
+            matched,
+            similarity,
+            relation,
+            deleted_at: Utc::now(),
+        }
+    }
+    pub fn of_message(message: &serenity::Message, verdict: &dedup::Verdict, matched: Option<String>) -> Self {
+        Self::new(message.channel_id, message.id, Some(Author::of_message(message)), message.content.clone(), verdict, matched)
+    }
+    pub fn of_post(thread: &serenity::GuildChannel, verdict: &dedup::Verdict, matched: Option<String>) -> Self {
+        // The post's starter message has the thread's ID
+        Self::new(thread.id, serenity::MessageId::new(thread.id.get()), Author::of_post(thread), thread.name.clone(), verdict, matched)
+    }
+}
+
+/// The entry a message with `keys` was found to duplicate in `cache`
+pub fn matched_entry(verdict: &dedup::Verdict, keys: &[dedup::Key], cache: &HashMap<String, Entry>) -> Option<String> {
+    match verdict {
+        dedup::Verdict::NearDuplicate { closest, .. } | dedup::Verdict::Related { closest, .. } => Some(closest.clone()),
+        _ => keys.iter().map(dedup::Key::to_entry).find(|entry| cache.contains_key(entry)),
+    }
+}
+
+/// Hands out the bot's events to everyone streaming them from the API
+pub struct Feed {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Default for Feed {
+    fn default() -> Self {
+        Self { sender: broadcast::channel(CAPACITY).0 }
+    }
+}
+
+impl Feed {
+    /// Events from now on
+    #[cfg_attr(not(feature = "api"), allow(dead_code))]
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
+
+/// Streams an accepted message to the API's subscribers
+pub fn accepted(data: &Data, acceptance: Acceptance) {
+    // Nobody listening isn't an error
+    let _ = data.feed.sender.send(Event::EntryAccepted(acceptance));
+}
+
+/// Sends a deleted duplicate to the webhook and streams it to the API's subscribers
+pub fn deleted(data: &Data, deletion: Deletion) {
+    let event = Event::DuplicateDeleted(deletion);
+    webhook::notify(data, &event);
+    let _ = data.feed.sender.send(event);
+}
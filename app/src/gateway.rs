@@ -0,0 +1,244 @@
+use crate::latency::Latencies;
+use poise::serenity_prelude as serenity;
+use std::{future::Future, sync::Arc, time::Instant};
+
+/// The Discord requests that catching up and deleting duplicates rely on
+///
+/// The bot sends them through serenity's HTTP client, but anything implementing this can stand in
+/// for Discord, e.g. a fake that records what would have been deleted.
+#[async_trait::async_trait]
+pub trait Gateway: Send + Sync {
+    /// A page of the messages of `channel_id` sent after `after`, or the latest 100 if unset,
+    /// newest first
+    async fn messages(&self, channel_id: serenity::ChannelId, after: Option<serenity::MessageId>) -> serenity::Result<Vec<serenity::Message>>;
+    async fn delete_message(&self, channel_id: serenity::ChannelId, message_id: serenity::MessageId) -> serenity::Result<()>;
+    /// Deletes 2 to 100 messages of `channel_id` at once, none older than two weeks
+    async fn delete_messages(&self, channel_id: serenity::ChannelId, message_ids: &[serenity::MessageId]) -> serenity::Result<()>;
+    /// Deletes a channel, or a thread with all of its messages
+    async fn delete_channel(&self, channel_id: serenity::ChannelId) -> serenity::Result<()>;
+    async fn send_message(&self, channel_id: serenity::ChannelId, message: serenity::CreateMessage) -> serenity::Result<serenity::Message>;
+    async fn create_reaction(&self, channel_id: serenity::ChannelId, message_id: serenity::MessageId, reaction: serenity::ReactionType) -> serenity::Result<()>;
+    /// Downloads a file from Discord's CDN, e.g. an attachment to upload again
+    async fn download(&self, url: &str) -> serenity::Result<serenity::CreateAttachment>;
+}
+
+#[async_trait::async_trait]
+impl Gateway for serenity::Http {
+    #[cfg_attr(feature = "otel", tracing::instrument(skip(self)))]
+    async fn messages(&self, channel_id: serenity::ChannelId, after: Option<serenity::MessageId>) -> serenity::Result<Vec<serenity::Message>> {
+        let query = match after {
+            Some(after) => serenity::builder::GetMessages::new().after(after),
+            None => serenity::builder::GetMessages::new().limit(100), // INFO: this is technically bugged, since without any specification, messages are ordered by most recent
+        };
+        channel_id.messages(self, query).await
+    }
+    #[cfg_attr(feature = "otel", tracing::instrument(skip(self)))]
+    async fn delete_message(&self, channel_id: serenity::ChannelId, message_id: serenity::MessageId) -> serenity::Result<()> {
+        channel_id.delete_message(self, message_id).await
+    }
+    #[cfg_attr(feature = "otel", tracing::instrument(skip(self)))]
+    async fn delete_messages(&self, channel_id: serenity::ChannelId, message_ids: &[serenity::MessageId]) -> serenity::Result<()> {
+        channel_id.delete_messages(self, message_ids).await
+    }
+    #[cfg_attr(feature = "otel", tracing::instrument(skip(self)))]
+    async fn delete_channel(&self, channel_id: serenity::ChannelId) -> serenity::Result<()> {
+        channel_id.delete(self).await.map(|_| ())
+    }
+    #[cfg_attr(feature = "otel", tracing::instrument(skip(self, message)))]
+    async fn send_message(&self, channel_id: serenity::ChannelId, message: serenity::CreateMessage) -> serenity::Result<serenity::Message> {
+        channel_id.send_message(self, message).await
+    }
+    #[cfg_attr(feature = "otel", tracing::instrument(skip(self)))]
+    async fn create_reaction(&self, channel_id: serenity::ChannelId, message_id: serenity::MessageId, reaction: serenity::ReactionType) -> serenity::Result<()> {
+        channel_id.create_reaction(self, message_id, reaction).await
+    }
+    #[cfg_attr(feature = "otel", tracing::instrument(skip(self)))]
+    async fn download(&self, url: &str) -> serenity::Result<serenity::CreateAttachment> {
+        serenity::CreateAttachment::url(self, url).await
+    }
+}
+
+/// Times every request of another gateway, for the latencies in `/stats`
+pub struct Measured {
+    inner: Arc<dyn Gateway>,
+    latencies: Arc<Latencies>,
+}
+
+impl Measured {
+    pub fn new(inner: Arc<dyn Gateway>, latencies: Arc<Latencies>) -> Self {
+        Self { inner, latencies }
+    }
+    async fn measure<T>(&self, request: impl Future<Output = serenity::Result<T>>) -> serenity::Result<T> {
+        let started = Instant::now();
+        let result = request.await;
+        self.latencies.record_request(started.elapsed(), result.is_ok()).await;
+        result
+    }
+}
+
+#[async_trait::async_trait]
+impl Gateway for Measured {
+    async fn messages(&self, channel_id: serenity::ChannelId, after: Option<serenity::MessageId>) -> serenity::Result<Vec<serenity::Message>> {
+        self.measure(self.inner.messages(channel_id, after)).await
+    }
+    async fn delete_message(&self, channel_id: serenity::ChannelId, message_id: serenity::MessageId) -> serenity::Result<()> {
+        self.measure(self.inner.delete_message(channel_id, message_id)).await
+    }
+    async fn delete_messages(&self, channel_id: serenity::ChannelId, message_ids: &[serenity::MessageId]) -> serenity::Result<()> {
+        self.measure(self.inner.delete_messages(channel_id, message_ids)).await
+    }
+    async fn delete_channel(&self, channel_id: serenity::ChannelId) -> serenity::Result<()> {
+        self.measure(self.inner.delete_channel(channel_id)).await
+    }
+    async fn send_message(&self, channel_id: serenity::ChannelId, message: serenity::CreateMessage) -> serenity::Result<serenity::Message> {
+        self.measure(self.inner.send_message(channel_id, message)).await
+    }
+    async fn create_reaction(&self, channel_id: serenity::ChannelId, message_id: serenity::MessageId, reaction: serenity::ReactionType) -> serenity::Result<()> {
+        self.measure(self.inner.create_reaction(channel_id, message_id, reaction)).await
+    }
+    /// Not measured, since downloads go to the CDN rather than the API
+    async fn download(&self, url: &str) -> serenity::Result<serenity::CreateAttachment> {
+        self.inner.download(url).await
+    }
+}
+
+/// A stand-in for Discord in tests, which serves canned pages of messages and records everything
+/// else it is asked to do
+#[cfg(test)]
+pub mod fake {
+    use super::Gateway;
+    use chrono::{DateTime, Utc};
+    use poise::serenity_prelude as serenity;
+    use std::{
+        collections::{HashSet, VecDeque},
+        sync::Mutex,
+    };
+
+    /// A request the bot sent, other than fetching messages
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Request {
+        DeleteMessage(serenity::MessageId),
+        DeleteMessages(Vec<serenity::MessageId>),
+        DeleteChannel(serenity::ChannelId),
+        /// A message sent to a channel, by its content
+        SendMessage(serenity::ChannelId, Option<String>),
+        CreateReaction(serenity::MessageId),
+    }
+
+    #[derive(Default)]
+    pub struct Recording {
+        /// Pages returned by `messages`, newest message first, in the order they are asked for.
+        /// Once they are used up, pages are empty.
+        pages: Mutex<VecDeque<Vec<serenity::Message>>>,
+        /// Where each page of messages was asked to start
+        fetched_after: Mutex<Vec<Option<serenity::MessageId>>>,
+        requests: Mutex<Vec<Request>>,
+        /// Messages whose deletion fails for good, like it does without permissions
+        undeletable: HashSet<serenity::MessageId>,
+        /// Whether bulk deletes fail, so messages are deleted one by one
+        bulk_deletes_fail: bool,
+    }
+
+    impl Recording {
+        pub fn new(pages: Vec<Vec<serenity::Message>>) -> Self {
+            Self { pages: Mutex::new(pages.into()), ..Default::default() }
+        }
+        pub fn undeletable(mut self, message_ids: impl IntoIterator<Item = serenity::MessageId>) -> Self {
+            self.undeletable.extend(message_ids);
+            self
+        }
+        pub fn bulk_deletes_fail(mut self) -> Self {
+            self.bulk_deletes_fail = true;
+            self
+        }
+        pub fn requests(&self) -> Vec<Request> {
+            self.requests.lock().unwrap().clone()
+        }
+        pub fn fetched_after(&self) -> Vec<Option<serenity::MessageId>> {
+            self.fetched_after.lock().unwrap().clone()
+        }
+        /// Every message deleted, alone or in bulk
+        pub fn deleted(&self) -> Vec<serenity::MessageId> {
+            let mut deleted = Vec::new();
+            for request in self.requests() {
+                match request {
+                    Request::DeleteMessage(message_id) => deleted.push(message_id),
+                    Request::DeleteMessages(message_ids) => deleted.extend(message_ids),
+                    _ => {}
+                }
+            }
+            deleted
+        }
+        fn record(&self, request: Request) {
+            self.requests.lock().unwrap().push(request);
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Gateway for Recording {
+        async fn messages(&self, _channel_id: serenity::ChannelId, after: Option<serenity::MessageId>) -> serenity::Result<Vec<serenity::Message>> {
+            self.fetched_after.lock().unwrap().push(after);
+            Ok(self.pages.lock().unwrap().pop_front().unwrap_or_default())
+        }
+        async fn delete_message(&self, _channel_id: serenity::ChannelId, message_id: serenity::MessageId) -> serenity::Result<()> {
+            if self.undeletable.contains(&message_id) {
+                return Err(serenity::Error::Other("Missing Permissions"));
+            }
+            self.record(Request::DeleteMessage(message_id));
+            Ok(())
+        }
+        async fn delete_messages(&self, _channel_id: serenity::ChannelId, message_ids: &[serenity::MessageId]) -> serenity::Result<()> {
+            if self.bulk_deletes_fail || message_ids.iter().any(|message_id| self.undeletable.contains(message_id)) {
+                return Err(serenity::Error::Other("Missing Permissions"));
+            }
+            self.record(Request::DeleteMessages(message_ids.to_vec()));
+            Ok(())
+        }
+        async fn delete_channel(&self, channel_id: serenity::ChannelId) -> serenity::Result<()> {
+            self.record(Request::DeleteChannel(channel_id));
+            Ok(())
+        }
+        async fn send_message(&self, channel_id: serenity::ChannelId, message: serenity::CreateMessage) -> serenity::Result<serenity::Message> {
+            let sent = serde_json::to_value(&message)?;
+            let content = sent["content"].as_str().map(str::to_owned);
+            self.record(Request::SendMessage(channel_id, content.clone()));
+            Ok(self::message(serenity::MessageId::new(1), channel_id, serenity::UserId::new(1), &content.unwrap_or_default()))
+        }
+        async fn create_reaction(&self, _channel_id: serenity::ChannelId, message_id: serenity::MessageId, _reaction: serenity::ReactionType) -> serenity::Result<()> {
+            self.record(Request::CreateReaction(message_id));
+            Ok(())
+        }
+        async fn download(&self, _url: &str) -> serenity::Result<serenity::CreateAttachment> {
+            Err(serenity::Error::Other("Nothing to download from a fake"))
+        }
+    }
+
+    /// Milliseconds from the Unix epoch to the start of 2015, where Discord's IDs start counting
+    const DISCORD_EPOCH: i64 = 1_420_070_400_000;
+
+    /// The ID of a message sent at `sent_at`, which must be unique among the messages of a test
+    pub fn message_id(sent_at: DateTime<Utc>) -> serenity::MessageId {
+        serenity::MessageId::new(((sent_at.timestamp_millis() - DISCORD_EPOCH) as u64) << 22)
+    }
+
+    /// A plain text message, sent when its ID says
+    pub fn message(id: serenity::MessageId, channel_id: serenity::ChannelId, author: serenity::UserId, content: &str) -> serenity::Message {
+        serde_json::from_value(serde_json::json!({
+            "id": id.to_string(),
+            "channel_id": channel_id.to_string(),
+            "author": { "id": author.to_string(), "username": format!("user{}", author), "discriminator": "0", "avatar": null },
+            "content": content,
+            "timestamp": id.created_at(),
+            "edited_timestamp": null,
+            "tts": false,
+            "mention_everyone": false,
+            "mentions": [],
+            "mention_roles": [],
+            "attachments": [],
+            "embeds": [],
+            "pinned": false,
+            "type": 0,
+        }))
+        .expect("The message is valid")
+    }
+}
@@ -0,0 +1,98 @@
+use crate::config::HeartbeatConfig;
+use chrono::Utc;
+use poise::serenity_prelude as serenity;
+use std::{
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
+use tokio::time::MissedTickBehavior;
+
+/// How long the health check gets to answer
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// How many of the channel's latest messages are looked through for the last status message
+const STATUS_SEARCH_LIMIT: u8 = 50;
+
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .user_agent(concat!("set/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .expect("The heartbeat client has a valid configuration")
+});
+
+/// Edits a status message and pings a health check every `interval`, as long as the gateway is connected
+///
+/// Nothing is sent while any shard is disconnected, so a status message that stops updating, or a
+/// health check that stops being pinged, means the bot died or lost the gateway.
+pub async fn beat(ctx: serenity::Context, shard_manager: Arc<serenity::ShardManager>, config: HeartbeatConfig) {
+    let mut status_message = None;
+    let mut interval = tokio::time::interval(config.interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let Some(latency) = gateway_latency(&shard_manager).await else {
+            log::warn!("Skipping a heartbeat, since the gateway is not connected");
+            continue;
+        };
+        if let Some(channel_id) = config.channel {
+            if let Err(error) = update_status(&ctx, channel_id, &mut status_message, latency).await {
+                log::error!("Failed to update the status message in {}: {:?}", channel_id, error);
+            }
+        }
+        if let Some(url) = &config.url {
+            if let Err(error) = ping(url).await {
+                log::warn!("Failed to ping the health check: {}", error);
+            }
+        }
+    }
+}
+
+/// The highest latency of the shards, or `None` unless all of them are connected
+///
+/// The latency is unknown until the first heartbeat was acknowledged.
+pub async fn gateway_latency(shard_manager: &serenity::ShardManager) -> Option<Option<Duration>> {
+    let runners = shard_manager.runners.lock().await;
+    let connected = !runners.is_empty() && runners.values().all(|runner| runner.stage == serenity::ConnectionStage::Connected);
+    connected.then(|| runners.values().filter_map(|runner| runner.latency).max())
+}
+
+fn status(latency: Option<Duration>) -> String {
+    let now = Utc::now().timestamp();
+    let latency = match latency {
+        Some(latency) => format!("{} ms", latency.as_millis()),
+        None => "unknown".to_owned(),
+    };
+    format!("💓 Alive as of <t:{now}:R> (<t:{now}:f>), gateway latency {latency}")
+}
+
+/// Edits the bot's status message in `channel_id`, or posts one if there is none
+///
+/// After a restart, the last status message the bot posted is picked up again instead of posting another one.
+async fn update_status(
+    ctx: &serenity::Context,
+    channel_id: serenity::ChannelId,
+    status_message: &mut Option<serenity::MessageId>,
+    latency: Option<Duration>,
+) -> Result<(), serenity::Error> {
+    let content = status(latency);
+    if status_message.is_none() {
+        let bot_id = ctx.cache.current_user().id;
+        let messages = channel_id.messages(ctx, serenity::GetMessages::new().limit(STATUS_SEARCH_LIMIT)).await?;
+        *status_message = messages.iter().find(|message| message.author.id == bot_id).map(|message| message.id);
+    }
+    if let Some(message_id) = *status_message {
+        match channel_id.edit_message(ctx, message_id, serenity::EditMessage::new().content(&content)).await {
+            Ok(_) => return Ok(()),
+            // Deleted in the meantime, so a new one is posted
+            Err(error) => log::debug!("Failed to edit the status message {}: {:?}", message_id, error),
+        }
+    }
+    let message = channel_id.send_message(ctx, serenity::CreateMessage::new().content(content)).await?;
+    *status_message = Some(message.id);
+    Ok(())
+}
+
+async fn ping(url: &str) -> reqwest::Result<()> {
+    CLIENT.get(url).send().await?.error_for_status()?;
+    Ok(())
+}
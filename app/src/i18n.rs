@@ -0,0 +1,97 @@
+use crate::{config, Context, Data, Error};
+use set_core::dedup::Relation;
+use std::{collections::HashMap, fmt, sync::LazyLock};
+
+/// Locale whose templates are used for anything the others don't translate
+pub const DEFAULT_LOCALE: &str = "en-US";
+
+/// Templates of every locale by Discord's name for it, each by its dotted key
+static LOCALES: LazyLock<HashMap<&str, HashMap<String, String>>> = LazyLock::new(|| {
+    [
+        ("en-US", include_str!("../locales/en-US.toml")),
+        ("de", include_str!("../locales/de.toml")),
+    ]
+    .into_iter()
+    .map(|(locale, file)| {
+        let table: toml::Table = file.parse().unwrap_or_else(|e| panic!("Failed to parse the {} locale: {}", locale, e));
+        let templates = config::flatten(&table)
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value.as_str()?.to_owned())))
+            .collect();
+        (locale, templates)
+    })
+    .collect()
+});
+
+/// Whether there are templates for `locale`
+pub fn is_supported(locale: &str) -> bool {
+    LOCALES.contains_key(locale)
+}
+
+/// The template of `key` in `locale`, with every `{name}` replaced by the value of `name`
+///
+/// Falls back to the default locale, and to the key itself if that misses it too.
+pub fn tr(locale: &str, key: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+    let template = [locale, DEFAULT_LOCALE]
+        .iter()
+        .find_map(|locale| LOCALES.get(locale)?.get(key));
+    let Some(template) = template else {
+        log::warn!("Missing translation for {}", key);
+        return key.to_owned();
+    };
+    args.iter().fold(template.clone(), |text, (name, value)| text.replace(&format!("{{{}}}", name), &value.to_string()))
+}
+
+/// How an entry relates to the one it duplicates, e.g. "an anagram of"
+pub fn relation(locale: &str, relation: Relation) -> String {
+    let key = match relation {
+        Relation::Anagram => "relations.anagram",
+        Relation::Substring => "relations.substring",
+        Relation::Superstring => "relations.superstring",
+    };
+    tr(locale, key, &[])
+}
+
+/// The locale to answer a command in: the user's for slash commands, the configured one otherwise
+pub fn locale(ctx: Context<'_>) -> String {
+    user_locale(ctx.data(), ctx.locale())
+}
+
+/// The locale to answer a user in: theirs if there are translations for it, the configured one
+/// otherwise
+pub fn user_locale(data: &Data, locale: Option<&str>) -> String {
+    match locale {
+        Some(locale) if is_supported(locale) => locale.to_owned(),
+        _ => data.config().i18n.locale.clone(),
+    }
+}
+
+/// Adds the translated names and descriptions of every locale to the slash commands, so Discord
+/// shows them in the user's language
+pub fn localize_commands(commands: &mut [poise::Command<Data, Error>]) {
+    for command in commands {
+        localize_command(command, &format!("commands.{}", command.name));
+    }
+}
+
+fn localize_command(command: &mut poise::Command<Data, Error>, prefix: &str) {
+    for (locale, templates) in LOCALES.iter() {
+        if let Some(name) = templates.get(&format!("{}.name", prefix)) {
+            command.name_localizations.insert(locale.to_string(), name.clone());
+        }
+        if let Some(description) = templates.get(&format!("{}.description", prefix)) {
+            command.description_localizations.insert(locale.to_string(), description.clone());
+        }
+        for parameter in &mut command.parameters {
+            if let Some(name) = templates.get(&format!("{}.{}.name", prefix, parameter.name)) {
+                parameter.name_localizations.insert(locale.to_string(), name.clone());
+            }
+            if let Some(description) = templates.get(&format!("{}.{}.description", prefix, parameter.name)) {
+                parameter.description_localizations.insert(locale.to_string(), description.clone());
+            }
+        }
+    }
+    for subcommand in &mut command.subcommands {
+        localize_command(subcommand, &format!("{}.{}", prefix, subcommand.name));
+    }
+}
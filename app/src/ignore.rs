@@ -0,0 +1,28 @@
+use crate::config::IgnoreConfig;
+use poise::serenity_prelude as serenity;
+
+/// Whether a message is outside the game, so it must neither be cached nor deleted
+pub fn is_ignored(bot_id: serenity::UserId, message: &serenity::Message, config: &IgnoreConfig) -> bool {
+    // The bot's own notices are never entries
+    if message.author.id == bot_id {
+        return true;
+    }
+    if config.bots && message.author.bot {
+        return true;
+    }
+    if config.webhooks && message.webhook_id.is_some() {
+        return true;
+    }
+    if config.replies && message.message_reference.is_some() && message.kind == serenity::MessageType::InlineReply {
+        return true;
+    }
+    is_ignored_content(&message.content, config)
+}
+
+/// Whether a message's content alone makes it ignored, i.e. it matches a prefix or pattern
+pub fn is_ignored_content(content: &str, config: &IgnoreConfig) -> bool {
+    if config.prefixes.iter().any(|prefix| content.starts_with(prefix.as_str())) {
+        return true;
+    }
+    config.compiled_patterns.iter().any(|pattern| pattern.is_match(content))
+}
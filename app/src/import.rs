@@ -0,0 +1,121 @@
+use crate::Error;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use set_core::{cache::ChannelCache, config::NormalizationConfig, dedup::Key, normalize::normalize_string};
+
+/// An entry read from an import file, with when it was first seen if the file says
+struct ImportedEntry {
+    key: Key,
+    first_seen: Option<DateTime<Utc>>,
+}
+
+/// A row of the bot's own export, in either format
+#[derive(Deserialize)]
+struct ExportRow {
+    entry: String,
+    first_seen: Option<DateTime<Utc>>,
+}
+
+/// A message from Discord's data package, whose `messages.json` and `messages.csv` use these names
+#[derive(Deserialize)]
+struct DataPackageMessage {
+    #[serde(rename = "Timestamp")]
+    timestamp: Option<String>,
+    #[serde(rename = "Contents")]
+    contents: String,
+}
+
+/// What an import changed
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub imported: usize,
+    /// Entries that were already cached
+    pub duplicates: usize,
+    /// Lines or messages without any content
+    pub empty: usize,
+}
+
+impl std::fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Imported {} entries, {} were already cached and {} were empty.",
+            self.imported, self.duplicates, self.empty,
+        )
+    }
+}
+
+/// Reads entries from `contents` and adds them to `channel_cache`
+///
+/// Accepted are the bot's own CSV and JSON exports, `messages.csv` or `messages.json` from a
+/// Discord data package, and plain text with one entry per line. Everything is normalized with the
+/// current settings.
+pub fn import(channel_cache: &mut ChannelCache, contents: &str, options: &NormalizationConfig) -> Result<ImportReport, Error> {
+    let mut report = ImportReport::default();
+    let now = Utc::now();
+    for entry in parse(contents, options)? {
+        if matches!(&entry.key, Key::Text(text) if text.is_empty()) {
+            report.empty += 1;
+            continue;
+        }
+        let first_seen = entry.first_seen.unwrap_or(now);
+        match channel_cache.cache.get_mut(&entry.key.to_entry()) {
+            Some(existing) => {
+                report.duplicates += 1;
+                existing.first_seen = existing.first_seen.min(first_seen);
+            }
+            None => {
+                channel_cache.insert_keys(&[entry.key], first_seen, None, None);
+                report.imported += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+fn parse(contents: &str, options: &NormalizationConfig) -> Result<Vec<ImportedEntry>, Error> {
+    let text = |contents: &str| Key::Text(normalize_string(contents, options));
+    let exported = |row: ExportRow| ImportedEntry {
+        key: Key::from_entry(&row.entry).renormalize(options),
+        first_seen: row.first_seen,
+    };
+    let from_data_package = |message: DataPackageMessage| ImportedEntry {
+        key: text(&message.contents),
+        first_seen: message.timestamp.as_deref().and_then(parse_data_package_timestamp),
+    };
+    let first_line = contents.lines().next().unwrap_or_default().trim();
+    if first_line.starts_with('[') {
+        let rows: Vec<serde_json::Value> = serde_json::from_str(contents)?;
+        return rows
+            .into_iter()
+            .map(|row| match row.get("Contents") {
+                Some(_) => Ok(from_data_package(serde_json::from_value(row)?)),
+                None => Ok(exported(serde_json::from_value(row)?)),
+            })
+            .collect();
+    }
+    if first_line == "channel_id,kind,entry,first_seen" {
+        return csv_rows(contents)?.into_iter().map(|row| Ok(exported(row))).collect();
+    }
+    if first_line.starts_with("ID,Timestamp,Contents") {
+        return csv_rows(contents)?.into_iter().map(|message| Ok(from_data_package(message))).collect();
+    }
+    Ok(contents
+        .lines()
+        .map(|line| ImportedEntry { key: text(line), first_seen: None })
+        .collect())
+}
+
+fn csv_rows<T: for<'de> Deserialize<'de>>(contents: &str) -> Result<Vec<T>, Error> {
+    Ok(csv::Reader::from_reader(contents.as_bytes())
+        .deserialize()
+        .collect::<Result<_, _>>()?)
+}
+
+/// Data packages write timestamps like `2021-03-04 05:06:07.123000+00:00`
+fn parse_data_package_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S%.f%:z")
+        .or_else(|_| DateTime::parse_from_rfc3339(timestamp))
+        .ok()
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+}
@@ -0,0 +1,90 @@
+use crate::config::Config;
+use set_core::{config::NormalizationConfig, dedup::Key, normalize::{canonicalize_link, normalize_string}};
+use image_hasher::HasherConfig;
+use poise::serenity_prelude as serenity;
+use sha2::{Digest, Sha256};
+
+/// Computes every key a message is deduplicated by in the channel whose cache is `scope`
+pub async fn message_keys(message: &serenity::Message, config: &Config, scope: serenity::ChannelId) -> Vec<Key> {
+    let mut keys: Vec<Key> = message
+        .sticker_items
+        .iter()
+        .map(|sticker| Key::Sticker(sticker.id.to_string()))
+        .collect();
+    if config.embeds.enabled {
+        keys.extend(embed_keys(&message.embeds, config, scope));
+    }
+    if config.attachments.enabled {
+        for attachment in &message.attachments {
+            keys.extend(attachment_keys(attachment, config).await);
+        }
+    }
+    let texts = text_keys(&message.content, config, &config.normalization_in(scope));
+    // A message that is only a sticker or upload shouldn't collide with every other one on its empty text
+    if texts.is_empty() && keys.is_empty() {
+        keys.push(Key::Text(String::new()));
+    }
+    keys.splice(0..0, texts);
+    keys
+}
+
+/// Computes the keys for a message's text, one per line if lines are split, and none if it is empty
+pub fn text_keys(content: &str, config: &Config, normalization: &NormalizationConfig) -> Vec<Key> {
+    let texts: Vec<&str> = match config.lines.split {
+        true => content.lines().collect(),
+        false => vec![content],
+    };
+    texts
+        .into_iter()
+        .map(|text| normalize_string(text, normalization))
+        .filter(|text| !text.is_empty())
+        .map(Key::Text)
+        .collect()
+}
+
+/// Computes the keys for a message's embeds, which Discord often only adds in a later update
+pub fn embed_keys(embeds: &[serenity::Embed], config: &Config, scope: serenity::ChannelId) -> Vec<Key> {
+    let normalization = config.normalization_in(scope);
+    embeds
+        .iter()
+        .filter_map(|embed| embed_key(embed.url.as_deref(), embed.title.as_deref(), &normalization))
+        .collect()
+}
+
+/// The key for an embed with the given URL and title
+pub fn embed_key(url: Option<&str>, title: Option<&str>, normalization: &NormalizationConfig) -> Option<Key> {
+    match (url, title) {
+        (Some(url), _) => Some(Key::Embed(canonicalize_link(url))),
+        (None, Some(title)) => Some(Key::Embed(normalize_string(title, normalization))),
+        (None, None) => None,
+    }
+}
+
+async fn attachment_keys(attachment: &serenity::Attachment, config: &Config) -> Vec<Key> {
+    if attachment.size as u64 > config.attachments.max_size {
+        log::warn!("Skipping attachment {} of {} bytes, which is over the size limit", attachment.filename, attachment.size);
+        return Vec::new();
+    }
+    let bytes = match attachment.download().await {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            log::warn!("Failed to download attachment {}: {:?}", attachment.filename, error);
+            return Vec::new();
+        }
+    };
+    let mut keys = vec![Key::File(format!("{:x}", Sha256::digest(&bytes)))];
+    let is_image = attachment.content_type.as_deref().is_some_and(|content_type| content_type.starts_with("image/"));
+    if config.attachments.perceptual_hash && is_image {
+        // Decoding and hashing is CPU-bound, keep it off the event loop
+        let hash = tokio::task::spawn_blocking(move || {
+            let image = image::load_from_memory(&bytes).ok()?;
+            Some(HasherConfig::new().to_hasher().hash_image(&image).to_base64())
+        })
+        .await;
+        match hash {
+            Ok(Some(hash)) => keys.push(Key::Image(hash)),
+            _ => log::warn!("Failed to compute a perceptual hash for {}", attachment.filename),
+        }
+    }
+    keys
+}
@@ -0,0 +1,85 @@
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+/// How long samples count towards the percentiles
+const WINDOW: Duration = Duration::from_secs(3600);
+/// Most samples kept of each kind, so a burst doesn't take up memory for the rest of the hour
+const MAX_SAMPLES: usize = 1000;
+
+/// How long Discord's REST API and the bot's own deletion path took lately
+///
+/// Comparing the two tells whether duplicates stay up because Discord is slow to answer or
+/// because the bot is slow to ask.
+#[derive(Default)]
+pub struct Latencies {
+    requests: Mutex<Samples>,
+    deletions: Mutex<Samples>,
+}
+
+#[derive(Default)]
+struct Samples {
+    /// When each sample was taken, how long it took and whether it succeeded, oldest first
+    samples: VecDeque<(Instant, Duration, bool)>,
+}
+
+impl Samples {
+    fn record(&mut self, took: Duration, succeeded: bool) {
+        let now = Instant::now();
+        self.forget_old(now);
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((now, took, succeeded));
+    }
+    fn forget_old(&mut self, now: Instant) {
+        while self.samples.front().is_some_and(|(taken, _, _)| now.duration_since(*taken) >= WINDOW) {
+            self.samples.pop_front();
+        }
+    }
+    fn summary(&mut self) -> Option<Summary> {
+        self.forget_old(Instant::now());
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut durations: Vec<_> = self.samples.iter().map(|(_, took, _)| *took).collect();
+        durations.sort_unstable();
+        let percentile = |p: usize| durations[(durations.len() - 1) * p / 100];
+        Some(Summary {
+            count: durations.len(),
+            errors: self.samples.iter().filter(|(_, _, succeeded)| !succeeded).count(),
+            p50_ms: percentile(50).as_millis() as u64,
+            p95_ms: percentile(95).as_millis() as u64,
+        })
+    }
+}
+
+/// Latencies of the last hour, or of the last samples if there were more
+#[derive(Serialize, Debug, Clone)]
+pub struct Summary {
+    pub count: usize,
+    /// How many failed
+    pub errors: usize,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+}
+
+impl Latencies {
+    /// Records a request to Discord's REST API
+    pub async fn record_request(&self, took: Duration, succeeded: bool) {
+        self.requests.lock().await.record(took, succeeded);
+    }
+    /// Records the time from receiving a duplicate until Discord confirmed deleting it
+    pub async fn record_deletion(&self, took: Duration, succeeded: bool) {
+        self.deletions.lock().await.record(took, succeeded);
+    }
+    pub async fn requests(&self) -> Option<Summary> {
+        self.requests.lock().await.summary()
+    }
+    pub async fn deletions(&self) -> Option<Summary> {
+        self.deletions.lock().await.summary()
+    }
+}
@@ -0,0 +1,22 @@
+use crate::{config::LengthConfig, i18n::tr};
+
+/// Why a message's content breaks the length rules, if it does
+///
+/// Messages without text, e.g. only an attachment, are exempt.
+pub fn violation(locale: &str, content: &str, config: &LengthConfig) -> Option<String> {
+    let content = content.trim();
+    if content.is_empty() {
+        return None;
+    }
+    if config.single_word && content.split_whitespace().nth(1).is_some() {
+        return Some(tr(locale, "rules.single_word", &[]));
+    }
+    let length = content.chars().count();
+    if length < config.min_length {
+        return Some(tr(locale, "rules.too_short", &[("min", &config.min_length)]));
+    }
+    match config.max_length {
+        Some(max_length) if length > max_length => Some(tr(locale, "rules.too_long", &[("max", &max_length)])),
+        _ => None,
+    }
+}
@@ -1,51 +1,153 @@
 #![warn(clippy::str_to_string)]
 
+#[cfg(feature = "api")]
+mod api;
+#[cfg(feature = "s3")]
+mod backup;
+mod appeals;
+mod bench;
+mod cache;
+mod cards;
+mod cli;
 mod commands;
+mod config;
+mod connection;
+mod cooldown;
+mod deletion;
+mod dictionary;
+mod events;
+mod export;
+mod feed;
+mod gateway;
+mod heartbeat;
+mod i18n;
+mod ignore;
+mod import;
+mod keys;
+mod latency;
+mod length;
+mod permissions;
+mod preflight;
+mod quarantine;
+mod reload;
+mod replay;
+mod response;
+mod rules;
+#[cfg(feature = "scripting")]
+mod script;
+mod store;
+mod summary;
+mod supervisor;
+#[cfg(feature = "systemd")]
+mod systemd;
+#[cfg(feature = "otel")]
+mod telemetry;
+mod theme;
+mod throttle;
+mod webhook;
 
 use poise::serenity_prelude as serenity;
 use std::{
     collections::{HashMap, HashSet},
     env,
-    sync::{Arc, atomic},
+    ops::Range,
+    sync::{Arc, OnceLock, atomic},
     time::Duration,
     fs,
     path,
 };
-use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
+use cache::MessagesCache;
 
 // Types used by all command functions
 type Error = Box<dyn std::error::Error + Send + Sync>;
 type Context<'a> = poise::Context<'a, Data, Error>;
 
-#[derive(Serialize, Deserialize)]
-struct MessagesCache {
-    cache: HashSet<String>,
-    last_message_id: Option<serenity::MessageId>,
-}
-impl MessagesCache {
-    fn new() -> Self {
-        Self {
-            cache: HashSet::new(),
-            last_message_id: None,
-        }
-    }
-    fn from_file(data_file: fs::File) -> Self {
-        // TODO: refactor
-        let data: MessagesCache = serde_json::from_reader(data_file).expect("Failed to deserialize data file");
-        data
-    }
-    fn to_file(data_file: fs::File) -> Self {
-        // TODO: refactor
-        unimplemented!("Implement saving to file")
-    }
-}
-
 // Custom user data passed to all command functions
 pub struct Data {
+    /// Replaced as a whole when settings change while the bot runs
+    config: Arc<std::sync::RwLock<Arc<config::Config>>>,
     messages_cache: Arc<Mutex<MessagesCache>>,
     //votes: Mutex<HashMap<String, u32>>,
+    #[allow(dead_code)] // Only used by the batched commit logic, which is currently disabled
     uncommitted_count: atomic::AtomicU32,
+    /// Recent messages whose embeds were already deduplicated
+    embeds_checked: Mutex<HashSet<serenity::MessageId>>,
+    presence_task_started: atomic::AtomicBool,
+    /// Parent channels of threads (`None` for channels that aren't threads), as far as seen
+    thread_parents: Mutex<HashMap<serenity::ChannelId, Option<serenity::ChannelId>>>,
+    deletions: deletion::DeletionQueue,
+    /// Where catch-up fetches messages and duplicates are deleted and replied to
+    gateway: Arc<dyn gateway::Gateway>,
+    /// Held while catching up, so catch-ups never overlap
+    catching_up: Mutex<()>,
+    /// Whether the watched channel turned out to be a forum
+    watching_forum: atomic::AtomicBool,
+    appeals: appeals::Appeals,
+    /// Recent deletions of each user's duplicates
+    deletion_tracker: cooldown::DeletionTracker,
+    /// Budget of API requests shared by message fetches and deletions
+    throttle: Arc<throttle::Throttle>,
+    /// When the bot was started, for `/uptime`
+    started_at: std::time::Instant,
+    /// Accepted messages and deleted duplicates, as streamed by the API
+    feed: Arc<feed::Feed>,
+    /// How long Discord requests and deleting duplicates took lately
+    latencies: Arc<latency::Latencies>,
+    /// How often the gateway connection was lost
+    connection: connection::Connection,
+    /// The bot's own user, whose messages are never entries
+    bot_id: serenity::UserId,
+    /// The guild of the watched channel, once looked up
+    watched_guild: tokio::sync::OnceCell<serenity::GuildId>,
+}
+
+impl Data {
+    /// The config as it is right now. Events and commands hold on to what they got, so a change
+    /// applies from the next one on.
+    fn config(&self) -> Arc<config::Config> {
+        self.config.read().expect("The config lock was poisoned").clone()
+    }
+    fn set_config(&self, config: config::Config) {
+        *self.config.write().expect("The config lock was poisoned") = Arc::new(config);
+    }
+}
+
+#[cfg(test)]
+impl Data {
+    /// Data with an empty cache and `gateway` standing in for Discord. The cache is saved to a
+    /// JSON file in the temporary directory, which all tests share.
+    async fn for_tests(config: config::Config, gateway: Arc<dyn gateway::Gateway>) -> Self {
+        let temp_path = |name: &str| env::temp_dir().join(format!("set-bot-test-{}-{}.json", name, std::process::id()));
+        if STORE.get().is_none() {
+            let store = store::open(store::Backend::Json, temp_path("data"), None, &config.storage).await.expect("Failed to open the test store");
+            // Another test may have opened it in the meantime, which is just as good
+            let _ = STORE.set(store);
+        }
+        let messages_cache = Arc::new(Mutex::new(MessagesCache::default()));
+        let throttle = Arc::new(throttle::Throttle::new(config.rate_limit.requests_per_second));
+        Data {
+            deletions: deletion::DeletionQueue::new(gateway.clone(), messages_cache.clone(), throttle.clone()),
+            gateway,
+            throttle,
+            config: Arc::new(std::sync::RwLock::new(Arc::new(config))),
+            messages_cache,
+            catching_up: Mutex::new(()),
+            uncommitted_count: atomic::AtomicU32::new(0),
+            embeds_checked: Mutex::new(HashSet::new()),
+            presence_task_started: atomic::AtomicBool::new(false),
+            thread_parents: Mutex::new(HashMap::new()),
+            watching_forum: atomic::AtomicBool::new(false),
+            appeals: appeals::Appeals::load_from(temp_path("appeals")),
+            deletion_tracker: cooldown::DeletionTracker::default(),
+            started_at: std::time::Instant::now(),
+            feed: Arc::new(feed::Feed::default()),
+            latencies: Arc::new(latency::Latencies::default()),
+            connection: connection::Connection::default(),
+            bot_id: serenity::UserId::new(1),
+            watched_guild: tokio::sync::OnceCell::new(),
+        }
+    }
 }
 
 async fn on_error(error: poise::FrameworkError<'_, Data, Error>) {
@@ -54,52 +156,570 @@ async fn on_error(error: poise::FrameworkError<'_, Data, Error>) {
     // and forward the rest to the default handler
     match error {
         poise::FrameworkError::Setup { error, .. } => panic!("Failed to start bot: {:?}", error),
+        poise::FrameworkError::CooldownHit { remaining_cooldown, ctx, .. } => {
+            // Rounded up, so users never come back a moment too early
+            let remaining = Duration::from_secs(remaining_cooldown.as_secs() + 1);
+            let notice = i18n::tr(&i18n::locale(ctx), "errors.cooldown", &[("remaining", &humantime::format_duration(remaining))]);
+            let reply = response::reply(response::Tone::Warning, notice);
+            if let Err(e) = ctx.send(reply.ephemeral(true)).await {
+                log::error!("Failed to report the cooldown: {}", e);
+            }
+        }
         poise::FrameworkError::Command { error, ctx, .. } => {
-            println!("Error in command `{}`: {:?}", ctx.command().name, error,);
+            log::error!("Error in command `{}`: {:?}", ctx.command().name, error,);
+            let error = i18n::tr(&i18n::locale(ctx), "errors.command", &[("error", &error)]);
+            let reply = response::reply(response::Tone::Error, error);
+            if let Err(e) = ctx.send(reply.ephemeral(true)).await {
+                log::error!("Failed to report the error: {}", e);
+            }
         }
         error => {
             if let Err(e) = poise::builtins::on_error(error).await {
-                println!("Error while handling error: {}", e)
+                log::error!("Error while handling error: {}", e)
             }
         }
     }
 }
 
+/// Sets the cooldowns of the commands named in `config`
+fn apply_command_cooldowns(commands: &mut [poise::Command<Data, Error>], config: &config::CommandCooldownConfig) {
+    for name in &config.commands {
+        let Some(command) = commands.iter_mut().find(|command| command.name == *name) else {
+            log::warn!("`command_cooldown.commands` names `{}`, which isn't a command", name);
+            continue;
+        };
+        let mut cooldowns = command.cooldown_config.write().expect("The cooldown lock was poisoned");
+        cooldowns.user = Some(config.per_user).filter(|cooldown| !cooldown.is_zero());
+        cooldowns.channel = Some(config.per_channel).filter(|cooldown| !cooldown.is_zero());
+    }
+}
+
+/// Loads `.env` if there is one, without it everything is read from the environment (e.g. in Docker)
+fn load_the_env_file() {
+    match dotenvy::dotenv() {
+        Ok(path) => log::info!("Loaded {}", path.display()),
+        Err(error) if error.not_found() => log::info!("There is no .env file, reading the environment only"),
+        Err(error) => panic!("Failed to load the .env file: {}", error),
+    }
+}
+
+/// Checks every env var the bot reads, returning all problems at once
+fn check_the_environment() -> Vec<String> {
+    [
+        try_get_the_token().err(),
+        try_get_the_channel_id().err(),
+        try_get_the_dev_guild_id().err(),
+        try_get_the_sharding().err(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+fn try_get_the_token() -> Result<String, String> {
+    env::var("DISCORD_TOKEN").map_err(|_| "Missing `DISCORD_TOKEN` env var, see README for more information.".to_owned())
+}
+
 fn get_the_channel_id() -> u64 {
-    env::var("CHANNEL_ID")
-        .expect("Missing `CHANNEL_ID` env var. Set it to the channel ID to listen to.")
+    try_get_the_channel_id().unwrap_or_else(|e| panic!("{}", e))
+}
+
+fn try_get_the_channel_id() -> Result<u64, String> {
+    let channel_id = env::var("CHANNEL_ID").map_err(|_| "Missing `CHANNEL_ID` env var. Set it to the channel ID to listen to.".to_owned())?;
+    channel_id.parse().map_err(|_| format!("Failed to convert `CHANNEL_ID` {} to a u64", channel_id))
+}
+
+/// Guild to register the commands in during development, where they show up immediately rather
+/// than within an hour as when registered globally
+fn get_the_dev_guild_id() -> Option<serenity::GuildId> {
+    try_get_the_dev_guild_id().unwrap_or_else(|e| panic!("{}", e))
+}
+
+fn try_get_the_dev_guild_id() -> Result<Option<serenity::GuildId>, String> {
+    let Ok(dev_guild_id) = env::var("DEV_GUILD_ID") else {
+        return Ok(None);
+    };
+    let dev_guild_id = dev_guild_id
+        .parse()
+        .map_err(|_| format!("Failed to convert `DEV_GUILD_ID` {} to a u64", dev_guild_id))?;
+    Ok(Some(serenity::GuildId::new(dev_guild_id)))
+}
+
+/// Gateway intents the bot connects with
+fn get_the_intents() -> serenity::GatewayIntents {
+    serenity::GatewayIntents::non_privileged() | serenity::GatewayIntents::MESSAGE_CONTENT
+}
+
+/// Which gateway shards this process runs
+enum Sharding {
+    /// One shard, which is enough for bots in up to 2500 guilds
+    Single,
+    /// As many shards as Discord recommends
+    Auto,
+    /// The shards in `range` out of `total`, where other processes may run the rest
+    Range { range: Range<u32>, total: u32 },
+}
+
+impl Sharding {
+    /// Whether this process runs the shard `shard_id`
+    #[cfg_attr(not(feature = "systemd"), allow(dead_code))]
+    fn runs(&self, shard_id: u32) -> bool {
+        match self {
+            Sharding::Single | Sharding::Auto => true,
+            Sharding::Range { range, .. } => range.contains(&shard_id),
+        }
+    }
+}
+
+fn get_the_sharding() -> Sharding {
+    try_get_the_sharding().unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Sharding as given by `SHARD_COUNT` (a number or `auto`) and `SHARDS` (e.g. `0-3`, all of them if unset)
+fn try_get_the_sharding() -> Result<Sharding, String> {
+    let Ok(shard_count) = env::var("SHARD_COUNT") else {
+        return Ok(Sharding::Single);
+    };
+    if shard_count == "auto" {
+        return Ok(Sharding::Auto);
+    }
+    let total: u32 = shard_count
         .parse()
-        .expect(format!("Failed to convert `CHANNEL_ID` {} to a u64", env::var("CHANNEL_ID").unwrap()).as_str())
+        .ok()
+        .filter(|total| *total > 0)
+        .ok_or_else(|| format!("Failed to convert `SHARD_COUNT` {} to a positive number or `auto`", shard_count))?;
+    let Ok(shards) = env::var("SHARDS") else {
+        return Ok(Sharding::Range { range: 0..total, total });
+    };
+    let parse = |id: &str| id.trim().parse::<u32>().map_err(|_| format!("Failed to convert `SHARDS` {} to a range of shard IDs like `0-3`", shards));
+    let (first, last) = match shards.split_once('-') {
+        Some((first, last)) => (parse(first)?, parse(last)?),
+        None => (parse(&shards)?, parse(&shards)?),
+    };
+    if first > last || last >= total {
+        return Err(format!("`SHARDS` {} isn't a range of shard IDs below `SHARD_COUNT` {}", shards, total));
+    }
+    Ok(Sharding::Range { range: first..last + 1, total })
 }
 
+/// Data file path given on the command line, if any
+static DATA_PATH: OnceLock<path::PathBuf> = OnceLock::new();
+/// Data directory given on the command line, if any
+static DATA_DIR: OnceLock<path::PathBuf> = OnceLock::new();
+
+const DATA_FILE: &str = "set-bot-cache.json";
+
+/// The data file, which files such as the appeals are kept next to
+///
+/// With a data directory, this is where the data file was kept before there was one.
 fn get_the_data_path() -> path::PathBuf {
-    let cwd = env::current_dir().expect("Failed to get current directory");
-    let data_path = cwd.join("set-bot-cache.json");
-    data_path
+    DATA_PATH
+        .get_or_init(|| match get_the_data_dir() {
+            Some(data_dir) => data_dir.join(DATA_FILE),
+            None => get_the_default_data_path(),
+        })
+        .clone()
+}
+
+/// Where the data file is kept unless told otherwise
+///
+/// A data file in the working directory, where it used to be kept, is still used. Otherwise it goes
+/// to systemd's `StateDirectory=`, or the XDG data directory, so it doesn't depend on where the bot
+/// was started from.
+fn get_the_default_data_path() -> path::PathBuf {
+    if let Some(data_path) = env::var_os("SET_DATA") {
+        log::warn!("`SET_DATA` is deprecated, use `SET_DATA_PATH` instead");
+        return data_path.into();
+    }
+    let in_cwd = env::current_dir().ok().map(|cwd| cwd.join(DATA_FILE));
+    if let Some(in_cwd) = in_cwd.as_ref().filter(|in_cwd| in_cwd.exists()) {
+        return in_cwd.clone();
+    }
+    let non_empty = |var: &str| env::var_os(var).filter(|value| !value.is_empty()).map(path::PathBuf::from);
+    // systemd separates several state directories with colons, the first one is the bot's
+    let dir = non_empty("STATE_DIRECTORY")
+        .and_then(|dirs| env::split_paths(&dirs).next())
+        .or_else(|| non_empty("XDG_DATA_HOME").map(|data_home| data_home.join("set")))
+        .or_else(|| non_empty("HOME").map(|home| home.join(".local/share/set")));
+    match dir {
+        Some(dir) => {
+            if let Err(error) = fs::create_dir_all(&dir) {
+                panic!("Failed to create the data directory {}: {}, set `SET_DATA_PATH` to keep the data elsewhere", dir.display(), error);
+            }
+            dir.join(DATA_FILE)
+        }
+        None => in_cwd.expect("Failed to get the current directory, set `SET_DATA_PATH` to where the data is kept"),
+    }
+}
+
+fn get_the_data_dir() -> Option<&'static path::Path> {
+    DATA_DIR.get().map(path::PathBuf::as_path)
+}
+
+fn get_the_round_archive_path(channel_id: serenity::ChannelId, round: u32) -> path::PathBuf {
+    get_the_data_path().with_file_name(format!("set-bot-round-{}-{}.json", channel_id, round))
+}
+
+/// The configured storage backend, opened at startup
+static STORE: OnceLock<Box<dyn store::CacheStore>> = OnceLock::new();
+
+fn get_the_store() -> &'static dyn store::CacheStore {
+    STORE.get().expect("The store is opened at startup").as_ref()
+}
+
+#[cfg_attr(feature = "otel", tracing::instrument(skip_all))]
+async fn commit_to_disk(messages_cache: &MessagesCache) -> Result<(), Error> {
+    get_the_store().save(messages_cache).await
+}
+
+/// Copies the stored data (if any) to a timestamped snapshot
+async fn archive_the_data_file() -> Result<Option<path::PathBuf>, Error> {
+    get_the_store().archive().await
+}
+
+/// Reads the cache from the store, for commands that run without connecting to Discord
+async fn load_the_cache() -> Option<MessagesCache> {
+    // Legacy data files belong to the channel the bot watched, which is configured in `.env`
+    dotenvy::dotenv().ok();
+    let watched = serenity::ChannelId::new(get_the_channel_id());
+    get_the_store()
+        .load(watched)
+        .await
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", get_the_store().describe(), e))
+}
+
+/// Announces the highest milestone the current round of `scope` newly reached in `channel_id`, if any
+async fn announce_milestone(
+    ctx: &serenity::Context,
+    channel_id: serenity::ChannelId,
+    scope: serenity::ChannelId,
+    data: &Data,
+) -> Result<(), Error> {
+    let milestone = {
+        let mut messages_cache = data.messages_cache.lock().await;
+        let round = &mut messages_cache.channel(scope).round;
+        let reached = data.config().milestones.counts
+            .iter()
+            .copied()
+            .filter(|&count| count <= round.accepted && count > round.milestone)
+            .max();
+        let Some(reached) = reached else {
+            return Ok(());
+        };
+        round.milestone = reached;
+        reached
+    };
+    log::info!("Reached milestone of {} unique entries", milestone);
+    let announcement = data.config().milestones.message.replace("{count}", &milestone.to_string());
+    let message = channel_id.say(ctx, announcement).await?;
+    if data.config().milestones.pin {
+        message.pin(ctx).await?;
+    }
+    Ok(())
+}
+
+/// Tells the bot's owner about a problem, in the log channel if one is configured or else by DM
+async fn alert_owner(ctx: &serenity::Context, data: &Data, alert: &str) -> Result<(), Error> {
+    alert_owner_via(&ctx.http, data.config().alerts.log_channel, alert).await
+}
+
+/// Tells the bot's owner about a problem in `log_channel`, or by DM without one
+async fn alert_owner_via(http: &serenity::Http, log_channel: Option<serenity::ChannelId>, alert: &str) -> Result<(), Error> {
+    log::info!("Alerting the owner: {}", alert);
+    if let Some(log_channel) = log_channel {
+        log_channel.say(http, alert).await?;
+        return Ok(());
+    }
+    let application_info = http.get_current_application_info().await?;
+    let owner_id = match (application_info.owner, application_info.team) {
+        (_, Some(team)) => team.owner_user_id,
+        (Some(owner), None) => owner.id,
+        (None, None) => return Err("The application has no owner to alert".into()),
+    };
+    owner_id.direct_message(http, serenity::CreateMessage::new().content(alert)).await?;
+    Ok(())
+}
+
+/// Formats a number with thousands separators, e.g. `4,321`
+fn format_count(count: usize) -> String {
+    let digits = count.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// Keeps the bot's activity showing the current number of unique entries
+async fn update_presence(shard_manager: Arc<serenity::ShardManager>, messages_cache: Arc<Mutex<MessagesCache>>, presence: config::PresenceConfig) {
+    let mut interval = tokio::time::interval(presence.interval);
+    // The count last shown, and by how many shards
+    let mut shown = None;
+    loop {
+        interval.tick().await;
+        let count = messages_cache.lock().await.total_entries();
+        // Every shard has a presence of its own
+        let runners = shard_manager.runners.lock().await;
+        if shown == Some((count, runners.len())) {
+            continue;
+        }
+        let activity = serenity::ActivityData::watching(presence.template.replace("{count}", &format_count(count)));
+        for runner in runners.values() {
+            runner.runner_tx.set_activity(Some(activity.clone()));
+        }
+        shown = Some((count, runners.len()));
+    }
 }
 
-fn noramlize_string(msg: &str) -> String {
-    let msg = msg.to_lowercase();
-    use unicode_normalization::UnicodeNormalization;
-    // Apply Unicode Normalization Form C
-    let msg: String = msg.nfc().collect();
-    // Remove all whitespaces, and split into tokens (formerly separated by whitespaces)
-    let tokens: Vec<_> = msg.split_whitespace().collect();
-    tokens.join(" ")
+/// Periodically evicts entries that have outlived their TTL
+async fn sweep_expired_entries(messages_cache: Arc<Mutex<MessagesCache>>, ttl: Duration, sweep_interval: Duration) {
+    let mut interval = tokio::time::interval(sweep_interval);
+    loop {
+        interval.tick().await;
+        let messages_cache = &mut *messages_cache.lock().await;
+        let now = chrono::Utc::now();
+        let evicted: usize = messages_cache.channels
+            .values_mut()
+            .map(|channel_cache| channel_cache.evict_expired(Some(ttl), now))
+            .sum();
+        if evicted == 0 {
+            continue;
+        }
+        log::info!("Evicted {} expired entries, committing messages to disk", evicted);
+        if let Err(error) = commit_to_disk(messages_cache).await {
+            log::error!("Failed to commit messages to disk: {:?}", error);
+        }
+    }
 }
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
-    env_logger::init();
+    let cli = <cli::Cli as clap::Parser>::parse();
+    let mut logger = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn,set=info"));
+    if let Some(log_level) = cli.log_level {
+        logger.filter_module("set", log_level);
+    }
+    logger.init();
+
+    if let Some(data_path) = cli.data {
+        DATA_PATH.set(data_path).expect("Data path was already set");
+    }
+    if let Some(data_dir) = cli.data_dir {
+        if let Err(error) = fs::create_dir_all(&data_dir) {
+            panic!("Failed to create the data directory {}: {}", data_dir.display(), error);
+        }
+        DATA_DIR.set(data_dir).expect("Data directory was already set");
+    }
+    let config_path = cli.config.unwrap_or_else(config::get_the_default_config_path);
+    let command = cli.command.unwrap_or(cli::Command::Run);
+    match command {
+        cli::Command::CheckConfig => return check_config(&config_path).await,
+        cli::Command::Replay { transcript } => {
+            let config = config::Config::load(&config_path);
+            if let Err(error) = replay::replay(&transcript, &config) {
+                log::error!("Failed to replay {}: {}", transcript.display(), error);
+                std::process::exit(1);
+            }
+            return;
+        }
+        cli::Command::Bench { messages } => {
+            let config = config::Config::load(&config_path);
+            if let Err(error) = bench::bench(&config, messages) {
+                log::error!("Failed to benchmark: {}", error);
+                std::process::exit(1);
+            }
+            return;
+        }
+        _ => {}
+    }
+    if matches!(command, cli::Command::Run) {
+        load_the_env_file();
+        let (_, problems) = preflight::check(&config_path).await;
+        if !problems.is_empty() {
+            for problem in &problems {
+                log::error!("{}", problem);
+            }
+            std::process::exit(1);
+        }
+    }
+    let config = config::Config::load(&config_path);
+    if let cli::Command::Migrate { from, to } = command {
+        return migrate(&config, from, to).await;
+    }
+    let store = open_the_store(config.storage.backend, &config).await;
+    if STORE.set(store).is_err() {
+        unreachable!("The store is only opened once");
+    }
+    match command {
+        cli::Command::Run => run(config).await,
+        cli::Command::Compact => compact(&config).await,
+        cli::Command::Export { format, output } => export_cache(format, output.as_deref()).await,
+        cli::Command::Import { path, channel } => import_entries(&config, &path, channel).await,
+        cli::Command::CheckConfig | cli::Command::Replay { .. } | cli::Command::Bench { .. } | cli::Command::Migrate { .. } => unreachable!(),
+    }
+}
+
+async fn open_the_store(backend: store::Backend, config: &config::Config) -> Box<dyn store::CacheStore> {
+    store::open(backend, get_the_data_path(), get_the_data_dir(), &config.storage)
+        .await
+        .unwrap_or_else(|e| panic!("Failed to open the {:?} store: {}", backend, e))
+}
+
+/// Validates the config file, printing the settings it results in
+///
+/// Also checks the env vars, the data file and the token, as done before the bot starts.
+async fn check_config(config_path: &path::Path) {
+    load_the_env_file();
+    match preflight::check(config_path).await {
+        (Some(config), problems) if problems.is_empty() => {
+            println!("{} is valid and results in these settings:\n{:#?}", config_path.display(), config);
+        }
+        (_, problems) => {
+            println!("Found {} problem(s):", problems.len());
+            for problem in problems {
+                println!("- {}", problem);
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Rewrites the stored data in the current format, or copies it to another backend
+///
+/// Both default to the configured backend. Copies are verified by reading them back.
+async fn migrate(config: &config::Config, from: Option<store::Backend>, to: Option<store::Backend>) {
+    // Legacy data files belong to the channel the bot watched, which is configured in `.env`
+    dotenvy::dotenv().ok();
+    let watched = serenity::ChannelId::new(get_the_channel_id());
+    let from = from.unwrap_or(config.storage.backend);
+    let to = to.unwrap_or(from);
+    let source = open_the_store(from, config).await;
+    let messages_cache = source
+        .load(watched)
+        .await
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", source.describe(), e))
+        .unwrap_or_else(|| panic!("There is nothing stored in {}", source.describe()));
+    let target = match to == from {
+        true => source,
+        false => open_the_store(to, config).await,
+    };
+    let archive_path = target.archive().await.expect("Failed to back up the target");
+    target.save(&messages_cache).await.expect("Failed to write the target");
+    if let Some(archive_path) = archive_path {
+        println!("The previous contents of {} were backed up to {}", target.describe(), archive_path.display());
+    }
+    let copy = target
+        .load(watched)
+        .await
+        .unwrap_or_else(|e| panic!("Failed to read back {}: {}", target.describe(), e))
+        .unwrap_or_default();
+    match verify_copy(&messages_cache, &copy) {
+        Ok(summary) => println!("Migrated to {}: {}", target.describe(), summary),
+        Err(error) => {
+            println!("Migrating to {} failed verification: {}", target.describe(), error);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Compares a migrated copy with the original by its counts and a sample of its entries
+fn verify_copy(original: &MessagesCache, copy: &MessagesCache) -> Result<String, String> {
+    use chrono::SubsecRound;
+    /// Roughly how many entries of each channel are compared one by one
+    const SAMPLES: usize = 100;
+    let counts = |cache: &MessagesCache| (cache.channels.len(), cache.total_entries(), cache.last_message_ids.len(), cache.blocklist.len(), cache.banned.len(), cache.dead_letters.len(), cache.card_games.len());
+    if counts(original) != counts(copy) {
+        return Err(format!("expected (channels, entries, last message IDs, blocked users, banned entries, failed deletions, card games) {:?}, found {:?}", counts(original), counts(copy)));
+    }
+    let mut checked = 0;
+    for (channel_id, channel_cache) in &original.channels {
+        let Some(copied) = copy.channels.get(channel_id) else {
+            return Err(format!("channel {} is missing", channel_id));
+        };
+        if channel_cache.cache.len() != copied.cache.len() {
+            return Err(format!("channel {} has {} entries instead of {}", channel_id, copied.cache.len(), channel_cache.cache.len()));
+        }
+        let step = (channel_cache.cache.len() / SAMPLES).max(1);
+        for (entry, metadata) in channel_cache.cache.iter().step_by(step) {
+            // PostgreSQL only keeps timestamps to the microsecond
+            let same_time = |copied: &set_core::cache::Entry| copied.first_seen.trunc_subsecs(6) == metadata.first_seen.trunc_subsecs(6);
+            match copied.cache.get(entry) {
+                Some(copied) if same_time(copied) => checked += 1,
+                Some(_) => return Err(format!("entry {:?} of channel {} has a different first seen time", entry, channel_id)),
+                None => return Err(format!("entry {:?} of channel {} is missing", entry, channel_id)),
+            }
+        }
+    }
+    Ok(format!("{} entries in {} channels, {} of which were spot-checked", copy.total_entries(), copy.channels.len(), checked))
+}
+
+/// Compacts the stored cache with the current config, keeping a backup
+async fn compact(config: &config::Config) {
+    let Some(mut messages_cache) = load_the_cache().await else {
+        panic!("There is nothing stored in {}", get_the_store().describe());
+    };
+    let before = messages_cache.total_entries();
+    let report = messages_cache.compact(config, chrono::Utc::now());
+    let archive_path = archive_the_data_file().await.expect("Failed to back up the data file");
+    commit_to_disk(&messages_cache).await.expect("Failed to write the data file");
+    println!("{}", describe_compaction(before, messages_cache.total_entries(), report));
+    if let Some(archive_path) = archive_path {
+        println!("The previous version was backed up to {}", archive_path.display());
+    }
+}
+
+/// Writes all cached entries to `output`, or stdout
+async fn export_cache(format: export::Format, output: Option<&path::Path>) {
+    use std::io::Write;
+    let Some(messages_cache) = load_the_cache().await else {
+        panic!("There is nothing stored in {}", get_the_store().describe());
+    };
+    let [contents] = &export::export(&messages_cache, format, usize::MAX)[..] else {
+        unreachable!("Without a size limit, everything fits in one file");
+    };
+    let res = match output {
+        Some(output) => fs::write(output, contents),
+        None => std::io::stdout().write_all(contents),
+    };
+    res.expect("Failed to write the export");
+}
 
-    dotenvy::dotenv().expect("Failed to load .env file");
+/// Adds the entries in the file at `import_path` to the cache of `channel_id`, or of the watched channel
+async fn import_entries(config: &config::Config, import_path: &path::Path, channel_id: Option<u64>) {
+    let mut messages_cache = load_the_cache().await.unwrap_or_else(MessagesCache::new);
+    let contents = fs::read_to_string(import_path)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", import_path.display(), e));
+    let scope = channel_id.map_or(serenity::ChannelId::new(get_the_channel_id()), serenity::ChannelId::new);
+    let report = import::import(messages_cache.channel(scope), &contents, &config.normalization_in(scope))
+        .unwrap_or_else(|e| panic!("Failed to import {}: {}", import_path.display(), e));
+    archive_the_data_file().await.expect("Failed to back up the data file");
+    commit_to_disk(&messages_cache).await.expect("Failed to write the data file");
+    println!("{}", report);
+}
+
+/// Summarizes what compacting the cache did
+fn describe_compaction(before: usize, after: usize, report: set_core::cache::CompactionReport) -> String {
+    format!(
+        "Compacted {} entries into {}: {} were re-normalized, {} merged into an existing entry and {} dropped as expired.",
+        before, after, report.renormalized, report.merged, report.expired,
+    )
+}
 
-    let _ = get_the_channel_id();
+/// Connects to Discord and runs the bot until it shuts down
+async fn run(config: config::Config) {
+    let sharding = get_the_sharding();
+
+    let mut commands = vec![commands::help(), commands::check(), commands::purgecache(), commands::removeentry(), commands::checkduplicate(), commands::addtocache(), commands::removefromcache(), commands::block(), commands::unblock(), commands::forget(), commands::newround(), commands::stats(), commands::streak(), commands::streaks(), commands::activity(), commands::recent(), commands::search(), commands::compact(), commands::export(), commands::import(), commands::config(), commands::whitelist(), commands::banned(), commands::theme(), commands::set(), commands::uptime(), commands::about(), commands::shutdown(), commands::restartcatchup(), commands::register()];
+    i18n::localize_commands(&mut commands);
+    apply_command_cooldowns(&mut commands, &config.command_cooldown);
 
     // FrameworkOptions contains all of poise's configuration option in one struct
     // Every option can be omitted to use its default value
     let options = poise::FrameworkOptions {
-        commands: vec![commands::help(), commands::check()],
+        commands,
         prefix_options: poise::PrefixFrameworkOptions {
             edit_tracker: Some(Arc::new(poise::EditTracker::for_timespan(
                 Duration::from_secs(3600),
@@ -112,153 +732,201 @@ async fn main() {
         // This code is run before every command
         pre_command: |ctx| {
             Box::pin(async move {
-                println!("Executing command {}...", ctx.command().qualified_name);
+                log::debug!("Executing command {}...", ctx.command().qualified_name);
             })
         },
         // This code is run after a command if it was successful (returned Ok)
         post_command: |ctx| {
             Box::pin(async move {
-                println!("Executed command {}!", ctx.command().qualified_name);
+                log::debug!("Executed command {}!", ctx.command().qualified_name);
             })
         },
         // Every command invocation must pass this check to continue execution
         command_check: Some(|ctx| {
             Box::pin(async move {
-                if ctx.author().id == 123456789 {
-                    return Ok(false);
+                // Owners can't be locked out, otherwise nobody could unblock them
+                if ctx.framework().options().owners.contains(&ctx.author().id) {
+                    return Ok(true);
                 }
-                Ok(true)
+                let messages_cache = ctx.data().messages_cache.lock().await;
+                Ok(!messages_cache.blocklist.contains(&ctx.author().id))
             })
         }),
         // Enforce command checks even for owners (enforced by default)
         // Set to true to bypass checks, which is useful for testing
         skip_checks_for_owners: false,
-        event_handler: |ctx, event, _framework, data| {
-            Box::pin(async move {
-                match event {
-                    serenity::FullEvent::Ready{data_about_bot: _} => {
-                        let channel_id = get_the_channel_id();
-                        let channel = serenity::ChannelId::new(channel_id).to_channel(ctx).await;
-                        let channel = match channel {
-                            Ok(serenity::Channel::Guild(channel)) => channel,
-                            _ => {
-                                println!("Channel is of the wrong type");
-                                return Err("Channel is of the wrong type".into());
-                            }
-                        };
-                        {
-                            let mut messages_cache = data.messages_cache.lock().await;
-                            let mut last_message_id = messages_cache.last_message_id;
-                            loop {
-                                let query = match last_message_id {
-                                    Some(last_message_id) => serenity::builder::GetMessages::new()
-                                        .after(last_message_id),
-                                    None => serenity::builder::GetMessages::new().limit(100), // INFO: this is technically bugged, since without any specification, messages are ordered by most recent
-                                };
-                                let msgs = channel.messages(ctx, query).await?;
-                                if msgs.is_empty() {
-                                    break;
-                                }
-                                for message in &msgs {
-                                    let msg = noramlize_string(&message.content);
-                                    println!("Catching up on msg from {:?}: {}", message.author_nick(ctx).await, msg);
-                                    let newly_inserted = messages_cache.cache.insert(msg);
-                                    if !newly_inserted {
-                                        println!("Deleting duplicate message");
-                                        let res = message.delete(ctx).await;
-                                        if let Err(error) = res {
-                                            println!("Failed to delete message: {:?}", error);
-                                        }
-                                    }
-                                }
-                                last_message_id = Some(msgs.first().unwrap().id); // messages are returned in reverse order (bottom to top)
-                            }
-                            messages_cache.last_message_id = last_message_id;
-                        }
-                        println!("Committing messages to disk");
-                        {
-                            let messages_cache = data.messages_cache.lock().await;
-                            let file = get_the_data_path();
-                            let file = fs::File::create(file)?;
-                            serde_json::to_writer_pretty(&file, &*messages_cache)?;
-                        }
-                        Ok(())
-                    }
-                    serenity::FullEvent::Message{new_message} => {
-                        if new_message.channel_id != get_the_channel_id() {
-                            println!("Got an event {:?} for channel {:?}, ignoring", event.snake_case_name(), new_message.channel_id);
-                            return Ok(());
-                        }
-                        println!("Handling message from {:?}: {}", new_message.author_nick(ctx).await, new_message.content);
-                        let msg = noramlize_string(&new_message.content);
-                        let newly_inserted = {
-                            let mut messages_cache = data.messages_cache.lock().await;
-                            messages_cache.last_message_id = Some(new_message.id);
-                            messages_cache.cache.insert(msg)
-                        };
-                        if !newly_inserted {
-                            print!("Deleting duplicate message");
-                            let res = new_message.delete(ctx).await;
-                            if let Err(error) = res {
-                                println!("Failed to delete message: {:?}", error);
-                            }
-                        }
-                        //let ct = data.uncommitted_count.fetch_add(1, atomic::Ordering::SeqCst);
-                        //if ct >= 9 {
-                        println!("Committing messages to disk");
-                        {
-                            let messages_cache = data.messages_cache.lock().await;
-                            let file = get_the_data_path();
-                            let file = fs::File::create(file)?;
-                            serde_json::to_writer_pretty(&file, &*messages_cache)?;
-                        }
-                        //    data.uncommitted_count.store(0, atomic::Ordering::SeqCst);
-                        //}
-                        Ok(())
-                    }
-                    _ => {
-                        println!("Got an event: {:?}", event.snake_case_name());
-                        Ok(())
-                    }
-                }
-            })
+        event_handler: |ctx, event, framework, data| {
+            Box::pin(events::event_handler(ctx, event, framework, data))
         },
         ..Default::default()
     };
 
-    let file = get_the_data_path();
-    let file = fs::File::open(file);
-    let messages_cache = match file {
-        Ok(file) => MessagesCache::from_file(file),
-        Err(_) => MessagesCache::new(),
+    let messages_cache = get_the_store()
+        .load(serenity::ChannelId::new(get_the_channel_id()))
+        .await
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", get_the_store().describe(), e))
+        .unwrap_or_else(MessagesCache::new);
+    // Settings changed with `/config set` take precedence over the config file
+    let config = if messages_cache.settings.is_empty() {
+        config
+    } else {
+        config::Config::try_load_with(&config.path, &messages_cache.settings)
+            .unwrap_or_else(|e| panic!("Failed to apply the settings changed with /config set: {}", e))
     };
+    let messages_cache = Arc::new(Mutex::new(messages_cache));
+
+    #[cfg(feature = "s3")]
+    let backup = config.backup.enabled.then(|| {
+        let backup = backup::Backup::open(&config.backup, &config.storage)
+            .unwrap_or_else(|e| panic!("Failed to set up backups to bucket {}: {}", config.backup.bucket, e));
+        Arc::new(backup)
+    });
+    #[cfg(not(feature = "s3"))]
+    if config.backup.enabled {
+        panic!("Backups are enabled, but the bot was built without the `s3` feature");
+    }
+    #[cfg(not(feature = "api"))]
+    if config.api.enabled {
+        panic!("The API is enabled, but the bot was built without the `api` feature");
+    }
+    #[cfg(feature = "otel")]
+    let telemetry = config.telemetry.enabled.then(|| {
+        telemetry::Telemetry::start(&config.telemetry)
+            .unwrap_or_else(|e| panic!("Failed to export traces to {}: {}", config.telemetry.endpoint, e))
+    });
+    #[cfg(not(feature = "otel"))]
+    if config.telemetry.enabled {
+        panic!("Telemetry is enabled, but the bot was built without the `otel` feature");
+    }
 
+    let shared_config = Arc::new(std::sync::RwLock::new(Arc::new(config)));
+    let framework_config = shared_config.clone();
+    let framework_messages_cache = messages_cache.clone();
     let framework = poise::Framework::builder()
         .setup(move |ctx, _ready, framework| {
             Box::pin(async move {
-                println!("Logged in as {}", _ready.user.name);
-                poise::builtins::register_globally(ctx, &framework.options().commands).await?;
+                log::info!("Logged in as {}", _ready.user.name);
+                match get_the_dev_guild_id() {
+                    Some(guild_id) => {
+                        log::info!("Registering the commands in guild {}", guild_id);
+                        poise::builtins::register_in_guild(ctx, &framework.options().commands, guild_id).await?;
+                    }
+                    None => poise::builtins::register_globally(ctx, &framework.options().commands).await?,
+                }
+                let messages_cache = framework_messages_cache;
+                let config = framework_config;
+                let startup_config = config.read().expect("The config lock was poisoned").clone();
+                let reporter = supervisor::Reporter::new(ctx.http.clone(), config.clone());
+                if let Some(ttl) = startup_config.ttl.expire_after {
+                    let (messages_cache, sweep_interval) = (messages_cache.clone(), startup_config.ttl.sweep_interval);
+                    supervisor::spawn("TTL sweep", reporter.clone(), move || sweep_expired_entries(messages_cache.clone(), ttl, sweep_interval));
+                }
+                if startup_config.summary.enabled {
+                    let (ctx, messages_cache, summary) = (ctx.clone(), messages_cache.clone(), startup_config.summary.clone());
+                    supervisor::spawn("summary", reporter.clone(), move || summary::post_summaries(ctx.clone(), messages_cache.clone(), summary.clone()));
+                }
+                if startup_config.heartbeat.enabled {
+                    let (ctx, shard_manager, heartbeat) = (ctx.clone(), framework.shard_manager().clone(), startup_config.heartbeat.clone());
+                    supervisor::spawn("heartbeat", reporter.clone(), move || heartbeat::beat(ctx.clone(), shard_manager.clone(), heartbeat.clone()));
+                }
+                let throttle = Arc::new(throttle::Throttle::new(startup_config.rate_limit.requests_per_second));
+                {
+                    let (config, messages_cache) = (config.clone(), messages_cache.clone());
+                    supervisor::spawn("config watcher", reporter.clone(), move || reload::watch_the_config_file(config.clone(), messages_cache.clone()));
+                }
+                {
+                    let (ctx, config) = (ctx.clone(), config.clone());
+                    supervisor::spawn("theme announcement", reporter.clone(), move || theme::announce(ctx.clone(), config.clone()));
+                }
+                let feed = Arc::new(feed::Feed::default());
+                let latencies = Arc::new(latency::Latencies::default());
+                #[cfg(feature = "api")]
+                if startup_config.api.enabled {
+                    let (config, messages_cache, feed, latencies) = (config.clone(), messages_cache.clone(), feed.clone(), latencies.clone());
+                    let api_reporter = reporter.clone();
+                    supervisor::spawn("API", reporter.clone(), move || {
+                        let (config, messages_cache, feed, latencies, reporter) = (config.clone(), messages_cache.clone(), feed.clone(), latencies.clone(), api_reporter.clone());
+                        async move {
+                            if let Err(error) = api::serve(config, messages_cache, feed, latencies).await {
+                                reporter.alert(&format!("The API stopped: {}", error)).await;
+                            }
+                        }
+                    });
+                }
+                let gateway: Arc<dyn gateway::Gateway> = Arc::new(gateway::Measured::new(ctx.http.clone(), latencies.clone()));
                 Ok(Data {
-                    messages_cache: Arc::new(Mutex::new(messages_cache)),
+                    deletions: deletion::DeletionQueue::new(gateway.clone(), messages_cache.clone(), throttle.clone()),
+                    gateway,
+                    throttle,
+                    config,
+                    messages_cache,
+                    catching_up: Mutex::new(()),
                     //votes: Mutex::new(HashMap::new()),
                     uncommitted_count: atomic::AtomicU32::new(0),
+                    embeds_checked: Mutex::new(HashSet::new()),
+                    presence_task_started: atomic::AtomicBool::new(false),
+                    thread_parents: Mutex::new(HashMap::new()),
+                    watching_forum: atomic::AtomicBool::new(false),
+                    appeals: appeals::Appeals::load(),
+                    deletion_tracker: cooldown::DeletionTracker::default(),
+                    started_at: std::time::Instant::now(),
+                    feed,
+                    latencies,
+                    connection: connection::Connection::default(),
+                    bot_id: _ready.user.id,
+                    watched_guild: tokio::sync::OnceCell::new(),
                 })
             })
         })
         .options(options)
         .build();
 
-    let token = env::var("DISCORD_TOKEN")
-        .expect("Missing `DISCORD_TOKEN` env var, see README for more information.");
-    let intents =
-        serenity::GatewayIntents::non_privileged() | serenity::GatewayIntents::MESSAGE_CONTENT;
-
-    let mut client = serenity::ClientBuilder::new(token, intents)
+    let token = try_get_the_token().unwrap_or_else(|e| panic!("{}", e));
+    let mut client = serenity::ClientBuilder::new(token, get_the_intents())
         .framework(framework)
         .await
         .expect("Error creating client");
 
-    if let Err(why) = client.start().await {
-        println!("An error occurred while running the client: {:?}", why);
+    let shard_manager = client.shard_manager.clone();
+    tokio::spawn(async move {
+        if let Err(error) = tokio::signal::ctrl_c().await {
+            log::error!("Failed to listen for Ctrl+C: {:?}", error);
+            return;
+        }
+        log::info!("Shutting down");
+        shard_manager.shutdown_all().await;
+    });
+    #[cfg(feature = "s3")]
+    if let Some(backup) = &backup {
+        let (backup, messages_cache) = (backup.clone(), messages_cache.clone());
+        let reporter = supervisor::Reporter::new(client.http.clone(), shared_config.clone());
+        supervisor::spawn("backup", reporter, move || backup::back_up_periodically(backup.clone(), messages_cache.clone()));
+    }
+
+    let started = match sharding {
+        Sharding::Single => client.start().await,
+        Sharding::Auto => client.start_autosharded().await,
+        Sharding::Range { range, total } => {
+            // Each process keeps its own copy of the cache, which only stores shared between
+            // instances keep in sync
+            if range != (0..total) && !get_the_store().is_shared() {
+                log::warn!("Running only some of the shards, but the {} store can't be shared with the processes running the others", get_the_store().name());
+            }
+            log::info!("Running shards {}-{} of {}", range.start, range.end - 1, total);
+            client.start_shard_range(range, total).await
+        }
+    };
+    if let Err(why) = started {
+        log::error!("An error occurred while running the client: {:?}", why);
+    }
+    #[cfg(feature = "systemd")]
+    systemd::stopping();
+    #[cfg(feature = "s3")]
+    if let Some(backup) = &backup {
+        backup.back_up(&messages_cache).await;
+    }
+    #[cfg(feature = "otel")]
+    if let Some(telemetry) = telemetry {
+        telemetry.shutdown();
     }
 }
\ No newline at end of file
@@ -0,0 +1,50 @@
+use crate::{Context, Error};
+use poise::serenity_prelude as serenity;
+
+/// Permissions the bot needs in the watched channel
+pub fn required_permissions(channel: &serenity::GuildChannel) -> serenity::Permissions {
+    let mut required = serenity::Permissions::VIEW_CHANNEL
+        | serenity::Permissions::MANAGE_MESSAGES
+        | serenity::Permissions::READ_MESSAGE_HISTORY;
+    if channel.kind == serenity::ChannelType::Forum {
+        // Deleting a forum post with a duplicate title deletes its thread
+        required |= serenity::Permissions::MANAGE_THREADS;
+    }
+    required
+}
+
+/// Permissions the bot needs in the log channel
+pub const LOG_CHANNEL_PERMISSIONS: serenity::Permissions = serenity::Permissions::VIEW_CHANNEL
+    .union(serenity::Permissions::SEND_MESSAGES);
+
+/// The permissions out of `required` that the bot lacks in `channel`
+pub async fn missing_permissions(
+    ctx: &serenity::Context,
+    channel: &serenity::GuildChannel,
+    required: serenity::Permissions,
+) -> Result<serenity::Permissions, Error> {
+    let bot_id = ctx.cache.current_user().id;
+    let guild = channel.guild_id.to_partial_guild(ctx).await?;
+    let member = guild.member(ctx, bot_id).await?;
+    let permissions = guild.user_permissions_in(channel, &member);
+    Ok(required - permissions)
+}
+
+/// Whether the author of a command may use the administrative ones: the bot's owners, members who
+/// can manage the server and members with one of the configured admin roles
+pub async fn is_admin(ctx: Context<'_>) -> Result<bool, Error> {
+    if ctx.framework().options().owners.contains(&ctx.author().id) {
+        return Ok(true);
+    }
+    let Some(member) = ctx.author_member().await else {
+        return Ok(false);
+    };
+    let config = ctx.data().config();
+    let admin_roles = &config.admin.roles;
+    if member.roles.iter().any(|role| admin_roles.contains(role)) {
+        return Ok(true);
+    }
+    // Only members that come with an interaction have their permissions resolved
+    let permissions = member.permissions.or_else(|| ctx.guild().map(|guild| guild.member_permissions(&member)));
+    Ok(permissions.is_some_and(|permissions| permissions.manage_guild()))
+}
@@ -0,0 +1,43 @@
+use crate::{check_the_environment, config::Config, get_the_data_path, get_the_intents, store, try_get_the_token};
+use poise::serenity_prelude as serenity;
+use std::path;
+
+/// Finds everything that would keep the bot from running, before it connects to the gateway
+///
+/// Returns the config, if it loaded, and every problem found, so they can all be fixed at once
+/// rather than one per restart.
+pub async fn check(config_path: &path::Path) -> (Option<Config>, Vec<String>) {
+    let mut problems = Vec::new();
+    let config = Config::try_load(config_path).map_err(|problem| problems.push(problem)).ok();
+    problems.extend(check_the_environment());
+    // The other backends are checked when they are opened
+    if config.as_ref().is_none_or(|config| config.storage.backend == store::Backend::Json) {
+        if let Err(problem) = store::check_file(&get_the_data_path()) {
+            problems.push(problem);
+        }
+    }
+    if let Ok(token) = try_get_the_token() {
+        if serenity::utils::token::validate(&token).is_err() {
+            problems.push("`DISCORD_TOKEN` doesn't look like a bot token, copy it from the Bot page of the developer portal".to_owned());
+        } else if let Err(problem) = check_intents(&serenity::Http::new(&token)).await {
+            problems.push(problem);
+        }
+    }
+    (config, problems)
+}
+
+/// Checks that the bot is allowed to connect with the privileged intents it requests
+pub async fn check_intents(http: &serenity::Http) -> Result<String, String> {
+    let intents = get_the_intents();
+    if !intents.message_content() {
+        return Err("The bot doesn't request the MESSAGE_CONTENT intent, so it can't see what messages say".to_owned());
+    }
+    let application_info = http.get_current_application_info().await
+        .map_err(|e| format!("Failed to get the application info, is `DISCORD_TOKEN` right? {}", e))?;
+    let flags = application_info.flags.unwrap_or_default();
+    // Unverified bots use the limited flag, verified ones the full one
+    if !flags.intersects(serenity::ApplicationFlags::GATEWAY_MESSAGE_CONTENT | serenity::ApplicationFlags::GATEWAY_MESSAGE_CONTENT_LIMITED) {
+        return Err("The MESSAGE_CONTENT intent isn't enabled for the application in the developer portal".to_owned());
+    }
+    Ok("The MESSAGE_CONTENT intent is requested and enabled".to_owned())
+}
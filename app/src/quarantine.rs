@@ -0,0 +1,38 @@
+use crate::{gateway::Gateway, i18n::tr, Error};
+use poise::serenity_prelude::{self as serenity, Mentionable};
+
+/// Forwards a duplicate that is about to be deleted to `quarantine`, with who sent it and why it
+/// is removed, labeled in `locale`. Attachments are uploaded again, since their links stop working
+/// with the message.
+pub async fn forward(
+    gateway: &dyn Gateway,
+    quarantine: serenity::ChannelId,
+    message: &serenity::Message,
+    reason: &str,
+    locale: &str,
+) -> Result<(), Error> {
+    // Messages fetched while catching up come without their author's member, so without a nickname
+    let author_name = message
+        .member
+        .as_ref()
+        .and_then(|member| member.nick.clone())
+        .unwrap_or_else(|| message.author.display_name().to_owned());
+    let embed = serenity::CreateEmbed::new()
+        .author(serenity::CreateEmbedAuthor::new(author_name).icon_url(message.author.face()))
+        .description(&message.content)
+        .field(tr(locale, "quarantine.author", &[]), message.author.mention().to_string(), true)
+        .field(tr(locale, "quarantine.channel", &[]), message.channel_id.mention().to_string(), true)
+        .field(tr(locale, "quarantine.reason", &[]), reason, true)
+        .timestamp(message.timestamp);
+    let mut forwarded = serenity::CreateMessage::new()
+        .embed(embed)
+        .allowed_mentions(serenity::CreateAllowedMentions::new());
+    for attachment in &message.attachments {
+        match gateway.download(&attachment.url).await {
+            Ok(file) => forwarded = forwarded.add_file(file),
+            Err(error) => log::warn!("Failed to download attachment {} to quarantine it: {:?}", attachment.filename, error),
+        }
+    }
+    gateway.send_message(quarantine, forwarded).await?;
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use crate::{cache::MessagesCache, config::{self, Config}};
+use notify::Watcher;
+use std::{
+    collections::BTreeSet,
+    path,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio::sync::{mpsc, Mutex};
+
+/// How long to wait for an editor to finish writing before reading the config file
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+/// Sections that are only read at startup, so changing them takes a restart
+const STARTUP_ONLY: &[&str] = &["storage", "backup", "summary", "heartbeat", "presence", "rate_limit", "ttl", "command_cooldown", "api", "telemetry"];
+
+/// Reloads the config whenever its file changes, with the settings changed by `/config set`
+/// applied over it, and logs what changed
+///
+/// A config that fails to load is logged and ignored, so a typo doesn't take the bot down. The
+/// file's directory is watched rather than the file, since editors tend to replace it on save.
+pub async fn watch_the_config_file(config: Arc<RwLock<Arc<Config>>>, messages_cache: Arc<Mutex<MessagesCache>>) {
+    let config_path = config.read().expect("The config lock was poisoned").path.clone();
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut watcher = match notify::recommended_watcher(move |event| {
+        let _ = sender.send(event);
+    }) {
+        Ok(watcher) => watcher,
+        Err(error) => {
+            log::warn!("Failed to watch the config file, changes need a restart: {}", error);
+            return;
+        }
+    };
+    let directory = match config_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => path::Path::new("."),
+    };
+    if let Err(error) = watcher.watch(directory, notify::RecursiveMode::NonRecursive) {
+        log::warn!("Failed to watch {}, config changes need a restart: {}", directory.display(), error);
+        return;
+    }
+    log::info!("Watching {} for changes", config_path.display());
+
+    while let Some(event) = receiver.recv().await {
+        let touches_config = match event {
+            Ok(event) => !event.kind.is_access() && event.paths.iter().any(|path| path.file_name() == config_path.file_name()),
+            Err(error) => {
+                log::warn!("Error watching the config file: {}", error);
+                false
+            }
+        };
+        if !touches_config {
+            continue;
+        }
+        // Saving tends to come as a burst of events
+        tokio::time::sleep(SETTLE_TIME).await;
+        while receiver.try_recv().is_ok() {}
+
+        let settings = messages_cache.lock().await.settings.clone();
+        let new_config = match Config::try_load_with(&config_path, &settings) {
+            Ok(new_config) => new_config,
+            Err(error) => {
+                log::error!("Not applying the changed config file: {}", error);
+                continue;
+            }
+        };
+        let mut current = config.write().expect("The config lock was poisoned");
+        log_changes(&current.source, &new_config.source);
+        *current = Arc::new(new_config);
+        // The lock can't be held across the wait for the next change
+        drop(current);
+    }
+}
+
+/// Logs every setting that differs between two versions of the config
+fn log_changes(old: &toml::Table, new: &toml::Table) {
+    let old = config::flatten(old);
+    let new = config::flatten(new);
+    let keys: BTreeSet<_> = old.keys().chain(new.keys()).collect();
+    let mut changed = false;
+    for key in keys {
+        match (old.get(key), new.get(key)) {
+            (Some(old_value), Some(new_value)) if old_value == new_value => continue,
+            (Some(old_value), Some(new_value)) => log::info!("Config changed: `{}` = {} (was {})", key, new_value, old_value),
+            (Some(old_value), None) => log::info!("Config changed: `{}` was removed (was {})", key, old_value),
+            (None, Some(new_value)) => log::info!("Config changed: `{}` = {} (was unset)", key, new_value),
+            (None, None) => continue,
+        }
+        changed = true;
+        let section = key.split('.').next().unwrap_or_default();
+        if STARTUP_ONLY.contains(&section) {
+            log::warn!("`{}` only applies after a restart", key);
+        }
+    }
+    if !changed {
+        log::info!("Reloaded the config file, nothing changed");
+    }
+}
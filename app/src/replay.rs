@@ -0,0 +1,129 @@
+use crate::{config::Config, ignore, keys, Error};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use set_core::{
+    cache::ChannelCache,
+    dedup::{self, Key, Verdict},
+};
+use std::{fs, path::Path};
+
+/// A channel export in DiscordChatExporter's JSON format, of which only the parts that affect
+/// deduplication are read
+#[derive(Deserialize)]
+struct Transcript {
+    messages: Vec<TranscriptMessage>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TranscriptMessage {
+    id: String,
+    #[serde(rename = "type", default)]
+    kind: String,
+    timestamp: DateTime<Utc>,
+    #[serde(default)]
+    content: String,
+    author: TranscriptAuthor,
+    #[serde(default)]
+    attachments: Vec<serde_json::Value>,
+    #[serde(default)]
+    embeds: Vec<TranscriptEmbed>,
+    #[serde(default)]
+    stickers: Vec<TranscriptSticker>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TranscriptAuthor {
+    name: String,
+    #[serde(default)]
+    is_bot: bool,
+}
+
+#[derive(Deserialize)]
+struct TranscriptEmbed {
+    url: Option<String>,
+    title: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TranscriptSticker {
+    id: String,
+}
+
+/// Feeds an exported transcript through the dedup engine with the current config, printing the
+/// messages that would have been deleted
+///
+/// Attachments can't be downloaded offline, so they are never deduplicated here.
+pub fn replay(transcript_path: &Path, config: &Config) -> Result<(), Error> {
+    let transcript: Transcript = serde_json::from_reader(fs::File::open(transcript_path)?)?;
+    let rules = config.dedup_rules();
+    let mut channel_cache = ChannelCache::default();
+    let (mut accepted, mut deleted, mut ignored, mut with_attachments) = (0, 0, 0, 0);
+    for message in &transcript.messages {
+        if is_ignored(message, config) {
+            ignored += 1;
+            continue;
+        }
+        if !message.attachments.is_empty() {
+            with_attachments += 1;
+        }
+        let keys = message_keys(message, config);
+        match dedup::check_all(&channel_cache.cache, &keys, &rules, message.timestamp) {
+            Verdict::Unique => {
+                channel_cache.insert_keys(&keys, message.timestamp, None, None);
+                accepted += 1;
+            }
+            verdict => {
+                deleted += 1;
+                let reason = match verdict {
+                    Verdict::NearDuplicate { closest, similarity } => format!("{:.0}% similar to {:?}", similarity * 100.0, closest),
+                    Verdict::Related { closest, relation } => format!("{} {:?}", relation, closest),
+                    _ => "duplicate".to_owned(),
+                };
+                println!(
+                    "Would delete {} [{}] {}: {:?} ({})",
+                    message.id, message.timestamp.format("%Y-%m-%d %H:%M"), message.author.name, message.content, reason,
+                );
+            }
+        }
+    }
+    println!(
+        "{} messages: {} accepted, {} would be deleted, {} ignored. {} unique entries.",
+        transcript.messages.len(), accepted, deleted, ignored, channel_cache.cache.len(),
+    );
+    if config.attachments.enabled && with_attachments > 0 {
+        println!("{} messages had attachments, which were not checked", with_attachments);
+    }
+    Ok(())
+}
+
+/// The ignore rules that can be applied to a transcript
+fn is_ignored(message: &TranscriptMessage, config: &Config) -> bool {
+    let ignore = &config.ignore;
+    if ignore.bots && message.author.is_bot {
+        return true;
+    }
+    if ignore.replies && message.kind == "Reply" {
+        return true;
+    }
+    ignore::is_ignored_content(&message.content, ignore)
+}
+
+/// Mirrors [`keys::message_keys`] without attachments
+fn message_keys(message: &TranscriptMessage, config: &Config) -> Vec<Key> {
+    let mut keys: Vec<Key> = message.stickers.iter().map(|sticker| Key::Sticker(sticker.id.clone())).collect();
+    if config.embeds.enabled {
+        keys.extend(
+            message.embeds
+                .iter()
+                .filter_map(|embed| keys::embed_key(embed.url.as_deref(), embed.title.as_deref(), &config.normalization)),
+        );
+    }
+    let texts = keys::text_keys(&message.content, config, &config.normalization);
+    if texts.is_empty() && keys.is_empty() {
+        keys.push(Key::Text(String::new()));
+    }
+    keys.splice(0..0, texts);
+    keys
+}
@@ -0,0 +1,35 @@
+use poise::serenity_prelude as serenity;
+
+/// How a command turned out, which sets the colour of its response
+#[derive(Debug, Clone, Copy)]
+pub enum Tone {
+    Success,
+    /// Nothing changed or went wrong, there is just something to show
+    Info,
+    /// Nothing went wrong, but the command didn't do what was asked, e.g. an entry wasn't cached
+    Warning,
+    Error,
+}
+
+impl Tone {
+    fn colour(self) -> serenity::Colour {
+        match self {
+            Tone::Success => serenity::Colour::DARK_GREEN,
+            Tone::Info => serenity::Colour::BLURPLE,
+            Tone::Warning => serenity::Colour::GOLD,
+            Tone::Error => serenity::Colour::RED,
+        }
+    }
+}
+
+/// An empty embed in the style of every response, to add a title, description or fields to
+pub fn embed(tone: Tone) -> serenity::CreateEmbed {
+    serenity::CreateEmbed::new()
+        .colour(tone.colour())
+        .footer(serenity::CreateEmbedFooter::new(concat!("set v", env!("CARGO_PKG_VERSION"))))
+}
+
+/// A response that says `description`
+pub fn reply(tone: Tone, description: impl Into<String>) -> poise::CreateReply {
+    poise::CreateReply::default().embed(embed(tone).description(description))
+}
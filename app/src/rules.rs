@@ -0,0 +1,328 @@
+use crate::{
+    config::{Config, DayWindow, DictionaryConfig, LengthConfig, OncePerDayConfig, RuleAction, RuleKind, ThemeConfig, ThemeKind},
+    dictionary,
+    i18n::tr,
+    length,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use poise::serenity_prelude as serenity;
+use set_core::{cache::ChannelCache, config::NormalizationConfig, games::{self, CountError}, normalize::normalize_string};
+use std::borrow::Cow;
+
+/// What a rule decides about a message
+#[derive(Debug, Clone, PartialEq)]
+pub enum Judgement {
+    Accept,
+    /// The message is deleted, for the given reason
+    Reject(String),
+    /// The message is left in place but not accepted, and its author told the given reason
+    Warn(String),
+}
+
+impl Judgement {
+    /// Rejects or warns about a broken rule, as configured for it
+    fn broken(action: RuleAction, reason: String) -> Self {
+        match action {
+            RuleAction::Delete => Judgement::Reject(reason),
+            RuleAction::Warn => Judgement::Warn(reason),
+        }
+    }
+}
+
+/// A message as the rules see it
+pub struct Candidate<'a> {
+    pub content: &'a str,
+    /// The content normalized like entries
+    pub entry: String,
+    pub author: u64,
+    pub sent_at: DateTime<Utc>,
+}
+
+impl<'a> Candidate<'a> {
+    pub fn new(message: &'a serenity::Message, normalization: &NormalizationConfig) -> Self {
+        Self {
+            content: &message.content,
+            entry: normalize_string(&message.content, normalization),
+            author: message.author.id.get(),
+            sent_at: *message.timestamp,
+        }
+    }
+}
+
+/// Something messages must follow to be accepted in a channel
+///
+/// Uniqueness isn't one, since duplicates can be flagged, quarantined, appealed and so on, which
+/// the event handlers take care of. Reasons are given in the configured locale, like the notices
+/// they end up in.
+pub trait Rule {
+    fn validate(&self, candidate: &Candidate, state: &ChannelCache) -> Judgement;
+    /// Updates the channel's state once a message was accepted by every rule
+    fn accept(&self, _candidate: &Candidate, _state: &mut ChannelCache) {}
+}
+
+struct Length<'a> {
+    config: &'a LengthConfig,
+    locale: &'a str,
+}
+
+impl Rule for Length<'_> {
+    fn validate(&self, candidate: &Candidate, _state: &ChannelCache) -> Judgement {
+        match length::violation(self.locale, candidate.content, self.config) {
+            Some(violation) => Judgement::broken(self.config.action, violation),
+            None => Judgement::Accept,
+        }
+    }
+}
+
+struct Dictionary<'a> {
+    config: &'a DictionaryConfig,
+    normalization: &'a NormalizationConfig,
+    locale: &'a str,
+}
+
+impl Rule for Dictionary<'_> {
+    fn validate(&self, candidate: &Candidate, _state: &ChannelCache) -> Judgement {
+        match dictionary::violation(self.locale, candidate.content, self.config, self.normalization) {
+            Some(violation) => Judgement::broken(self.config.action, violation),
+            None => Judgement::Accept,
+        }
+    }
+}
+
+struct Theme<'a> {
+    config: &'a ThemeConfig,
+    normalization: Cow<'a, NormalizationConfig>,
+    locale: &'a str,
+}
+
+impl Rule for Theme<'_> {
+    fn validate(&self, candidate: &Candidate, _state: &ChannelCache) -> Judgement {
+        let Some(value) = &self.config.value else {
+            return Judgement::Accept;
+        };
+        let follows = match (self.config.kind, &self.config.compiled_pattern) {
+            (ThemeKind::Prefix, _) => candidate.entry.starts_with(&normalize_string(value, &self.normalization)),
+            (ThemeKind::Word, _) => {
+                let word = normalize_string(value, &self.normalization);
+                candidate.entry.split_whitespace().any(|entry_word| entry_word == word)
+            }
+            (ThemeKind::Regex, Some(pattern)) => pattern.is_match(candidate.content),
+            // Loading the config compiles the pattern
+            (ThemeKind::Regex, None) => true,
+        };
+        match follows {
+            true => Judgement::Accept,
+            false => Judgement::broken(self.config.action, theme_violation(self.locale, self.config.kind, value)),
+        }
+    }
+}
+
+/// Why a message doesn't follow the theme, which lasts until it's changed rather than a day
+fn theme_violation(locale: &str, kind: ThemeKind, value: &str) -> String {
+    let key = match kind {
+        ThemeKind::Prefix => "rules.theme_prefix",
+        ThemeKind::Word => "rules.theme_word",
+        ThemeKind::Regex => "rules.theme_regex",
+    };
+    tr(locale, key, &[("value", &value)])
+}
+
+struct OncePerDay<'a> {
+    config: &'a OncePerDayConfig,
+    locale: &'a str,
+}
+
+impl OncePerDay<'_> {
+    /// When the author of an entry accepted at `last_accepted_at` can have another one accepted
+    fn next_allowed(&self, last_accepted_at: DateTime<Utc>) -> DateTime<Utc> {
+        match self.config.window {
+            DayWindow::Rolling => last_accepted_at + chrono::Duration::days(1),
+            DayWindow::Calendar => {
+                let timezone = self.config.compiled_timezone.unwrap_or(chrono_tz::UTC);
+                let next_day = last_accepted_at.with_timezone(&timezone).date_naive() + chrono::Days::new(1);
+                // Midnight may be skipped by a DST change, in which case the day starts later
+                let start = (0..24)
+                    .filter_map(|hour| timezone.from_local_datetime(&next_day.and_hms_opt(hour, 0, 0)?).earliest())
+                    .next()
+                    .unwrap_or_else(|| unreachable!("Every day has hours"));
+                start.with_timezone(&Utc)
+            }
+        }
+    }
+}
+
+impl Rule for OncePerDay<'_> {
+    fn validate(&self, candidate: &Candidate, state: &ChannelCache) -> Judgement {
+        let last_accepted_at = state.streaks.get(&candidate.author).and_then(|streak| streak.last_accepted_at);
+        match last_accepted_at.map(|last_accepted_at| self.next_allowed(last_accepted_at)) {
+            Some(next_allowed) if candidate.sent_at < next_allowed => {
+                let next = format!("<t:{}:R>", next_allowed.timestamp());
+                Judgement::broken(self.config.action, tr(self.locale, "rules.once_per_day", &[("next", &next)]))
+            }
+            _ => Judgement::Accept,
+        }
+    }
+}
+
+struct WordChain<'a> {
+    locale: &'a str,
+}
+
+impl Rule for WordChain<'_> {
+    fn validate(&self, candidate: &Candidate, state: &ChannelCache) -> Judgement {
+        match games::check_chain(&state.round, &candidate.entry) {
+            Ok(()) => Judgement::Accept,
+            Err(error) => Judgement::Reject(tr(self.locale, "rules.next_letter", &[("letter", &error.expected)])),
+        }
+    }
+    fn accept(&self, candidate: &Candidate, state: &mut ChannelCache) {
+        if !candidate.entry.is_empty() {
+            games::record_chain(&mut state.round, &candidate.entry);
+        }
+    }
+}
+
+struct Alphabet<'a> {
+    locale: &'a str,
+}
+
+impl Rule for Alphabet<'_> {
+    fn validate(&self, candidate: &Candidate, state: &ChannelCache) -> Judgement {
+        match games::check_alphabet(&state.round, &candidate.entry) {
+            Ok(_) => Judgement::Accept,
+            Err(error) => Judgement::Reject(tr(self.locale, "rules.next_letter", &[("letter", &error.expected)])),
+        }
+    }
+    fn accept(&self, candidate: &Candidate, state: &mut ChannelCache) {
+        if let Ok(letter) = games::check_alphabet(&state.round, &candidate.entry) {
+            games::record_alphabet(&mut state.round, letter);
+        }
+    }
+}
+
+struct Counting<'a> {
+    locale: &'a str,
+}
+
+impl Rule for Counting<'_> {
+    fn validate(&self, candidate: &Candidate, state: &ChannelCache) -> Judgement {
+        let reason = match games::check_count(&state.round, candidate.content, candidate.author) {
+            Ok(_) => return Judgement::Accept,
+            Err(CountError::NotANumber) => tr(self.locale, "rules.not_a_number", &[]),
+            Err(CountError::WrongNumber { expected }) => tr(self.locale, "rules.wrong_number", &[("expected", &expected)]),
+            Err(CountError::SameCounter) => tr(self.locale, "rules.same_counter", &[]),
+        };
+        Judgement::Reject(reason)
+    }
+    fn accept(&self, candidate: &Candidate, state: &mut ChannelCache) {
+        if let Ok(number) = games::check_count(&state.round, candidate.content, candidate.author) {
+            games::record_count(&mut state.round, number, candidate.author);
+        }
+    }
+}
+
+#[cfg(feature = "scripting")]
+struct Script<'a> {
+    ast: &'a rhai::AST,
+    locale: &'a str,
+}
+
+#[cfg(feature = "scripting")]
+impl Rule for Script<'_> {
+    fn validate(&self, candidate: &Candidate, state: &ChannelCache) -> Judgement {
+        crate::script::run(self.ast, candidate, state, self.locale)
+    }
+}
+
+impl RuleKind {
+    /// The rule as followed in the channel whose cache is `scope`, or `None` for uniqueness
+    fn rule(self, config: &Config, scope: serenity::ChannelId) -> Option<Box<dyn Rule + '_>> {
+        let locale = config.i18n.locale.as_str();
+        match self {
+            RuleKind::Unique => None,
+            RuleKind::Length => Some(Box::new(Length { config: &config.length, locale })),
+            RuleKind::Dictionary => Some(Box::new(Dictionary {
+                config: &config.dictionary,
+                normalization: &config.normalization,
+                locale,
+            })),
+            RuleKind::Theme => Some(Box::new(Theme {
+                config: &config.theme,
+                normalization: config.normalization_in(scope),
+                locale,
+            })),
+            RuleKind::OncePerDay => Some(Box::new(OncePerDay { config: &config.once_per_day, locale })),
+            RuleKind::WordChain => Some(Box::new(WordChain { locale })),
+            RuleKind::Alphabet => Some(Box::new(Alphabet { locale })),
+            RuleKind::Counting => Some(Box::new(Counting { locale })),
+            #[cfg(feature = "scripting")]
+            RuleKind::Script => {
+                let channel = config.rules.channels.iter().find(|channel| channel.channel == scope)?;
+                Some(Box::new(Script { ast: channel.compiled_script.as_ref()?, locale }))
+            }
+            // Loading the config makes sure scripts are only used with the feature
+            #[cfg(not(feature = "scripting"))]
+            RuleKind::Script => {
+                let _ = scope;
+                None
+            }
+        }
+    }
+}
+
+/// Judges a message by every rule of `chain` but uniqueness, returning the first judgement that
+/// isn't to accept it
+pub fn judge(chain: &[RuleKind], config: &Config, scope: serenity::ChannelId, candidate: &Candidate, state: &ChannelCache) -> Judgement {
+    chain
+        .iter()
+        .filter_map(|kind| kind.rule(config, scope))
+        .map(|rule| rule.validate(candidate, state))
+        .find(|judgement| *judgement != Judgement::Accept)
+        .unwrap_or(Judgement::Accept)
+}
+
+/// Lets every rule of `chain` record an accepted message
+///
+/// Counting the round's accepted messages is left to the caller.
+pub fn accept(chain: &[RuleKind], config: &Config, scope: serenity::ChannelId, candidate: &Candidate, state: &mut ChannelCache) {
+    for rule in chain.iter().filter_map(|kind| kind.rule(config, scope)) {
+        rule.accept(candidate, state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: &str) -> DateTime<Utc> {
+        timestamp.parse().unwrap()
+    }
+
+    fn next_allowed(window: DayWindow, timezone: Option<chrono_tz::Tz>, last_accepted_at: &str) -> DateTime<Utc> {
+        let config = OncePerDayConfig { window, compiled_timezone: timezone, ..Default::default() };
+        OncePerDay { config: &config, locale: "en-US" }.next_allowed(at(last_accepted_at))
+    }
+
+    #[test]
+    fn rolling_window_waits_a_day() {
+        assert_eq!(next_allowed(DayWindow::Rolling, None, "2026-03-10T15:20:00Z"), at("2026-03-11T15:20:00Z"));
+        // The time zone only matters for calendar days
+        assert_eq!(next_allowed(DayWindow::Rolling, Some(chrono_tz::Europe::Berlin), "2026-03-10T23:30:00Z"), at("2026-03-11T23:30:00Z"));
+    }
+
+    #[test]
+    fn calendar_window_waits_for_midnight_in_the_time_zone() {
+        assert_eq!(next_allowed(DayWindow::Calendar, None, "2026-01-10T22:30:00Z"), at("2026-01-11T00:00:00Z"));
+        // 23:30 in Berlin, so the day is over in half an hour
+        assert_eq!(next_allowed(DayWindow::Calendar, Some(chrono_tz::Europe::Berlin), "2026-01-10T22:30:00Z"), at("2026-01-10T23:00:00Z"));
+        // 0:30 in Berlin, so the whole next day has to pass
+        assert_eq!(next_allowed(DayWindow::Calendar, Some(chrono_tz::Europe::Berlin), "2026-01-10T23:30:00Z"), at("2026-01-11T23:00:00Z"));
+    }
+
+    #[test]
+    fn calendar_window_starts_the_day_after_a_skipped_midnight() {
+        // Chile starts summer time by skipping from 0:00 to 1:00 on 6 September 2026
+        let santiago = Some(chrono_tz::America::Santiago);
+        assert_eq!(next_allowed(DayWindow::Calendar, santiago, "2026-09-05T18:00:00Z"), at("2026-09-06T04:00:00Z"));
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{
+    i18n::tr,
+    rules::{Candidate, Judgement},
+};
+use rhai::{Dynamic, Engine, Scope, AST};
+use set_core::cache::ChannelCache;
+use std::sync::LazyLock;
+
+/// Scripts can't touch files or the network, and these limits keep them from hanging the bot or
+/// eating its memory
+static ENGINE: LazyLock<Engine> = LazyLock::new(|| {
+    let mut engine = Engine::new();
+    engine.set_max_operations(100_000);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(10_000);
+    engine.set_max_array_size(1_000);
+    engine.set_max_map_size(1_000);
+    engine
+});
+
+pub fn compile(source: &str) -> Result<AST, String> {
+    ENGINE.compile(source).map_err(|e| e.to_string())
+}
+
+/// Runs a channel's script on a message
+///
+/// The script sees the message as `content`, `entry` (normalized like entries) and `author` (a
+/// user ID), and the channel's state as `accepted`, `entries`, `count` and `last_entry` (`()` if
+/// there is none). It accepts the message by returning `true` or nothing, and rejects it by
+/// returning `false`, or a map like `#{ accept: false, reply: "why", warn: false }` where `warn`
+/// leaves the message in place. Scripts that fail accept the message, so a broken script doesn't
+/// delete everything.
+pub fn run(ast: &AST, candidate: &Candidate, state: &ChannelCache, locale: &str) -> Judgement {
+    let mut scope = Scope::new();
+    scope
+        .push_constant("content", candidate.content.to_owned())
+        .push_constant("entry", candidate.entry.clone())
+        .push_constant("author", candidate.author.to_string())
+        .push_constant("accepted", state.round.accepted as i64)
+        .push_constant("entries", state.cache.len() as i64)
+        .push_constant("count", state.round.count as i64)
+        .push_constant("last_entry", state.round.last_entry.clone().map_or(Dynamic::UNIT, Dynamic::from));
+    let result = match ENGINE.eval_ast_with_scope::<Dynamic>(&mut scope, ast) {
+        Ok(result) => result,
+        Err(error) => {
+            log::warn!("Rule script failed, accepting the message: {}", error);
+            return Judgement::Accept;
+        }
+    };
+    if result.is_unit() {
+        return Judgement::Accept;
+    }
+    if let Some(accept) = result.clone().try_cast::<bool>() {
+        return match accept {
+            true => Judgement::Accept,
+            false => Judgement::Reject(tr(locale, "rules.broken", &[])),
+        };
+    }
+    let Some(map) = result.try_cast::<rhai::Map>() else {
+        log::warn!("Rule script returned neither a bool nor a map, accepting the message");
+        return Judgement::Accept;
+    };
+    let field = |name: &str| map.get(name).cloned();
+    if field("accept").and_then(|accept| accept.as_bool().ok()).unwrap_or(true) {
+        return Judgement::Accept;
+    }
+    let reason = field("reply")
+        .and_then(|reply| reply.into_string().ok())
+        // Given when a script rejects a message without a reason of its own
+        .unwrap_or_else(|| tr(locale, "rules.broken", &[]));
+    match field("warn").and_then(|warn| warn.as_bool().ok()).unwrap_or(false) {
+        true => Judgement::Warn(reason),
+        false => Judgement::Reject(reason),
+    }
+}
@@ -0,0 +1,154 @@
+use super::{
+    check_file,
+    encryption::Key,
+    json::{open_json, write_json, Compression, JsonStore},
+    CacheStore,
+};
+use crate::{cache::MessagesCache, deletion::DeadLetter, Error};
+use poise::serenity_prelude as serenity;
+use serde::{Deserialize, Serialize};
+use set_core::{cache::ChannelCache, cards::CardGame};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs, path,
+};
+
+/// Version of the manifest's format, raised when it changes incompatibly
+const MANIFEST_VERSION: u32 = 1;
+const MANIFEST_FILE: &str = "manifest.json";
+/// Directory holding a file per channel's cache
+const CHANNELS_DIR: &str = "channels";
+/// The single data file, picked up if the directory has no manifest yet
+const LEGACY_FILE: &str = "set-bot-cache.json";
+
+/// Everything but the channels' caches, and which files those are in
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    /// Files of the channels' caches, relative to the data directory
+    channels: BTreeMap<serenity::ChannelId, String>,
+    last_message_ids: HashMap<serenity::ChannelId, serenity::MessageId>,
+    #[serde(default)]
+    blocklist: HashSet<serenity::UserId>,
+    #[serde(default)]
+    banned: HashSet<String>,
+    #[serde(default)]
+    dead_letters: Vec<DeadLetter>,
+    #[serde(default)]
+    settings: BTreeMap<String, String>,
+    #[serde(default)]
+    card_games: HashMap<serenity::ChannelId, CardGame>,
+}
+
+/// Keeps the cache in a data directory, with a JSON file per channel (or thread) and a manifest
+/// of the rest
+///
+/// The files are compressed and encrypted like the single JSON file. A broken file only loses
+/// its channel, and every channel's state can be told apart on disk.
+pub struct DirectoryStore {
+    dir: path::PathBuf,
+    compression: Compression,
+    /// Encrypts the files if set
+    key: Option<Key>,
+}
+
+impl DirectoryStore {
+    pub fn new(dir: path::PathBuf, compression: Compression, key: Option<Key>) -> Self {
+        Self { dir, compression, key }
+    }
+    fn channel_file(channel_id: serenity::ChannelId) -> String {
+        format!("{}/{}.json", CHANNELS_DIR, channel_id)
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheStore for DirectoryStore {
+    fn name(&self) -> &'static str {
+        "JSON directory"
+    }
+    fn describe(&self) -> String {
+        format!("JSON files in {}", self.dir.display())
+    }
+    async fn load(&self, channel_id: serenity::ChannelId) -> Result<Option<MessagesCache>, Error> {
+        let Some(reader) = open_json(&self.dir.join(MANIFEST_FILE), self.key.as_ref())? else {
+            let legacy_path = self.dir.join(LEGACY_FILE);
+            if legacy_path.exists() {
+                log::info!("Reading {}, it will be split up into a file per channel on the next save", legacy_path.display());
+            }
+            return JsonStore::new(legacy_path, self.compression, self.key.clone()).load(channel_id).await;
+        };
+        let manifest: Manifest = serde_json::from_reader(reader).map_err(|e| format!("Failed to deserialize the manifest: {}", e))?;
+        if manifest.version > MANIFEST_VERSION {
+            return Err(format!("The manifest has version {}, but this build only reads up to {}", manifest.version, MANIFEST_VERSION).into());
+        }
+        let mut channels = HashMap::new();
+        for (channel_id, file) in manifest.channels {
+            let path = self.dir.join(&file);
+            let reader = open_json(&path, self.key.as_ref())?.ok_or_else(|| format!("{} is listed in the manifest, but missing", path.display()))?;
+            let channel_cache: ChannelCache = serde_json::from_reader(reader).map_err(|e| format!("Failed to deserialize {}: {}", path.display(), e))?;
+            channels.insert(channel_id, channel_cache);
+        }
+        Ok(Some(MessagesCache {
+            channels,
+            last_message_ids: manifest.last_message_ids,
+            blocklist: manifest.blocklist,
+            banned: manifest.banned,
+            dead_letters: manifest.dead_letters,
+            settings: manifest.settings,
+            card_games: manifest.card_games,
+        }))
+    }
+    async fn save(&self, messages_cache: &MessagesCache) -> Result<(), Error> {
+        fs::create_dir_all(self.dir.join(CHANNELS_DIR))?;
+        let mut channels = BTreeMap::new();
+        for (channel_id, channel_cache) in &messages_cache.channels {
+            let file = Self::channel_file(*channel_id);
+            write_json(&self.dir.join(&file), channel_cache, self.compression, self.key.as_ref())?;
+            channels.insert(*channel_id, file);
+        }
+        let manifest = Manifest {
+            version: MANIFEST_VERSION,
+            channels,
+            last_message_ids: messages_cache.last_message_ids.clone(),
+            blocklist: messages_cache.blocklist.clone(),
+            banned: messages_cache.banned.clone(),
+            dead_letters: messages_cache.dead_letters.clone(),
+            settings: messages_cache.settings.clone(),
+            card_games: messages_cache.card_games.clone(),
+        };
+        // Written last, so it never lists a channel whose file isn't written yet
+        write_json(&self.dir.join(MANIFEST_FILE), &manifest, self.compression, self.key.as_ref())?;
+        // Files of channels that were purged
+        for file in fs::read_dir(self.dir.join(CHANNELS_DIR))? {
+            let path = file?.path();
+            let listed = path
+                .file_stem()
+                .and_then(|stem| stem.to_str()?.parse().ok())
+                .is_some_and(|channel_id: u64| manifest.channels.contains_key(&serenity::ChannelId::new(channel_id)));
+            if !listed {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+    async fn archive(&self) -> Result<Option<path::PathBuf>, Error> {
+        let manifest_path = self.dir.join(MANIFEST_FILE);
+        if !manifest_path.exists() {
+            return Ok(None);
+        }
+        let timestamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ");
+        let archive_dir = self.dir.join("snapshots").join(timestamp.to_string());
+        fs::create_dir_all(archive_dir.join(CHANNELS_DIR))?;
+        fs::copy(&manifest_path, archive_dir.join(MANIFEST_FILE))?;
+        for file in fs::read_dir(self.dir.join(CHANNELS_DIR))? {
+            let path = file?.path();
+            if let Some(name) = path.file_name() {
+                fs::copy(&path, archive_dir.join(CHANNELS_DIR).join(name))?;
+            }
+        }
+        Ok(Some(archive_dir))
+    }
+    async fn check(&self) -> Result<String, String> {
+        check_file(&self.dir.join(MANIFEST_FILE))
+    }
+}
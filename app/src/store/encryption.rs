@@ -0,0 +1,56 @@
+use crate::Error;
+use chacha20poly1305::{aead::{Aead, AeadCore, KeyInit, OsRng}, ChaCha20Poly1305, Nonce};
+use std::env;
+
+/// Environment variable holding the key the JSON file is encrypted with, as 64 hex digits
+pub const KEY_VAR: &str = "SET_CACHE_KEY";
+
+/// Starts every encrypted file, followed by the nonce and the ciphertext
+pub const MAGIC: &[u8] = b"set-enc1";
+const NONCE_LEN: usize = 12;
+
+/// Encrypts and decrypts files with ChaCha20-Poly1305
+#[derive(Clone)]
+pub struct Key(ChaCha20Poly1305);
+
+impl Key {
+    /// The key in `SET_CACHE_KEY`, or `None` if it isn't set
+    pub fn from_env() -> Result<Option<Self>, Error> {
+        // Like the bot's token, the key belongs in `.env`
+        dotenvy::dotenv().ok();
+        let Ok(hex) = env::var(KEY_VAR) else {
+            return Ok(None);
+        };
+        let bytes = parse_hex(hex.trim()).ok_or_else(|| format!("{} must be 64 hex digits, e.g. from `openssl rand -hex 32`", KEY_VAR))?;
+        let cipher = ChaCha20Poly1305::new_from_slice(&bytes).map_err(|_| format!("{} must be 32 bytes long", KEY_VAR))?;
+        Ok(Some(Self(cipher)))
+    }
+    /// `plaintext` encrypted under a fresh nonce, prefixed by `MAGIC`
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self.0.encrypt(&nonce, plaintext).map_err(|_| "Failed to encrypt the data file")?;
+        Ok([MAGIC, nonce.as_slice(), &ciphertext].concat())
+    }
+    /// Decrypts what `encrypt` returned, failing if it was made with another key or tampered with
+    pub fn decrypt(&self, encrypted: &[u8]) -> Result<Vec<u8>, Error> {
+        let rest = encrypted.strip_prefix(MAGIC).ok_or("The data file isn't encrypted")?;
+        if rest.len() < NONCE_LEN {
+            return Err("The encrypted data file is truncated".into());
+        }
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let plaintext = self.0
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| format!("Failed to decrypt the data file, is {} the key it was written with?", KEY_VAR))?;
+        Ok(plaintext)
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
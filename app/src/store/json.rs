@@ -0,0 +1,159 @@
+use super::{check_file, encryption::{self, Key}, get_the_snapshot_path, CacheStore};
+use crate::{cache::MessagesCache, Error};
+use poise::serenity_prelude as serenity;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{self, BufRead, Read, Write},
+    path,
+};
+
+/// How the JSON file is compressed when written. Reading detects the compression by itself.
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// File extension of JSON compressed like this
+    #[cfg_attr(not(feature = "s3"), allow(dead_code))]
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::None => "json",
+            Compression::Gzip => "json.gz",
+            Compression::Zstd => "json.zst",
+        }
+    }
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Keeps the whole cache in one JSON file, optionally compressed and encrypted
+pub struct JsonStore {
+    path: path::PathBuf,
+    compression: Compression,
+    /// Encrypts the file if set
+    key: Option<Key>,
+}
+
+impl JsonStore {
+    pub fn new(path: path::PathBuf, compression: Compression, key: Option<Key>) -> Self {
+        Self { path, compression, key }
+    }
+}
+
+/// The contents of a JSON file holding `messages_cache`, as `JsonStore` would write them
+pub fn encode(messages_cache: &MessagesCache, compression: Compression, key: Option<&Key>) -> Result<Vec<u8>, Error> {
+    let mut contents = Vec::new();
+    write_compressed(&mut contents, messages_cache, compression)?;
+    match key {
+        Some(key) => key.encrypt(&contents),
+        None => Ok(contents),
+    }
+}
+
+/// Writes `value` to `writer` as JSON with `compression`
+fn write_compressed(writer: &mut impl Write, value: &impl Serialize, compression: Compression) -> Result<(), Error> {
+    match compression {
+        Compression::None => serde_json::to_writer(writer, value)?,
+        Compression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+            serde_json::to_writer(&mut encoder, value)?;
+            encoder.finish()?;
+        }
+        Compression::Zstd => {
+            let mut encoder = zstd::Encoder::new(writer, 0)?;
+            serde_json::to_writer(&mut encoder, value)?;
+            encoder.finish()?;
+        }
+    }
+    Ok(())
+}
+
+/// Undoes the compression of `reader`, whatever it was compressed with
+fn decompressed<'a>(mut reader: impl BufRead + 'a) -> Result<Box<dyn Read + 'a>, Error> {
+    let header = reader.fill_buf()?;
+    Ok(if header.starts_with(ZSTD_MAGIC) {
+        Box::new(zstd::Decoder::with_buffer(reader)?)
+    } else if header.starts_with(GZIP_MAGIC) {
+        Box::new(flate2::bufread::GzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    })
+}
+
+/// Opens the JSON file at `path`, decrypted with `key` and decompressed, or `None` if there is none
+pub(super) fn open_json(path: &path::Path, key: Option<&Key>) -> Result<Option<Box<dyn Read>>, Error> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut reader = io::BufReader::new(file);
+    if !reader.fill_buf()?.starts_with(encryption::MAGIC) {
+        if key.is_some() {
+            log::info!("{} isn't encrypted yet, it will be on the next save", path.display());
+        }
+        return Ok(Some(decompressed(reader)?));
+    }
+    let Some(key) = key else {
+        return Err(format!("{} is encrypted, set {} to the key it was written with", path.display(), encryption::KEY_VAR).into());
+    };
+    let mut encrypted = Vec::new();
+    reader.read_to_end(&mut encrypted)?;
+    let plaintext = key.decrypt(&encrypted)?;
+    Ok(Some(decompressed(io::Cursor::new(plaintext))?))
+}
+
+/// Writes `value` to the JSON file at `path`, compressed with `compression` and encrypted with `key`
+pub(super) fn write_json(path: &path::Path, value: &impl Serialize, compression: Compression, key: Option<&Key>) -> Result<(), Error> {
+    match key {
+        Some(key) => {
+            let mut contents = Vec::new();
+            write_compressed(&mut contents, value, compression)?;
+            fs::write(path, key.encrypt(&contents)?)?;
+        }
+        None => {
+            // Streamed, so the plaintext never has to fit in memory twice
+            let mut writer = io::BufWriter::new(fs::File::create(path)?);
+            write_compressed(&mut writer, value, compression)?;
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl CacheStore for JsonStore {
+    fn name(&self) -> &'static str {
+        "JSON file"
+    }
+    fn describe(&self) -> String {
+        format!("JSON file {}", self.path.display())
+    }
+    async fn load(&self, channel_id: serenity::ChannelId) -> Result<Option<MessagesCache>, Error> {
+        let Some(reader) = open_json(&self.path, self.key.as_ref())? else {
+            return Ok(None);
+        };
+        Ok(Some(MessagesCache::from_reader(reader, channel_id)?))
+    }
+    async fn save(&self, messages_cache: &MessagesCache) -> Result<(), Error> {
+        write_json(&self.path, messages_cache, self.compression, self.key.as_ref())
+    }
+    async fn archive(&self) -> Result<Option<path::PathBuf>, Error> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let archive_path = get_the_snapshot_path(&self.path);
+        fs::copy(&self.path, &archive_path)?;
+        Ok(Some(archive_path))
+    }
+    async fn check(&self) -> Result<String, String> {
+        check_file(&self.path)
+    }
+}
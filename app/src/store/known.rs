@@ -0,0 +1,105 @@
+use crate::{cache::MessagesCache, Error};
+use poise::serenity_prelude as serenity;
+use set_core::cache::{Entry, MessageRef};
+use std::{collections::HashMap, sync::Mutex};
+
+/// Entries as a store last loaded or saved them, so saving only needs to write what changed since
+///
+/// Until anything was loaded or saved, the store's entries are unknown and get replaced as a whole.
+#[derive(Default)]
+pub struct KnownEntries(Mutex<Option<HashMap<serenity::ChannelId, HashMap<String, Entry>>>>);
+
+/// How the entries of a cache differ from the stored ones
+pub struct EntryChanges<'a> {
+    /// What's stored is unknown, so it should all be removed first
+    pub replace_all: bool,
+    /// Channels that were purged or compacted away
+    pub removed_channels: Vec<serenity::ChannelId>,
+    pub removed: Vec<(serenity::ChannelId, String)>,
+    /// Entries that are new or whose metadata changed
+    pub changed: Vec<(serenity::ChannelId, &'a str, &'a Entry)>,
+}
+
+impl KnownEntries {
+    /// How the entries of `messages_cache` differ from the stored ones
+    pub fn changes<'a>(&self, messages_cache: &'a MessagesCache) -> EntryChanges<'a> {
+        let known = self.0.lock().unwrap();
+        let nothing = HashMap::new();
+        let known_channels = known.as_ref().unwrap_or(&nothing);
+        let mut changes = EntryChanges {
+            replace_all: known.is_none(),
+            removed_channels: known_channels
+                .keys()
+                .filter(|channel_id| !messages_cache.channels.contains_key(channel_id))
+                .copied()
+                .collect(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+        };
+        for (channel_id, channel_cache) in &messages_cache.channels {
+            let known_entries = known_channels.get(channel_id);
+            changes.removed.extend(
+                known_entries
+                    .into_iter()
+                    .flatten()
+                    .filter(|(entry, _)| !channel_cache.cache.contains_key(*entry))
+                    .map(|(entry, _)| (*channel_id, entry.clone())),
+            );
+            changes.changed.extend(
+                channel_cache.cache
+                    .iter()
+                    .filter(|(entry, metadata)| known_entries.and_then(|known| known.get(*entry)) != Some(*metadata))
+                    .map(|(entry, metadata)| (*channel_id, entry.as_str(), metadata)),
+            );
+        }
+        changes
+    }
+    /// Records the entries of `messages_cache` as the stored ones
+    pub fn remember(&self, messages_cache: &MessagesCache) {
+        *self.0.lock().unwrap() = Some(
+            messages_cache
+                .channels
+                .iter()
+                .map(|(channel_id, channel_cache)| (*channel_id, channel_cache.cache.clone()))
+                .collect(),
+        );
+    }
+}
+
+/// An entry's metadata as a single value, for the stores that keep entries as key-value pairs
+///
+/// That's the first seen time, then the author, then the message's guild, channel and message
+/// IDs, each left out if unknown. An unknown author is written as `-` if the message is known.
+pub fn entry_value(metadata: &Entry) -> String {
+    let mut value = metadata.first_seen.to_rfc3339();
+    match (metadata.author, metadata.message) {
+        (Some(author), None) => value += &format!(" {}", author),
+        (author, Some(message)) => {
+            let author = author.map_or_else(|| "-".to_owned(), |author| author.to_string());
+            value += &format!(" {} {}:{}:{}", author, message.guild_id, message.channel_id, message.message_id);
+        }
+        (None, None) => {}
+    }
+    value
+}
+
+/// Reads what `entry_value` wrote, also without the message or author that older versions left out
+pub fn parse_entry_value(value: &str) -> Result<Entry, Error> {
+    let mut parts = value.split(' ');
+    let first_seen = parts.next().unwrap_or_default().parse()?;
+    let author = match parts.next() {
+        None | Some("-") => None,
+        Some(author) => Some(author.parse()?),
+    };
+    let message = match parts.next() {
+        Some(message) => {
+            let ids = message.split(':').map(str::parse).collect::<Result<Vec<u64>, _>>()?;
+            let [guild_id, channel_id, message_id] = ids[..] else {
+                return Err(format!("Invalid message {:?} of an entry", message).into());
+            };
+            Some(MessageRef { guild_id, channel_id, message_id })
+        }
+        None => None,
+    };
+    Ok(Entry { first_seen, author, message })
+}
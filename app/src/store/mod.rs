@@ -0,0 +1,147 @@
+use crate::{cache::MessagesCache, config::StorageConfig, Error};
+use poise::serenity_prelude as serenity;
+use serde::Deserialize;
+use std::{fs, path};
+
+mod directory;
+mod encryption;
+mod json;
+#[cfg(any(feature = "redis", feature = "postgres", feature = "sled"))]
+mod known;
+#[cfg(feature = "postgres")]
+mod postgres;
+#[cfg(feature = "redis")]
+mod redis;
+#[cfg(feature = "sled")]
+mod sled;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+#[cfg(feature = "s3")]
+pub use encryption::Key;
+pub use json::{encode as encode_json, Compression};
+
+/// Where the bot persists its cache
+///
+/// Stores are read in full when the bot starts, and messages are looked up in that copy in
+/// memory. Only stores shared between bot instances are still consulted for unique messages,
+/// through `claim()`, so another instance can't take the same entry in the meantime.
+#[async_trait::async_trait]
+pub trait CacheStore: Send + Sync {
+    /// Which backend this is, without where it keeps the data
+    fn name(&self) -> &'static str;
+    /// Where the data is kept, for humans
+    fn describe(&self) -> String;
+    /// Whether several bot instances can use the store at once, keeping each other's changes
+    fn is_shared(&self) -> bool {
+        false
+    }
+    /// Reads the stored cache, or `None` if nothing was stored yet
+    ///
+    /// Data from before threads were supported is migrated into `channel_id`.
+    async fn load(&self, channel_id: serenity::ChannelId) -> Result<Option<MessagesCache>, Error>;
+    /// Replaces the stored cache with `messages_cache`
+    async fn save(&self, messages_cache: &MessagesCache) -> Result<(), Error>;
+    /// Copies the stored data (if any) to a timestamped snapshot, returning where it went
+    async fn archive(&self) -> Result<Option<path::PathBuf>, Error>;
+    /// Whether the store can be read and written, as shown by `/check`
+    async fn check(&self) -> Result<String, String>;
+    /// Atomically reserves `entries` of `scope` for `message_id`, after the message was found to
+    /// be unique in this instance's cache. Returns `false` if another message got any of them first.
+    ///
+    /// Only stores shared between bot instances need this, for the others the cache's lock
+    /// already rules out races. Claims of messages sent before `expired_before` don't count.
+    async fn claim(
+        &self,
+        _scope: serenity::ChannelId,
+        _message_id: serenity::MessageId,
+        _entries: &[String],
+        _expired_before: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<bool, Error> {
+        Ok(true)
+    }
+}
+
+/// The storage backends, as named in the config and on the command line
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// A single JSON file, rewritten on every change and encrypted if `SET_CACHE_KEY` is set
+    #[default]
+    Json,
+    /// An SQLite database
+    Sqlite,
+    /// A Redis server, which several bot instances can share
+    Redis,
+    /// A PostgreSQL database, connected to through `DATABASE_URL`
+    Postgres,
+    /// An embedded sled database
+    Sled,
+}
+
+/// Opens the store of `backend`
+///
+/// `data_path` is where the JSON file is kept, unless there is a `data_dir`, which then holds a
+/// JSON file per channel. Relative paths of the other backends, which are configured in `storage`,
+/// are within `data_dir` if given.
+pub async fn open(backend: Backend, data_path: path::PathBuf, data_dir: Option<&path::Path>, storage: &StorageConfig) -> Result<Box<dyn CacheStore>, Error> {
+    #[cfg_attr(not(any(feature = "sqlite", feature = "sled")), allow(unused_variables))]
+    let within_data_dir = |path: &path::Path| match data_dir {
+        Some(data_dir) => data_dir.join(path),
+        None => path.to_owned(),
+    };
+    match backend {
+        Backend::Json => match data_dir {
+            Some(data_dir) => Ok(Box::new(directory::DirectoryStore::new(data_dir.to_owned(), storage.compression, encryption::Key::from_env()?))),
+            None => Ok(Box::new(json::JsonStore::new(data_path, storage.compression, encryption::Key::from_env()?))),
+        },
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite => Ok(Box::new(sqlite::SqliteStore::open(&within_data_dir(&storage.sqlite_path)).await?)),
+        #[cfg(not(feature = "sqlite"))]
+        Backend::Sqlite => Err("The bot was built without the `sqlite` feature".into()),
+        #[cfg(feature = "redis")]
+        Backend::Redis => Ok(Box::new(redis::RedisStore::open(&storage.redis_url, &storage.redis_prefix).await?)),
+        #[cfg(not(feature = "redis"))]
+        Backend::Redis => Err("The bot was built without the `redis` feature".into()),
+        #[cfg(feature = "postgres")]
+        Backend::Postgres => {
+            // Like the bot's token, the URL (and the password in it) belongs in `.env`
+            dotenvy::dotenv().ok();
+            let url = std::env::var("DATABASE_URL").map_err(|_| "DATABASE_URL must be set to use PostgreSQL")?;
+            Ok(Box::new(postgres::PostgresStore::open(&url, storage.postgres_max_connections).await?))
+        }
+        #[cfg(not(feature = "postgres"))]
+        Backend::Postgres => Err("The bot was built without the `postgres` feature".into()),
+        #[cfg(feature = "sled")]
+        Backend::Sled => Ok(Box::new(sled::SledStore::open(&within_data_dir(&storage.sled_path))?)),
+        #[cfg(not(feature = "sled"))]
+        Backend::Sled => Err("The bot was built without the `sled` feature".into()),
+    }
+}
+
+/// Path of a timestamped snapshot of the file at `path`, next to it
+fn get_the_snapshot_path(path: &path::Path) -> path::PathBuf {
+    let timestamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ");
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(extension) => path.with_file_name(format!("{}.{}.{}", stem, timestamp, extension.to_string_lossy())),
+        None => path.with_file_name(format!("{}.{}", stem, timestamp)),
+    }
+}
+
+/// Checks that the file at `path` can be read and written, or created if there is none yet
+pub fn check_file(path: &path::Path) -> Result<String, String> {
+    match fs::OpenOptions::new().read(true).append(true).open(path) {
+        Ok(_) => Ok(format!("`{}` is readable and writable", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let dir = path.parent().unwrap_or(path);
+            match fs::metadata(dir) {
+                Ok(metadata) if !metadata.permissions().readonly() => Ok(format!("`{}` doesn't exist yet, but can be created", path.display())),
+                Ok(_) => Err(format!("`{}` doesn't exist and its directory is read-only", path.display())),
+                Err(e) => Err(format!("`{}` doesn't exist and its directory can't be accessed: {}", path.display(), e)),
+            }
+        }
+        Err(e) => Err(format!("`{}` can't be opened for reading and writing: {}", path.display(), e)),
+    }
+}
+
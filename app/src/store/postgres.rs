@@ -0,0 +1,239 @@
+use super::{known::KnownEntries, CacheStore};
+use crate::{cache::MessagesCache, Error};
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude as serenity;
+use set_core::cache::{ChannelCache, DayActivity, Entry, MessageRef};
+use sqlx::{postgres, Row};
+use std::{path, str::FromStr};
+
+/// Rows inserted per statement, well below Postgres' limit on bound parameters
+const BATCH_SIZE: usize = 1000;
+
+static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// Keeps the cache in a PostgreSQL database, through a pool of connections
+///
+/// Entries are written as differences from what was last loaded or saved, so saving after every
+/// message stays cheap with large caches. The other tables are small and replaced as a whole.
+pub struct PostgresStore {
+    name: String,
+    pool: postgres::PgPool,
+    known: KnownEntries,
+}
+
+impl PostgresStore {
+    /// Connects to the database at `url`, bringing its schema up to date
+    pub async fn open(url: &str, max_connections: u32) -> Result<Self, Error> {
+        let options = postgres::PgConnectOptions::from_str(url)?;
+        // The URL may hold a password, so only the server and database are shown
+        let name = format!("{}:{}/{}", options.get_host(), options.get_port(), options.get_database().unwrap_or_default());
+        let pool = postgres::PgPoolOptions::new()
+            .max_connections(max_connections)
+            .connect_with(options)
+            .await?;
+        MIGRATOR.run(&pool).await?;
+        Ok(Self {
+            name,
+            pool,
+            known: KnownEntries::default(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheStore for PostgresStore {
+    fn name(&self) -> &'static str {
+        "PostgreSQL"
+    }
+    fn is_shared(&self) -> bool {
+        true
+    }
+    fn describe(&self) -> String {
+        format!("PostgreSQL database {}", self.name)
+    }
+    async fn load(&self, _channel_id: serenity::ChannelId) -> Result<Option<MessagesCache>, Error> {
+        let channels = sqlx::query("SELECT channel_id, round, past_rounds, streaks FROM channels").fetch_all(&self.pool).await?;
+        let last_message_ids = sqlx::query("SELECT channel_id, message_id FROM last_message_ids").fetch_all(&self.pool).await?;
+        if channels.is_empty() && last_message_ids.is_empty() {
+            return Ok(None);
+        }
+        let mut messages_cache = MessagesCache::new();
+        for row in channels {
+            let channel_cache = ChannelCache {
+                cache: Default::default(),
+                round: serde_json::from_str(row.get("round"))?,
+                past_rounds: serde_json::from_str(row.get("past_rounds"))?,
+                streaks: serde_json::from_str(row.get("streaks"))?,
+                activity: Default::default(),
+            };
+            messages_cache.channels.insert(channel_id(&row), channel_cache);
+        }
+        let query = "SELECT channel_id, entry, first_seen, author, message_guild_id, message_channel_id, message_id FROM entries";
+        for row in sqlx::query(query).fetch_all(&self.pool).await? {
+            let first_seen: DateTime<Utc> = row.get("first_seen");
+            let author = row.get::<Option<i64>, _>("author").map(|author| author as u64);
+            let message = match (row.get::<Option<i64>, _>("message_guild_id"), row.get::<Option<i64>, _>("message_channel_id"), row.get::<Option<i64>, _>("message_id")) {
+                (Some(guild_id), Some(channel_id), Some(message_id)) => {
+                    Some(MessageRef { guild_id: guild_id as u64, channel_id: channel_id as u64, message_id: message_id as u64 })
+                }
+                _ => None,
+            };
+            messages_cache.channel(channel_id(&row)).cache.insert(row.get("entry"), Entry { first_seen, author, message });
+        }
+        for row in sqlx::query("SELECT channel_id, day, accepted, deleted FROM activity").fetch_all(&self.pool).await? {
+            let activity = DayActivity { accepted: row.get::<i32, _>("accepted") as u32, deleted: row.get::<i32, _>("deleted") as u32 };
+            messages_cache.channel(channel_id(&row)).activity.insert(row.get("day"), activity);
+        }
+        for row in last_message_ids {
+            let message_id = serenity::MessageId::new(row.get::<i64, _>("message_id") as u64);
+            messages_cache.last_message_ids.insert(channel_id(&row), message_id);
+        }
+        for row in sqlx::query("SELECT user_id FROM blocklist").fetch_all(&self.pool).await? {
+            messages_cache.blocklist.insert(serenity::UserId::new(row.get::<i64, _>("user_id") as u64));
+        }
+        for row in sqlx::query("SELECT entry FROM banned").fetch_all(&self.pool).await? {
+            messages_cache.banned.insert(row.get("entry"));
+        }
+        for row in sqlx::query("SELECT dead_letter FROM dead_letters ORDER BY position").fetch_all(&self.pool).await? {
+            messages_cache.dead_letters.push(serde_json::from_str(row.get("dead_letter"))?);
+        }
+        for row in sqlx::query("SELECT key, value FROM settings").fetch_all(&self.pool).await? {
+            messages_cache.settings.insert(row.get("key"), row.get("value"));
+        }
+        for row in sqlx::query("SELECT channel_id, game FROM card_games").fetch_all(&self.pool).await? {
+            messages_cache.card_games.insert(channel_id(&row), serde_json::from_str(row.get("game"))?);
+        }
+        self.known.remember(&messages_cache);
+        Ok(Some(messages_cache))
+    }
+    async fn save(&self, messages_cache: &MessagesCache) -> Result<(), Error> {
+        let mut channels = Vec::new();
+        let mut activity = Vec::new();
+        for (channel_id, channel_cache) in &messages_cache.channels {
+            channels.push((channel_id.get() as i64, serde_json::to_string(&channel_cache.round)?, serde_json::to_string(&channel_cache.past_rounds)?, serde_json::to_string(&channel_cache.streaks)?));
+            activity.extend(channel_cache.activity.iter().map(|(day, counts)| {
+                (channel_id.get() as i64, *day, counts.accepted as i32, counts.deleted as i32)
+            }));
+        }
+        let changes = self.known.changes(messages_cache);
+        let removed_channels: Vec<_> = changes.removed_channels.iter().map(|channel_id| channel_id.get() as i64).collect();
+        let last_message_ids: Vec<_> = messages_cache.last_message_ids
+            .iter()
+            .map(|(channel_id, message_id)| (channel_id.get() as i64, message_id.get() as i64))
+            .collect();
+        let blocklist: Vec<_> = messages_cache.blocklist.iter().map(|user_id| user_id.get() as i64).collect();
+        let dead_letters = messages_cache.dead_letters
+            .iter()
+            .map(serde_json::to_string)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut transaction = self.pool.begin().await?;
+        if changes.replace_all {
+            sqlx::query("DELETE FROM entries").execute(&mut *transaction).await?;
+        }
+        sqlx::query("DELETE FROM entries WHERE channel_id = ANY($1)")
+            .bind(&removed_channels)
+            .execute(&mut *transaction)
+            .await?;
+        for batch in changes.removed.chunks(BATCH_SIZE) {
+            let mut query = sqlx::QueryBuilder::new("DELETE FROM entries WHERE (channel_id, entry) IN ");
+            query.push_tuples(batch, |mut row, (channel_id, entry)| {
+                row.push_bind(channel_id.get() as i64).push_bind(entry.as_str());
+            });
+            query.build().execute(&mut *transaction).await?;
+        }
+        for batch in changes.changed.chunks(BATCH_SIZE) {
+            let mut query = sqlx::QueryBuilder::new("INSERT INTO entries (channel_id, entry, first_seen, author, message_guild_id, message_channel_id, message_id) ");
+            query.push_values(batch, |mut row, (channel_id, entry, metadata)| {
+                row.push_bind(channel_id.get() as i64)
+                    .push_bind(*entry)
+                    .push_bind(metadata.first_seen)
+                    .push_bind(metadata.author.map(|author| author as i64))
+                    .push_bind(metadata.message.map(|message| message.guild_id as i64))
+                    .push_bind(metadata.message.map(|message| message.channel_id as i64))
+                    .push_bind(metadata.message.map(|message| message.message_id as i64));
+            });
+            query.push(" ON CONFLICT (channel_id, entry) DO UPDATE SET first_seen = EXCLUDED.first_seen, author = EXCLUDED.author, ");
+            query.push("message_guild_id = EXCLUDED.message_guild_id, message_channel_id = EXCLUDED.message_channel_id, message_id = EXCLUDED.message_id");
+            query.build().execute(&mut *transaction).await?;
+        }
+        for table in ["channels", "activity", "last_message_ids", "blocklist", "banned", "dead_letters", "settings", "card_games"] {
+            sqlx::query(&format!("DELETE FROM {}", table)).execute(&mut *transaction).await?;
+        }
+        for batch in channels.chunks(BATCH_SIZE) {
+            let mut query = sqlx::QueryBuilder::new("INSERT INTO channels (channel_id, round, past_rounds, streaks) ");
+            query.push_values(batch, |mut row, (channel_id, round, past_rounds, streaks)| {
+                row.push_bind(*channel_id).push_bind(round.as_str()).push_bind(past_rounds.as_str()).push_bind(streaks.as_str());
+            });
+            query.build().execute(&mut *transaction).await?;
+        }
+        for batch in activity.chunks(BATCH_SIZE) {
+            let mut query = sqlx::QueryBuilder::new("INSERT INTO activity (channel_id, day, accepted, deleted) ");
+            query.push_values(batch, |mut row, (channel_id, day, accepted, deleted)| {
+                row.push_bind(*channel_id).push_bind(*day).push_bind(*accepted).push_bind(*deleted);
+            });
+            query.build().execute(&mut *transaction).await?;
+        }
+        for batch in last_message_ids.chunks(BATCH_SIZE) {
+            let mut query = sqlx::QueryBuilder::new("INSERT INTO last_message_ids (channel_id, message_id) ");
+            query.push_values(batch, |mut row, (channel_id, message_id)| {
+                row.push_bind(*channel_id).push_bind(*message_id);
+            });
+            query.build().execute(&mut *transaction).await?;
+        }
+        for batch in blocklist.chunks(BATCH_SIZE) {
+            let mut query = sqlx::QueryBuilder::new("INSERT INTO blocklist (user_id) ");
+            query.push_values(batch, |mut row, user_id| {
+                row.push_bind(*user_id);
+            });
+            query.build().execute(&mut *transaction).await?;
+        }
+        let banned: Vec<&String> = messages_cache.banned.iter().collect();
+        for batch in banned.chunks(BATCH_SIZE) {
+            let mut query = sqlx::QueryBuilder::new("INSERT INTO banned (entry) ");
+            query.push_values(batch, |mut row, entry| {
+                row.push_bind(entry.as_str());
+            });
+            query.build().execute(&mut *transaction).await?;
+        }
+        for batch in dead_letters.chunks(BATCH_SIZE) {
+            let mut query = sqlx::QueryBuilder::new("INSERT INTO dead_letters (dead_letter) ");
+            query.push_values(batch, |mut row, dead_letter| {
+                row.push_bind(dead_letter.as_str());
+            });
+            query.build().execute(&mut *transaction).await?;
+        }
+        for (key, value) in &messages_cache.settings {
+            sqlx::query("INSERT INTO settings (key, value) VALUES ($1, $2)")
+                .bind(key)
+                .bind(value)
+                .execute(&mut *transaction)
+                .await?;
+        }
+        for (channel_id, game) in &messages_cache.card_games {
+            sqlx::query("INSERT INTO card_games (channel_id, game) VALUES ($1, $2)")
+                .bind(channel_id.get() as i64)
+                .bind(serde_json::to_string(game)?)
+                .execute(&mut *transaction)
+                .await?;
+        }
+        transaction.commit().await?;
+        self.known.remember(messages_cache);
+        Ok(())
+    }
+    async fn archive(&self) -> Result<Option<path::PathBuf>, Error> {
+        // Snapshots are left to pg_dump and the server's own backups
+        Ok(None)
+    }
+    async fn check(&self) -> Result<String, String> {
+        sqlx::query("SELECT COUNT(*) FROM entries")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| format!("{} can't be queried: {}", self.describe(), e))?;
+        Ok(format!("{} can be queried", self.describe()))
+    }
+}
+
+fn channel_id(row: &postgres::PgRow) -> serenity::ChannelId {
+    serenity::ChannelId::new(row.get::<i64, _>("channel_id") as u64)
+}
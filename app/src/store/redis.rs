@@ -0,0 +1,233 @@
+use super::{known::{entry_value, parse_entry_value, KnownEntries}, CacheStore};
+use crate::{cache::MessagesCache, Error};
+use ::redis::AsyncCommands;
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude as serenity;
+use set_core::cache::ChannelCache;
+use std::{
+    collections::{HashMap, HashSet},
+    path,
+};
+
+/// Reserves a message's entries unless another message holds any of them, see [`CacheStore::claim`]
+///
+/// Claims are message IDs, zero-padded so comparing them as strings orders them by age.
+const CLAIM_SCRIPT: &str = r"
+for i = 3, #ARGV do
+    local claimant = redis.call('HGET', KEYS[1], ARGV[i])
+    if claimant and claimant ~= ARGV[1] and claimant >= ARGV[2] then
+        return 0
+    end
+end
+for i = 3, #ARGV do
+    redis.call('HSET', KEYS[1], ARGV[i], ARGV[1])
+end
+return 1
+";
+
+/// Milliseconds between the Unix epoch and the first snowflake
+const DISCORD_EPOCH: i64 = 1_420_070_400_000;
+
+/// Keeps the cache on a Redis server, shared by every bot instance pointed at it
+///
+/// The layout mirrors the SQLite tables: a set of channels, hashes of their entries, claims,
+/// rounds and last message IDs, a set of blocked users and a JSON list of failed deletions.
+/// Entries are written as differences from what this instance last loaded or saved, so entries
+/// added by other instances survive. Everything else is last writer wins.
+pub struct RedisStore {
+    url: String,
+    prefix: String,
+    connection: ::redis::aio::ConnectionManager,
+    claim_script: ::redis::Script,
+    known: KnownEntries,
+}
+
+impl RedisStore {
+    pub async fn open(url: &str, prefix: &str) -> Result<Self, Error> {
+        let client = ::redis::Client::open(url)?;
+        let connection = client.get_connection_manager().await?;
+        Ok(Self {
+            // The URL may hold a password, so only its address is shown
+            url: client.get_connection_info().addr().to_string(),
+            prefix: prefix.to_owned(),
+            connection,
+            claim_script: ::redis::Script::new(CLAIM_SCRIPT),
+            known: KnownEntries::default(),
+        })
+    }
+    fn key(&self, name: &str) -> String {
+        format!("{}:{}", self.prefix, name)
+    }
+    fn channel_key(&self, name: &str, channel_id: serenity::ChannelId) -> String {
+        format!("{}:{}:{}", self.prefix, name, channel_id)
+    }
+}
+
+/// A snowflake as a string that sorts like the snowflake
+fn padded(snowflake: u64) -> String {
+    format!("{:020}", snowflake)
+}
+
+#[async_trait::async_trait]
+impl CacheStore for RedisStore {
+    fn name(&self) -> &'static str {
+        "Redis"
+    }
+    fn is_shared(&self) -> bool {
+        true
+    }
+    fn describe(&self) -> String {
+        format!("Redis server {} (prefix `{}`)", self.url, self.prefix)
+    }
+    async fn load(&self, _channel_id: serenity::ChannelId) -> Result<Option<MessagesCache>, Error> {
+        let mut connection = self.connection.clone();
+        let channel_ids: HashSet<u64> = connection.smembers(self.key("channels")).await?;
+        let last_message_ids: HashMap<u64, u64> = connection.hgetall(self.key("last_message_ids")).await?;
+        if channel_ids.is_empty() && last_message_ids.is_empty() {
+            return Ok(None);
+        }
+        let rounds: HashMap<u64, String> = connection.hgetall(self.key("rounds")).await?;
+        let past_rounds: HashMap<u64, String> = connection.hgetall(self.key("past_rounds")).await?;
+        let streaks: HashMap<u64, String> = connection.hgetall(self.key("streaks")).await?;
+        let activity: HashMap<u64, String> = connection.hgetall(self.key("activity")).await?;
+        let mut messages_cache = MessagesCache::new();
+        for channel_id in channel_ids {
+            let entries: HashMap<String, String> = connection
+                .hgetall(self.channel_key("entries", serenity::ChannelId::new(channel_id)))
+                .await?;
+            let channel_cache = ChannelCache {
+                cache: entries
+                    .into_iter()
+                    .map(|(entry, value)| Ok((entry, parse_entry_value(&value)?)))
+                    .collect::<Result<_, Error>>()?,
+                round: rounds.get(&channel_id).map(|round| serde_json::from_str(round)).transpose()?.unwrap_or_default(),
+                past_rounds: past_rounds.get(&channel_id).map(|rounds| serde_json::from_str(rounds)).transpose()?.unwrap_or_default(),
+                streaks: streaks.get(&channel_id).map(|streaks| serde_json::from_str(streaks)).transpose()?.unwrap_or_default(),
+                activity: activity.get(&channel_id).map(|activity| serde_json::from_str(activity)).transpose()?.unwrap_or_default(),
+            };
+            messages_cache.channels.insert(serenity::ChannelId::new(channel_id), channel_cache);
+        }
+        messages_cache.last_message_ids = last_message_ids
+            .into_iter()
+            .map(|(channel_id, message_id)| (serenity::ChannelId::new(channel_id), serenity::MessageId::new(message_id)))
+            .collect();
+        let blocklist: HashSet<u64> = connection.smembers(self.key("blocklist")).await?;
+        messages_cache.blocklist = blocklist.into_iter().map(serenity::UserId::new).collect();
+        messages_cache.banned = connection.smembers(self.key("banned")).await?;
+        let dead_letters: Option<String> = connection.get(self.key("dead_letters")).await?;
+        if let Some(dead_letters) = dead_letters {
+            messages_cache.dead_letters = serde_json::from_str(&dead_letters)?;
+        }
+        let settings: Option<String> = connection.get(self.key("settings")).await?;
+        if let Some(settings) = settings {
+            messages_cache.settings = serde_json::from_str(&settings)?;
+        }
+        let card_games: Option<String> = connection.get(self.key("card_games")).await?;
+        if let Some(card_games) = card_games {
+            messages_cache.card_games = serde_json::from_str(&card_games)?;
+        }
+        self.known.remember(&messages_cache);
+        Ok(Some(messages_cache))
+    }
+    async fn save(&self, messages_cache: &MessagesCache) -> Result<(), Error> {
+        let changes = self.known.changes(messages_cache);
+        let mut connection = self.connection.clone();
+        let mut removed_channels = changes.removed_channels;
+        if changes.replace_all {
+            let channel_ids: Vec<u64> = connection.smembers(self.key("channels")).await?;
+            removed_channels.extend(channel_ids.into_iter().map(serenity::ChannelId::new));
+        }
+        let mut pipe = ::redis::pipe();
+        pipe.atomic();
+        for channel_id in removed_channels {
+            pipe.srem(self.key("channels"), channel_id.get()).ignore();
+            pipe.del(self.channel_key("entries", channel_id)).ignore();
+            pipe.del(self.channel_key("claims", channel_id)).ignore();
+        }
+        for (channel_id, entry) in &changes.removed {
+            pipe.hdel(self.channel_key("entries", *channel_id), entry).ignore();
+            pipe.hdel(self.channel_key("claims", *channel_id), entry).ignore();
+        }
+        for (channel_id, entry, metadata) in &changes.changed {
+            pipe.hset(self.channel_key("entries", *channel_id), *entry, entry_value(metadata)).ignore();
+        }
+        // Other instances may have added channels, so the set of them is only ever added to here
+        for name in ["rounds", "past_rounds", "streaks", "activity", "last_message_ids", "blocklist", "banned", "dead_letters"] {
+            pipe.del(self.key(name)).ignore();
+        }
+        if !messages_cache.channels.is_empty() {
+            let channel_ids: Vec<u64> = messages_cache.channels.keys().map(|channel_id| channel_id.get()).collect();
+            pipe.sadd(self.key("channels"), channel_ids).ignore();
+            let mut rounds = Vec::new();
+            let mut past_rounds = Vec::new();
+            let mut streaks = Vec::new();
+            let mut activity = Vec::new();
+            for (channel_id, channel_cache) in &messages_cache.channels {
+                rounds.push((channel_id.get(), serde_json::to_string(&channel_cache.round)?));
+                past_rounds.push((channel_id.get(), serde_json::to_string(&channel_cache.past_rounds)?));
+                streaks.push((channel_id.get(), serde_json::to_string(&channel_cache.streaks)?));
+                activity.push((channel_id.get(), serde_json::to_string(&channel_cache.activity)?));
+            }
+            pipe.hset_multiple(self.key("rounds"), &rounds).ignore();
+            pipe.hset_multiple(self.key("past_rounds"), &past_rounds).ignore();
+            pipe.hset_multiple(self.key("streaks"), &streaks).ignore();
+            pipe.hset_multiple(self.key("activity"), &activity).ignore();
+        }
+        if !messages_cache.last_message_ids.is_empty() {
+            let last_message_ids: Vec<(u64, u64)> = messages_cache
+                .last_message_ids
+                .iter()
+                .map(|(channel_id, message_id)| (channel_id.get(), message_id.get()))
+                .collect();
+            pipe.hset_multiple(self.key("last_message_ids"), &last_message_ids).ignore();
+        }
+        if !messages_cache.blocklist.is_empty() {
+            let blocklist: Vec<u64> = messages_cache.blocklist.iter().map(|user_id| user_id.get()).collect();
+            pipe.sadd(self.key("blocklist"), blocklist).ignore();
+        }
+        if !messages_cache.banned.is_empty() {
+            let banned: Vec<&String> = messages_cache.banned.iter().collect();
+            pipe.sadd(self.key("banned"), banned).ignore();
+        }
+        pipe.set(self.key("dead_letters"), serde_json::to_string(&messages_cache.dead_letters)?).ignore();
+        pipe.set(self.key("settings"), serde_json::to_string(&messages_cache.settings)?).ignore();
+        pipe.set(self.key("card_games"), serde_json::to_string(&messages_cache.card_games)?).ignore();
+
+        pipe.query_async::<()>(&mut connection).await?;
+        self.known.remember(messages_cache);
+        Ok(())
+    }
+    async fn archive(&self) -> Result<Option<path::PathBuf>, Error> {
+        // Snapshots are left to Redis' own persistence (RDB or AOF)
+        Ok(None)
+    }
+    async fn check(&self) -> Result<String, String> {
+        let mut connection = self.connection.clone();
+        ::redis::cmd("PING")
+            .query_async::<String>(&mut connection)
+            .await
+            .map_err(|e| format!("{} can't be reached: {}", self.describe(), e))?;
+        Ok(format!("{} is reachable", self.describe()))
+    }
+    async fn claim(
+        &self,
+        scope: serenity::ChannelId,
+        message_id: serenity::MessageId,
+        entries: &[String],
+        expired_before: Option<DateTime<Utc>>,
+    ) -> Result<bool, Error> {
+        if entries.is_empty() {
+            return Ok(true);
+        }
+        // Claims older than the oldest snowflake sent after `expired_before` are ignored
+        let oldest_valid = expired_before.map_or(0, |expired_before| ((expired_before.timestamp_millis() - DISCORD_EPOCH).max(0) as u64) << 22);
+        let mut invocation = self.claim_script.key(self.channel_key("claims", scope));
+        invocation.arg(padded(message_id.get())).arg(padded(oldest_valid));
+        for entry in entries {
+            invocation.arg(entry);
+        }
+        let mut connection = self.connection.clone();
+        let claimed: bool = invocation.invoke_async(&mut connection).await?;
+        Ok(claimed)
+    }
+}
@@ -0,0 +1,181 @@
+use super::{get_the_snapshot_path, known::{entry_value, parse_entry_value, KnownEntries}, CacheStore};
+use crate::{cache::MessagesCache, Error};
+use poise::serenity_prelude as serenity;
+use set_core::cache::{ChannelCache, RoundStats};
+use std::path;
+
+/// Keeps the cache in an embedded sled database, a directory of its own
+///
+/// Entries are keyed by their channel's big-endian ID followed by the entry, so each channel's
+/// entries are one prefix. Only entries that changed are written, and every save is flushed.
+pub struct SledStore {
+    path: path::PathBuf,
+    db: sled::Db,
+    /// Entry to first seen time and author
+    entries: sled::Tree,
+    /// Channel ID to its round and past rounds, as JSON
+    channels: sled::Tree,
+    /// Channel ID to its streaks, as JSON
+    streaks: sled::Tree,
+    /// Channel ID to its activity by day, as JSON
+    activity: sled::Tree,
+    known: KnownEntries,
+}
+
+/// Keys of the database's default tree, each holding JSON
+const LAST_MESSAGE_IDS: &str = "last_message_ids";
+const BLOCKLIST: &str = "blocklist";
+const BANNED: &str = "banned";
+const DEAD_LETTERS: &str = "dead_letters";
+const SETTINGS: &str = "settings";
+const CARD_GAMES: &str = "card_games";
+
+impl SledStore {
+    pub fn open(path: &path::Path) -> Result<Self, Error> {
+        let db = sled::open(path)?;
+        Ok(Self {
+            path: path.to_owned(),
+            entries: db.open_tree("entries")?,
+            channels: db.open_tree("channels")?,
+            streaks: db.open_tree("streaks")?,
+            activity: db.open_tree("activity")?,
+            db,
+            known: KnownEntries::default(),
+        })
+    }
+}
+
+fn entry_key(channel_id: serenity::ChannelId, entry: &str) -> Vec<u8> {
+    [&channel_id.get().to_be_bytes()[..], entry.as_bytes()].concat()
+}
+
+#[async_trait::async_trait]
+impl CacheStore for SledStore {
+    fn name(&self) -> &'static str {
+        "sled"
+    }
+    fn describe(&self) -> String {
+        format!("sled database {}", self.path.display())
+    }
+    async fn load(&self, _channel_id: serenity::ChannelId) -> Result<Option<MessagesCache>, Error> {
+        let Some(last_message_ids) = self.db.get(LAST_MESSAGE_IDS)? else {
+            return Ok(None);
+        };
+        let mut messages_cache = MessagesCache::new();
+        messages_cache.last_message_ids = serde_json::from_slice(&last_message_ids)?;
+        for channel in &self.channels {
+            let (channel_id, rounds) = channel?;
+            let channel_id = serenity::ChannelId::new(u64::from_be_bytes(channel_id.as_ref().try_into()?));
+            let (round, past_rounds): (RoundStats, Vec<RoundStats>) = serde_json::from_slice(&rounds)?;
+            let streaks = match self.streaks.get(channel_id.get().to_be_bytes())? {
+                Some(streaks) => serde_json::from_slice(&streaks)?,
+                None => Default::default(),
+            };
+            let activity = match self.activity.get(channel_id.get().to_be_bytes())? {
+                Some(activity) => serde_json::from_slice(&activity)?,
+                None => Default::default(),
+            };
+            messages_cache.channels.insert(channel_id, ChannelCache { cache: Default::default(), round, past_rounds, streaks, activity });
+        }
+        for entry in &self.entries {
+            let (key, value) = entry?;
+            let (channel_id, entry) = key.split_at(8);
+            let channel_id = serenity::ChannelId::new(u64::from_be_bytes(channel_id.try_into()?));
+            let metadata = parse_entry_value(std::str::from_utf8(&value)?)?;
+            messages_cache.channel(channel_id).cache.insert(String::from_utf8(entry.to_vec())?, metadata);
+        }
+        if let Some(blocklist) = self.db.get(BLOCKLIST)? {
+            messages_cache.blocklist = serde_json::from_slice(&blocklist)?;
+        }
+        if let Some(banned) = self.db.get(BANNED)? {
+            messages_cache.banned = serde_json::from_slice(&banned)?;
+        }
+        if let Some(dead_letters) = self.db.get(DEAD_LETTERS)? {
+            messages_cache.dead_letters = serde_json::from_slice(&dead_letters)?;
+        }
+        if let Some(settings) = self.db.get(SETTINGS)? {
+            messages_cache.settings = serde_json::from_slice(&settings)?;
+        }
+        if let Some(card_games) = self.db.get(CARD_GAMES)? {
+            messages_cache.card_games = serde_json::from_slice(&card_games)?;
+        }
+        self.known.remember(&messages_cache);
+        Ok(Some(messages_cache))
+    }
+    async fn save(&self, messages_cache: &MessagesCache) -> Result<(), Error> {
+        let changes = self.known.changes(messages_cache);
+        let mut entries = sled::Batch::default();
+        if changes.replace_all {
+            for key in self.entries.iter().keys() {
+                entries.remove(key?);
+            }
+        }
+        for channel_id in &changes.removed_channels {
+            for key in self.entries.scan_prefix(channel_id.get().to_be_bytes()).keys() {
+                entries.remove(key?);
+            }
+        }
+        for (channel_id, entry) in &changes.removed {
+            entries.remove(entry_key(*channel_id, entry));
+        }
+        for (channel_id, entry, metadata) in &changes.changed {
+            entries.insert(entry_key(*channel_id, entry), entry_value(metadata).as_bytes());
+        }
+        let mut channels = sled::Batch::default();
+        for key in self.channels.iter().keys() {
+            channels.remove(key?);
+        }
+        let mut streaks = sled::Batch::default();
+        for key in self.streaks.iter().keys() {
+            streaks.remove(key?);
+        }
+        let mut activity = sled::Batch::default();
+        for key in self.activity.iter().keys() {
+            activity.remove(key?);
+        }
+        for (channel_id, channel_cache) in &messages_cache.channels {
+            let rounds = serde_json::to_vec(&(&channel_cache.round, &channel_cache.past_rounds))?;
+            channels.insert(&channel_id.get().to_be_bytes(), rounds);
+            streaks.insert(&channel_id.get().to_be_bytes(), serde_json::to_vec(&channel_cache.streaks)?);
+            activity.insert(&channel_id.get().to_be_bytes(), serde_json::to_vec(&channel_cache.activity)?);
+        }
+        let mut state = sled::Batch::default();
+        state.insert(LAST_MESSAGE_IDS, serde_json::to_vec(&messages_cache.last_message_ids)?);
+        state.insert(BLOCKLIST, serde_json::to_vec(&messages_cache.blocklist)?);
+        state.insert(BANNED, serde_json::to_vec(&messages_cache.banned)?);
+        state.insert(DEAD_LETTERS, serde_json::to_vec(&messages_cache.dead_letters)?);
+        state.insert(SETTINGS, serde_json::to_vec(&messages_cache.settings)?);
+        state.insert(CARD_GAMES, serde_json::to_vec(&messages_cache.card_games)?);
+
+        use sled::Transactional;
+        (&self.entries, &self.channels, &self.streaks, &self.activity, &*self.db)
+            .transaction(|(entries_tree, channels_tree, streaks_tree, activity_tree, state_tree)| {
+                entries_tree.apply_batch(&entries)?;
+                channels_tree.apply_batch(&channels)?;
+                streaks_tree.apply_batch(&streaks)?;
+                activity_tree.apply_batch(&activity)?;
+                state_tree.apply_batch(&state)?;
+                Ok::<_, sled::transaction::ConflictableTransactionError>(())
+            })
+            .map_err(|e| format!("Failed to write {}: {}", self.describe(), e))?;
+        self.db.flush_async().await?;
+        self.known.remember(messages_cache);
+        Ok(())
+    }
+    async fn archive(&self) -> Result<Option<path::PathBuf>, Error> {
+        let archive_path = get_the_snapshot_path(&self.path);
+        // A snapshot taken within the same second is replaced, like the JSON file's would be
+        match std::fs::remove_dir_all(&archive_path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        let snapshot = sled::open(&archive_path)?;
+        snapshot.import(self.db.export());
+        snapshot.flush_async().await?;
+        Ok(Some(archive_path))
+    }
+    async fn check(&self) -> Result<String, String> {
+        let size = self.db.size_on_disk().map_err(|e| format!("`{}` can't be read: {}", self.path.display(), e))?;
+        Ok(format!("`{}` is open and takes up {} bytes", self.path.display(), size))
+    }
+}
@@ -0,0 +1,290 @@
+use super::{check_file, get_the_snapshot_path, CacheStore};
+use crate::{cache::MessagesCache, Error};
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude as serenity;
+use set_core::cache::{ChannelCache, DayActivity, Entry, MessageRef};
+use sqlx::{sqlite, Row};
+use std::path;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS channels (
+    channel_id INTEGER PRIMARY KEY,
+    round TEXT NOT NULL,
+    past_rounds TEXT NOT NULL,
+    streaks TEXT NOT NULL DEFAULT '{}'
+);
+CREATE TABLE IF NOT EXISTS entries (
+    channel_id INTEGER NOT NULL,
+    entry TEXT NOT NULL,
+    first_seen TEXT NOT NULL,
+    author INTEGER,
+    message_guild_id INTEGER,
+    message_channel_id INTEGER,
+    message_id INTEGER,
+    PRIMARY KEY (channel_id, entry)
+);
+CREATE TABLE IF NOT EXISTS activity (
+    channel_id INTEGER NOT NULL,
+    day TEXT NOT NULL,
+    accepted INTEGER NOT NULL,
+    deleted INTEGER NOT NULL,
+    PRIMARY KEY (channel_id, day)
+);
+CREATE TABLE IF NOT EXISTS last_message_ids (
+    channel_id INTEGER PRIMARY KEY,
+    message_id INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS blocklist (
+    user_id INTEGER PRIMARY KEY
+);
+CREATE TABLE IF NOT EXISTS banned (
+    entry TEXT PRIMARY KEY
+);
+CREATE TABLE IF NOT EXISTS dead_letters (
+    dead_letter TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS card_games (
+    channel_id INTEGER PRIMARY KEY,
+    game TEXT NOT NULL
+);
+";
+
+/// Rows inserted per statement, well below SQLite's limit on bound parameters
+const BATCH_SIZE: usize = 1000;
+
+/// Keeps the cache in an SQLite database
+///
+/// Snowflakes are stored as SQLite's signed integers, which they fit in until the year 2084.
+pub struct SqliteStore {
+    path: path::PathBuf,
+    pool: sqlite::SqlitePool,
+}
+
+impl SqliteStore {
+    pub async fn open(path: &path::Path) -> Result<Self, Error> {
+        let options = sqlite::SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        let pool = sqlite::SqlitePoolOptions::new().connect_with(options).await?;
+        sqlx::raw_sql(SCHEMA).execute(&pool).await?;
+        // Databases created before authors were recorded lack their column
+        let author_columns: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pragma_table_info('entries') WHERE name = 'author'")
+            .fetch_one(&pool)
+            .await?;
+        if author_columns == 0 {
+            sqlx::query("ALTER TABLE entries ADD COLUMN author INTEGER").execute(&pool).await?;
+        }
+        // Likewise for the messages that introduced entries
+        let message_columns: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pragma_table_info('entries') WHERE name = 'message_id'")
+            .fetch_one(&pool)
+            .await?;
+        if message_columns == 0 {
+            for column in ["message_guild_id", "message_channel_id", "message_id"] {
+                sqlx::query(&format!("ALTER TABLE entries ADD COLUMN {} INTEGER", column)).execute(&pool).await?;
+            }
+        }
+        // And for streaks
+        let streak_columns: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pragma_table_info('channels') WHERE name = 'streaks'")
+            .fetch_one(&pool)
+            .await?;
+        if streak_columns == 0 {
+            sqlx::query("ALTER TABLE channels ADD COLUMN streaks TEXT NOT NULL DEFAULT '{}'").execute(&pool).await?;
+        }
+        Ok(Self {
+            path: path.to_owned(),
+            pool,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheStore for SqliteStore {
+    fn name(&self) -> &'static str {
+        "SQLite"
+    }
+    fn describe(&self) -> String {
+        format!("SQLite database {}", self.path.display())
+    }
+    async fn load(&self, _channel_id: serenity::ChannelId) -> Result<Option<MessagesCache>, Error> {
+        let channels = sqlx::query("SELECT channel_id, round, past_rounds, streaks FROM channels").fetch_all(&self.pool).await?;
+        let last_message_ids = sqlx::query("SELECT channel_id, message_id FROM last_message_ids").fetch_all(&self.pool).await?;
+        if channels.is_empty() && last_message_ids.is_empty() {
+            return Ok(None);
+        }
+        let mut messages_cache = MessagesCache::new();
+        for row in channels {
+            let channel_cache = ChannelCache {
+                cache: Default::default(),
+                round: serde_json::from_str(row.get("round"))?,
+                past_rounds: serde_json::from_str(row.get("past_rounds"))?,
+                streaks: serde_json::from_str(row.get("streaks"))?,
+                activity: Default::default(),
+            };
+            messages_cache.channels.insert(channel_id(&row), channel_cache);
+        }
+        let query = "SELECT channel_id, entry, first_seen, author, message_guild_id, message_channel_id, message_id FROM entries";
+        for row in sqlx::query(query).fetch_all(&self.pool).await? {
+            let first_seen: DateTime<Utc> = row.get::<&str, _>("first_seen").parse()?;
+            let author = row.get::<Option<i64>, _>("author").map(|author| author as u64);
+            let message = match (row.get::<Option<i64>, _>("message_guild_id"), row.get::<Option<i64>, _>("message_channel_id"), row.get::<Option<i64>, _>("message_id")) {
+                (Some(guild_id), Some(channel_id), Some(message_id)) => {
+                    Some(MessageRef { guild_id: guild_id as u64, channel_id: channel_id as u64, message_id: message_id as u64 })
+                }
+                _ => None,
+            };
+            messages_cache.channel(channel_id(&row)).cache.insert(row.get("entry"), Entry { first_seen, author, message });
+        }
+        for row in sqlx::query("SELECT channel_id, day, accepted, deleted FROM activity").fetch_all(&self.pool).await? {
+            let activity = DayActivity { accepted: row.get::<i64, _>("accepted") as u32, deleted: row.get::<i64, _>("deleted") as u32 };
+            messages_cache.channel(channel_id(&row)).activity.insert(row.get::<&str, _>("day").parse()?, activity);
+        }
+        for row in last_message_ids {
+            let message_id = serenity::MessageId::new(row.get::<i64, _>("message_id") as u64);
+            messages_cache.last_message_ids.insert(channel_id(&row), message_id);
+        }
+        for row in sqlx::query("SELECT user_id FROM blocklist").fetch_all(&self.pool).await? {
+            messages_cache.blocklist.insert(serenity::UserId::new(row.get::<i64, _>("user_id") as u64));
+        }
+        for row in sqlx::query("SELECT entry FROM banned").fetch_all(&self.pool).await? {
+            messages_cache.banned.insert(row.get("entry"));
+        }
+        for row in sqlx::query("SELECT dead_letter FROM dead_letters ORDER BY rowid").fetch_all(&self.pool).await? {
+            messages_cache.dead_letters.push(serde_json::from_str(row.get("dead_letter"))?);
+        }
+        for row in sqlx::query("SELECT key, value FROM settings").fetch_all(&self.pool).await? {
+            messages_cache.settings.insert(row.get("key"), row.get("value"));
+        }
+        for row in sqlx::query("SELECT channel_id, game FROM card_games").fetch_all(&self.pool).await? {
+            messages_cache.card_games.insert(channel_id(&row), serde_json::from_str(row.get("game"))?);
+        }
+        Ok(Some(messages_cache))
+    }
+    async fn save(&self, messages_cache: &MessagesCache) -> Result<(), Error> {
+        let mut channels = Vec::new();
+        let mut entries = Vec::new();
+        let mut activity = Vec::new();
+        for (channel_id, channel_cache) in &messages_cache.channels {
+            let channel_id = channel_id.get() as i64;
+            channels.push((channel_id, serde_json::to_string(&channel_cache.round)?, serde_json::to_string(&channel_cache.past_rounds)?, serde_json::to_string(&channel_cache.streaks)?));
+            entries.extend(channel_cache.cache.iter().map(|(entry, metadata)| {
+                (channel_id, entry.clone(), metadata.first_seen.to_rfc3339(), metadata.author.map(|author| author as i64), metadata.message)
+            }));
+            activity.extend(channel_cache.activity.iter().map(|(day, counts)| {
+                (channel_id, day.to_string(), counts.accepted as i64, counts.deleted as i64)
+            }));
+        }
+        let last_message_ids: Vec<_> = messages_cache.last_message_ids
+            .iter()
+            .map(|(channel_id, message_id)| (channel_id.get() as i64, message_id.get() as i64))
+            .collect();
+        let blocklist: Vec<_> = messages_cache.blocklist.iter().map(|user_id| user_id.get() as i64).collect();
+        let dead_letters = messages_cache.dead_letters
+            .iter()
+            .map(serde_json::to_string)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut transaction = self.pool.begin().await?;
+        for table in ["channels", "entries", "activity", "last_message_ids", "blocklist", "banned", "dead_letters", "settings", "card_games"] {
+            sqlx::query(&format!("DELETE FROM {}", table)).execute(&mut *transaction).await?;
+        }
+        for batch in channels.chunks(BATCH_SIZE) {
+            let mut query = sqlx::QueryBuilder::new("INSERT INTO channels (channel_id, round, past_rounds, streaks) ");
+            query.push_values(batch, |mut row, (channel_id, round, past_rounds, streaks)| {
+                row.push_bind(*channel_id).push_bind(round.as_str()).push_bind(past_rounds.as_str()).push_bind(streaks.as_str());
+            });
+            query.build().execute(&mut *transaction).await?;
+        }
+        for batch in entries.chunks(BATCH_SIZE) {
+            let mut query = sqlx::QueryBuilder::new("INSERT INTO entries (channel_id, entry, first_seen, author, message_guild_id, message_channel_id, message_id) ");
+            query.push_values(batch, |mut row, (channel_id, entry, first_seen, author, message)| {
+                row.push_bind(*channel_id).push_bind(entry.as_str()).push_bind(first_seen.as_str()).push_bind(*author);
+                row.push_bind(message.map(|message| message.guild_id as i64));
+                row.push_bind(message.map(|message| message.channel_id as i64));
+                row.push_bind(message.map(|message| message.message_id as i64));
+            });
+            query.build().execute(&mut *transaction).await?;
+        }
+        for batch in activity.chunks(BATCH_SIZE) {
+            let mut query = sqlx::QueryBuilder::new("INSERT INTO activity (channel_id, day, accepted, deleted) ");
+            query.push_values(batch, |mut row, (channel_id, day, accepted, deleted)| {
+                row.push_bind(*channel_id).push_bind(day.as_str()).push_bind(*accepted).push_bind(*deleted);
+            });
+            query.build().execute(&mut *transaction).await?;
+        }
+        for batch in last_message_ids.chunks(BATCH_SIZE) {
+            let mut query = sqlx::QueryBuilder::new("INSERT INTO last_message_ids (channel_id, message_id) ");
+            query.push_values(batch, |mut row, (channel_id, message_id)| {
+                row.push_bind(*channel_id).push_bind(*message_id);
+            });
+            query.build().execute(&mut *transaction).await?;
+        }
+        for batch in blocklist.chunks(BATCH_SIZE) {
+            let mut query = sqlx::QueryBuilder::new("INSERT INTO blocklist (user_id) ");
+            query.push_values(batch, |mut row, user_id| {
+                row.push_bind(*user_id);
+            });
+            query.build().execute(&mut *transaction).await?;
+        }
+        let banned: Vec<&String> = messages_cache.banned.iter().collect();
+        for batch in banned.chunks(BATCH_SIZE) {
+            let mut query = sqlx::QueryBuilder::new("INSERT INTO banned (entry) ");
+            query.push_values(batch, |mut row, entry| {
+                row.push_bind(entry.as_str());
+            });
+            query.build().execute(&mut *transaction).await?;
+        }
+        for batch in dead_letters.chunks(BATCH_SIZE) {
+            let mut query = sqlx::QueryBuilder::new("INSERT INTO dead_letters (dead_letter) ");
+            query.push_values(batch, |mut row, dead_letter| {
+                row.push_bind(dead_letter.as_str());
+            });
+            query.build().execute(&mut *transaction).await?;
+        }
+        for (key, value) in &messages_cache.settings {
+            sqlx::query("INSERT INTO settings (key, value) VALUES (?, ?)")
+                .bind(key)
+                .bind(value)
+                .execute(&mut *transaction)
+                .await?;
+        }
+        for (channel_id, game) in &messages_cache.card_games {
+            sqlx::query("INSERT INTO card_games (channel_id, game) VALUES (?, ?)")
+                .bind(channel_id.get() as i64)
+                .bind(serde_json::to_string(game)?)
+                .execute(&mut *transaction)
+                .await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+    async fn archive(&self) -> Result<Option<path::PathBuf>, Error> {
+        let archive_path = get_the_snapshot_path(&self.path);
+        // VACUUM INTO refuses to overwrite a snapshot taken within the same second, copying would not
+        match std::fs::remove_file(&archive_path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        // Unlike copying the file, this gives a consistent snapshot while the database is open
+        sqlx::query("VACUUM INTO ?")
+            .bind(archive_path.to_string_lossy().into_owned())
+            .execute(&self.pool)
+            .await?;
+        Ok(Some(archive_path))
+    }
+    async fn check(&self) -> Result<String, String> {
+        check_file(&self.path)?;
+        sqlx::query("SELECT COUNT(*) FROM entries")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| format!("`{}` can't be queried: {}", self.path.display(), e))?;
+        Ok(format!("`{}` is readable and writable", self.path.display()))
+    }
+}
+
+fn channel_id(row: &sqlite::SqliteRow) -> serenity::ChannelId {
+    serenity::ChannelId::new(row.get::<i64, _>("channel_id") as u64)
+}
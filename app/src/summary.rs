@@ -0,0 +1,101 @@
+use crate::{cache::MessagesCache, config::SummaryConfig, format_count, get_the_channel_id};
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::{self as serenity, Mentionable};
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::Mutex;
+
+/// What happened in the cache during one summary's period
+struct Summary {
+    since: DateTime<Utc>,
+    new_entries: usize,
+    deleted: u64,
+    /// Authors by how many entries they added, most first
+    contributors: Vec<(serenity::UserId, usize)>,
+    total: usize,
+}
+
+/// Duplicates deleted in all rounds of all channels so far
+fn total_deleted(messages_cache: &MessagesCache) -> u64 {
+    messages_cache.channels
+        .values()
+        .map(|channel_cache| channel_cache.round.deleted + channel_cache.past_rounds.iter().map(|round| round.deleted).sum::<u64>())
+        .sum()
+}
+
+fn summarize(messages_cache: &MessagesCache, since: DateTime<Utc>, deleted_before: u64, top_contributors: usize) -> Summary {
+    let mut new_entries = 0;
+    let mut contributions: HashMap<u64, usize> = HashMap::new();
+    for channel_cache in messages_cache.channels.values() {
+        for entry in channel_cache.cache.values().filter(|entry| entry.first_seen >= since) {
+            new_entries += 1;
+            if let Some(author) = entry.author {
+                *contributions.entry(author).or_default() += 1;
+            }
+        }
+    }
+    let mut contributors: Vec<_> = contributions
+        .into_iter()
+        .map(|(author, count)| (serenity::UserId::new(author), count))
+        .collect();
+    contributors.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    contributors.truncate(top_contributors);
+    Summary {
+        since,
+        new_entries,
+        deleted: total_deleted(messages_cache).saturating_sub(deleted_before),
+        contributors,
+        total: messages_cache.total_entries(),
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "**Summary since {}**\n{} new unique entries, {} duplicates deleted, {} entries in total",
+            self.since.format("%Y-%m-%d %H:%M UTC"),
+            format_count(self.new_entries),
+            format_count(self.deleted as usize),
+            format_count(self.total),
+        )?;
+        if !self.contributors.is_empty() {
+            write!(f, "\nMost active:")?;
+            for (i, (user_id, count)) in self.contributors.iter().enumerate() {
+                write!(f, "\n{}. {} with {} entries", i + 1, user_id.mention(), format_count(*count))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Posts a summary every time the configured schedule fires
+///
+/// A summary covers the time since the previous one, or since the bot started.
+pub async fn post_summaries(ctx: serenity::Context, messages_cache: Arc<Mutex<MessagesCache>>, config: SummaryConfig) {
+    let Some(schedule) = config.compiled_schedule.clone() else {
+        return;
+    };
+    let channel_id = config.channel.unwrap_or(serenity::ChannelId::new(get_the_channel_id()));
+    let mut since = Utc::now();
+    let mut deleted_before = total_deleted(&*messages_cache.lock().await);
+    for next in schedule.upcoming(Utc) {
+        let Ok(wait) = (next - Utc::now()).to_std() else {
+            continue;
+        };
+        tokio::time::sleep(wait).await;
+        let summary = {
+            let messages_cache = messages_cache.lock().await;
+            let summary = summarize(&messages_cache, since, deleted_before, config.top_contributors);
+            deleted_before = total_deleted(&messages_cache);
+            summary
+        };
+        since = next;
+        let message = serenity::CreateMessage::new()
+            .content(summary.to_string())
+            // Contributors are listed, not pinged
+            .allowed_mentions(serenity::CreateAllowedMentions::new());
+        if let Err(error) = channel_id.send_message(&ctx, message).await {
+            log::error!("Failed to post the summary in {}: {:?}", channel_id, error);
+        }
+    }
+}
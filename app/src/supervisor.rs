@@ -0,0 +1,76 @@
+use crate::{alert_owner_via, config::Config};
+use futures::FutureExt;
+use poise::serenity_prelude as serenity;
+use std::{
+    any::Any,
+    future::Future,
+    panic::AssertUnwindSafe,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+/// How long a task that panicked waits before it is restarted, so one that panics right away
+/// doesn't spin
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Where panics of supervised tasks are reported, like other alerts
+///
+/// The log channel is read from the config on every report, so reloading it applies to every alert.
+#[derive(Clone)]
+pub struct Reporter {
+    http: Arc<serenity::Http>,
+    config: Arc<RwLock<Arc<Config>>>,
+}
+
+impl Reporter {
+    pub fn new(http: Arc<serenity::Http>, config: Arc<RwLock<Arc<Config>>>) -> Self {
+        Self { http, config }
+    }
+    async fn report(&self, name: &str, panic: &(dyn Any + Send)) {
+        let message = panic
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown cause");
+        log::error!("The {} task panicked, restarting it: {}", name, message);
+        self.send(&format!("The {} task panicked and was restarted: {}", name, message)).await;
+    }
+    /// Reports a task that stopped with an error, which isn't restarted
+    pub async fn alert(&self, alert: &str) {
+        log::error!("{}", alert);
+        self.send(alert).await;
+    }
+    async fn send(&self, alert: &str) {
+        let log_channel = self.config.read().expect("The config lock was poisoned").alerts.log_channel;
+        if let Err(error) = alert_owner_via(&self.http, log_channel, alert).await {
+            log::error!("Failed to report {:?}: {:?}", alert, error);
+        }
+    }
+}
+
+/// Runs the task made by `start` until it finishes, starting it over whenever it panics
+///
+/// A restarted task starts from scratch, so tasks must pick up where they left off from the
+/// cache, e.g. catch-up from the last handled message.
+pub async fn restarting<T, F, Fut>(name: &str, reporter: &Reporter, mut start: F) -> T
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = T>,
+{
+    loop {
+        match AssertUnwindSafe(start()).catch_unwind().await {
+            Ok(output) => return output,
+            Err(panic) => reporter.report(name, &*panic).await,
+        }
+        tokio::time::sleep(RESTART_DELAY).await;
+    }
+}
+
+/// Runs the task made by `start` in the background, restarting it whenever it panics
+pub fn spawn<F, Fut>(name: &'static str, reporter: Reporter, start: F)
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(async move { restarting(name, &reporter, start).await });
+}
@@ -0,0 +1,55 @@
+use crate::heartbeat::gateway_latency;
+use poise::serenity_prelude as serenity;
+use sd_notify::NotifyState;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Whether systemd was told that the bot is ready, which only counts once
+static NOTIFIED_READY: AtomicBool = AtomicBool::new(false);
+
+fn notify(state: &[NotifyState]) {
+    // Not running under systemd isn't an error, `notify` does nothing then
+    if let Err(error) = sd_notify::notify(false, state) {
+        log::warn!("Failed to notify systemd: {}", error);
+    }
+}
+
+/// Tells systemd the bot is ready, once it caught up, and starts petting the watchdog if the
+/// service has `WatchdogSec=`
+pub fn ready(shard_manager: Arc<serenity::ShardManager>) {
+    if NOTIFIED_READY.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    notify(&[NotifyState::Ready]);
+    let mut timeout = 0;
+    if sd_notify::watchdog_enabled(false, &mut timeout) {
+        // Twice per timeout, as systemd recommends
+        tokio::spawn(pet_the_watchdog(shard_manager, Duration::from_micros(timeout) / 2));
+    }
+}
+
+/// Tells systemd the bot is shutting down, so the time it takes to save isn't mistaken for a hang
+pub fn stopping() {
+    notify(&[NotifyState::Stopping]);
+}
+
+/// Pets the watchdog every `interval` while the gateway is connected
+///
+/// A stalled event loop or a gateway connection lost for longer than `WatchdogSec=` stops the
+/// petting, and systemd restarts the bot.
+async fn pet_the_watchdog(shard_manager: Arc<serenity::ShardManager>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        if gateway_latency(&shard_manager).await.is_some() {
+            notify(&[NotifyState::Watchdog]);
+        } else {
+            log::debug!("Not petting the watchdog, since the gateway is not connected");
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use crate::{config::TelemetryConfig, Error};
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace::TracerProvider, Resource};
+use tracing_subscriber::layer::SubscriberExt;
+
+/// Exports the spans around event handling, Discord requests and saving the cache over OTLP
+///
+/// Only spans go through `tracing`, logs keep going through `log` and `RUST_LOG`.
+pub struct Telemetry {
+    provider: TracerProvider,
+}
+
+impl Telemetry {
+    pub fn start(config: &TelemetryConfig) -> Result<Self, Error> {
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(&config.endpoint)
+            .build()?;
+        let provider = TracerProvider::builder()
+            .with_batch_exporter(exporter, runtime::Tokio)
+            .with_resource(Resource::new([KeyValue::new("service.name", config.service_name.clone())]))
+            .build();
+        let tracer = provider.tracer("set");
+        // Not `init()`, which would also route `log` through `tracing` and clash with env_logger
+        let subscriber = tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+        tracing::subscriber::set_global_default(subscriber)?;
+        log::info!("Exporting traces to {}", config.endpoint);
+        Ok(Self { provider })
+    }
+    /// Exports the spans that are still buffered
+    pub fn shutdown(self) {
+        if let Err(error) = self.provider.shutdown() {
+            log::warn!("Failed to export the last traces: {}", error);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use crate::{
+    config::{Config, ThemeKind},
+    get_the_channel_id,
+    i18n::tr,
+};
+use chrono::Utc;
+use poise::serenity_prelude as serenity;
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+/// How often the announcement schedule is checked, so changing it takes no restart
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The theme `value` of `kind`, as announced
+pub fn describe(locale: &str, kind: ThemeKind, value: &str) -> String {
+    let key = match kind {
+        ThemeKind::Prefix => "theme.prefix",
+        ThemeKind::Word => "theme.word",
+        ThemeKind::Regex => "theme.regex",
+    };
+    tr(locale, key, &[("value", &value)])
+}
+
+/// Announces the theme every time `theme.announce` fires, as long as there is one
+///
+/// The config is read anew on every check, so themes set with `/theme set` are announced without
+/// a restart.
+pub async fn announce(ctx: serenity::Context, config: Arc<RwLock<Arc<Config>>>) {
+    let mut checked_at = Utc::now();
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let now = Utc::now();
+        let config = config.read().expect("The config lock was poisoned").clone();
+        let theme = &config.theme;
+        let due = theme
+            .compiled_announce
+            .as_ref()
+            .and_then(|schedule| schedule.after(&checked_at).next())
+            .is_some_and(|next| next <= now);
+        checked_at = now;
+        let (true, Some(value)) = (due, &theme.value) else {
+            continue;
+        };
+        let channel_id = theme.channel.unwrap_or(serenity::ChannelId::new(get_the_channel_id()));
+        let announcement = describe(&config.i18n.locale, theme.kind, value);
+        if let Err(error) = channel_id.say(&ctx, announcement).await {
+            log::error!("Failed to announce the theme in {}: {:?}", channel_id, error);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+/// Window that the budget of requests applies to
+const WINDOW: Duration = Duration::from_secs(1);
+/// How long hit rate limits count towards the pressure
+const RATE_LIMIT_MEMORY: Duration = Duration::from_secs(3600);
+
+/// A budget of API requests shared by message fetches and deletions
+///
+/// Serenity already waits out the rate limits of each route, but a long catch-up or a burst of
+/// duplicates can still send requests fast enough in total for Cloudflare to ban the bot for a
+/// while. Requests are spread out so no more than the budget is sent per second, and when
+/// Discord reports a rate limit anyway, all of them wait until it is over.
+pub struct Throttle {
+    per_second: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// When the requests of the last second were sent
+    recent: VecDeque<Instant>,
+    /// Requests wait until then after a rate limit was hit
+    paused_until: Option<Instant>,
+    /// When rate limits were hit, within the last hour
+    rate_limits: VecDeque<Instant>,
+}
+
+impl State {
+    fn forget_old(&mut self, now: Instant) {
+        while self.recent.front().is_some_and(|sent| now.duration_since(*sent) >= WINDOW) {
+            self.recent.pop_front();
+        }
+        while self.rate_limits.front().is_some_and(|hit| now.duration_since(*hit) >= RATE_LIMIT_MEMORY) {
+            self.rate_limits.pop_front();
+        }
+    }
+}
+
+/// How close the bot is to its budget
+pub struct Pressure {
+    /// Requests sent in the last second
+    pub used: usize,
+    pub per_second: usize,
+    /// Rate limits hit in the last hour
+    pub rate_limits: usize,
+    /// How long requests are paused for because of a rate limit
+    pub paused_for: Option<Duration>,
+}
+
+impl Throttle {
+    pub fn new(per_second: usize) -> Self {
+        Self {
+            per_second: per_second.max(1),
+            state: Mutex::new(State::default()),
+        }
+    }
+    /// Waits until a request fits into the budget, and counts it against it
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().await;
+                let now = Instant::now();
+                state.forget_old(now);
+                match (state.paused_until, state.recent.front()) {
+                    (Some(until), _) if until > now => until - now,
+                    (_, Some(oldest)) if state.recent.len() >= self.per_second => WINDOW - now.duration_since(*oldest),
+                    _ => {
+                        state.recent.push_back(now);
+                        return;
+                    }
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+    /// Pauses all requests for `timeout` after Discord reported a rate limit
+    pub async fn on_rate_limit(&self, timeout: Duration) {
+        let mut state = self.state.lock().await;
+        let now = Instant::now();
+        let until = now + timeout;
+        state.paused_until = Some(state.paused_until.map_or(until, |paused_until| paused_until.max(until)));
+        state.rate_limits.push_back(now);
+    }
+    pub async fn pressure(&self) -> Pressure {
+        let mut state = self.state.lock().await;
+        let now = Instant::now();
+        state.forget_old(now);
+        Pressure {
+            used: state.recent.len(),
+            per_second: self.per_second,
+            rate_limits: state.rate_limits.len(),
+            paused_for: state.paused_until.filter(|until| *until > now).map(|until| until - now),
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use crate::{feed::Event, Data};
+use std::{sync::LazyLock, time::Duration};
+
+/// How long the webhook gets to answer
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Shared by every notification, so connections to the webhook are reused
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .user_agent(concat!("set/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .expect("The webhook client has a valid configuration")
+});
+
+/// Sends `event` to the configured webhook, if any, in the background
+///
+/// Failures are logged and not retried, so a dashboard that is down never holds up deletions.
+pub fn notify(data: &Data, event: &Event) {
+    let config = data.config();
+    let Some(url) = config.webhook.url.clone() else {
+        return;
+    };
+    let token = config.webhook.token.clone();
+    let event = event.clone();
+    tokio::spawn(async move {
+        if let Err(error) = send(&url, token.as_deref(), &event).await {
+            log::warn!("Failed to send an event to the webhook: {}", error);
+        }
+    });
+}
+
+async fn send(url: &str, token: Option<&str>, event: &Event) -> reqwest::Result<()> {
+    let mut request = CLIENT.post(url).json(event);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
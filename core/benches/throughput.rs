@@ -0,0 +1,113 @@
+//! Throughput of normalization, cache inserts and lookups, and snapshot serialization
+//!
+//! Run with `cargo bench -p set-core`.
+
+use chrono::Utc;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use set_core::{
+    cache::ChannelCache,
+    config::{CaseFolding, FuzzyConfig, MatchingConfig, NormalizationConfig, StemmingLanguage},
+    dedup::{self, Key},
+    normalize::normalize_string,
+};
+use std::hint::black_box;
+
+const ENTRIES: usize = 1_000_000;
+
+const MESSAGES: &[&str] = &[
+    "hello",
+    "The quick brown fox jumps over the lazy dog",
+    "**Check** this out: https://example.com/watch?v=abc&utm_source=share&si=xyz <@!123456789012345678>",
+    "Ｈｅｌｌｏ wоrld, café au lait <:blob:123456789012345678> ||spoiler||",
+    "Токио東京 Tōkyō straße İstanbul h3ll0 > quoted\n`code` and ```rust\nfn main() {}\n```",
+];
+
+/// Every optional stage of the pipeline
+fn all_stages() -> NormalizationConfig {
+    NormalizationConfig {
+        confusables: true,
+        quotes_and_code: true,
+        markdown: true,
+        mentions: true,
+        custom_emoji: true,
+        links: true,
+        case_folding: CaseFolding::Full,
+        diacritics: true,
+        transliterate: true,
+        leet: true,
+        stemming: Some(StemmingLanguage::English),
+    }
+}
+
+/// Distinct entries of a realistic length
+fn entry(i: usize) -> String {
+    format!("entry number {} of the benchmark", i)
+}
+
+fn filled_cache() -> ChannelCache {
+    let mut channel_cache = ChannelCache::default();
+    let now = Utc::now();
+    for i in 0..ENTRIES {
+        channel_cache.insert_keys(&[Key::Text(entry(i))], now, Some(i as u64), None);
+    }
+    channel_cache
+}
+
+fn normalization(c: &mut Criterion) {
+    let mut group = c.benchmark_group("normalize");
+    for (name, options) in [("default", NormalizationConfig::default()), ("all stages", all_stages())] {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &options, |b, options| {
+            b.iter(|| {
+                for message in MESSAGES {
+                    black_box(normalize_string(black_box(message), options));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn inserts(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert");
+    group.sample_size(10).throughput(Throughput::Elements(ENTRIES as u64));
+    let keys: Vec<_> = (0..ENTRIES).map(|i| Key::Text(entry(i))).collect();
+    let now = Utc::now();
+    group.bench_function("1M entries", |b| {
+        b.iter_batched(
+            ChannelCache::default,
+            |mut channel_cache| {
+                for key in &keys {
+                    channel_cache.insert_keys(std::slice::from_ref(key), now, None, None);
+                }
+                channel_cache
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+fn lookups(c: &mut Criterion) {
+    let channel_cache = filled_cache();
+    let (fuzzy, matching) = (FuzzyConfig::default(), MatchingConfig::default());
+    let rules = dedup::Rules { fuzzy: &fuzzy, matching: &matching, max_image_distance: 0, expire_after: None };
+    let now = Utc::now();
+    let mut group = c.benchmark_group("lookup in 1M entries");
+    for (name, key) in [("duplicate", Key::Text(entry(ENTRIES / 2))), ("unique", Key::Text("not cached".to_owned()))] {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &key, |b, key| {
+            b.iter(|| dedup::check(&channel_cache.cache, black_box(key), &rules, now))
+        });
+    }
+    group.finish();
+}
+
+fn snapshots(c: &mut Criterion) {
+    let channel_cache = filled_cache();
+    let mut group = c.benchmark_group("serialize");
+    group.sample_size(10);
+    group.bench_function("1M entries", |b| b.iter(|| serde_json::to_vec(&channel_cache).unwrap()));
+    group.finish();
+}
+
+criterion_group!(benches, normalization, inserts, lookups, snapshots);
+criterion_main!(benches);
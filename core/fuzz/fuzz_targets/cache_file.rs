@@ -0,0 +1,16 @@
+//! Feeds arbitrary bytes to the deserialization of cached channels and round archives, which make
+//! up nearly all of the data file. Whatever is read must survive being written and read again.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use set_core::cache::{ChannelCache, RoundArchive};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(channel_cache) = serde_json::from_slice::<ChannelCache>(data) {
+        let written = serde_json::to_vec(&channel_cache).expect("A read cache can be written");
+        let reread: ChannelCache = serde_json::from_slice(&written).expect("A written cache can be read");
+        assert_eq!(reread.cache, channel_cache.cache);
+    }
+    let _ = serde_json::from_slice::<RoundArchive>(data);
+});
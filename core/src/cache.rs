@@ -0,0 +1,319 @@
+use crate::{config::NormalizationConfig, dedup::Key};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::Duration,
+};
+
+/// Metadata stored for every cached entry
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// When the message that introduced this entry was sent
+    pub first_seen: DateTime<Utc>,
+    /// Discord user ID of who sent that message, unknown for entries that were imported or
+    /// cached before authors were recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<u64>,
+    /// Where that message is, unknown for entries that were imported or cached before messages
+    /// were recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<MessageRef>,
+}
+
+/// Discord IDs of a message, enough to link to it
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct MessageRef {
+    pub guild_id: u64,
+    pub channel_id: u64,
+    pub message_id: u64,
+}
+
+impl MessageRef {
+    /// The message's jump link
+    pub fn url(&self) -> String {
+        format!("https://discord.com/channels/{}/{}/{}", self.guild_id, self.channel_id, self.message_id)
+    }
+}
+
+/// Statistics about a round, i.e. the time between two resets of the cache
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoundStats {
+    pub number: u32,
+    pub started_at: DateTime<Utc>,
+    /// Unset for the current round
+    pub ended_at: Option<DateTime<Utc>>,
+    /// Messages accepted as unique
+    pub accepted: u64,
+    /// Duplicate messages deleted
+    pub deleted: u64,
+    /// Highest milestone of accepted messages that was already announced
+    #[serde(default)]
+    pub milestone: u64,
+    /// Last number counted, in channels playing the counting game
+    #[serde(default)]
+    pub count: u64,
+    /// Discord user ID of who counted it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_counter: Option<u64>,
+    /// Last accepted entry, in channels playing the word-chain game
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_entry: Option<String>,
+    /// Letter the last accepted entry started with, in channels playing the alphabet game
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_letter: Option<char>,
+}
+impl Default for RoundStats {
+    fn default() -> Self {
+        Self {
+            number: 1,
+            started_at: Utc::now(),
+            ended_at: None,
+            accepted: 0,
+            deleted: 0,
+            milestone: 0,
+            count: 0,
+            last_counter: None,
+            last_entry: None,
+            last_letter: None,
+        }
+    }
+}
+
+/// A finished round, as written to its archive file
+#[derive(Serialize, Deserialize)]
+pub struct RoundArchive {
+    pub stats: RoundStats,
+    pub cache: HashMap<String, Entry>,
+}
+
+/// What compacting a cache changed
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CompactionReport {
+    /// Entries whose key changed under the current normalization settings
+    pub renormalized: usize,
+    /// Entries dropped because they now collide with another
+    pub merged: usize,
+    /// Entries dropped because they had expired
+    pub expired: usize,
+}
+
+impl std::ops::AddAssign for CompactionReport {
+    fn add_assign(&mut self, other: Self) {
+        self.renormalized += other.renormalized;
+        self.merged += other.merged;
+        self.expired += other.expired;
+    }
+}
+
+/// The cache of a watched channel, or of a thread that doesn't share its parent's cache
+#[derive(Serialize, Deserialize, Default)]
+pub struct ChannelCache {
+    /// Entries by their normalized text. The text itself is kept, not a hash of it, since fuzzy,
+    /// anagram and substring matching, autocomplete, exports and compaction all need it.
+    #[serde(deserialize_with = "deserialize_cache")]
+    pub cache: HashMap<String, Entry>,
+    #[serde(default)]
+    pub round: RoundStats,
+    #[serde(default)]
+    pub past_rounds: Vec<RoundStats>,
+    /// Streaks by the Discord user ID of who they belong to, kept across rounds
+    #[serde(default)]
+    pub streaks: HashMap<u64, Streak>,
+    /// What happened on each of the last days with any activity, kept across rounds
+    #[serde(default)]
+    pub activity: BTreeMap<NaiveDate, DayActivity>,
+}
+
+/// How many days of activity are kept
+const ACTIVITY_DAYS: u64 = 90;
+
+/// What happened in a channel on one day (in UTC)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct DayActivity {
+    /// Messages accepted
+    pub accepted: u32,
+    /// Duplicates deleted
+    pub deleted: u32,
+}
+
+/// Consecutive days (in UTC) on which a user had at least one message accepted
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Streak {
+    /// Length of the streak as of `last_day`
+    pub current: u32,
+    pub longest: u32,
+    /// Last day a message of theirs was accepted
+    pub last_day: NaiveDate,
+    /// When the last message of theirs that was accepted was sent, unknown for streaks recorded
+    /// before it was kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_accepted_at: Option<DateTime<Utc>>,
+}
+
+impl Streak {
+    fn new(day: NaiveDate) -> Self {
+        Self { current: 1, longest: 1, last_day: day, last_accepted_at: None }
+    }
+    /// Counts a message accepted on `day`, which continues the streak if it's the day after the
+    /// last one and starts a new one if days were missed
+    ///
+    /// Messages from before the last day, e.g. seen while catching up, change nothing.
+    pub fn record(&mut self, day: NaiveDate) {
+        match (day - self.last_day).num_days() {
+            ..=0 => return,
+            1 => self.current += 1,
+            _ => self.current = 1,
+        }
+        self.last_day = day;
+        self.longest = self.longest.max(self.current);
+    }
+    /// The streak as of `today`, which is over once a whole day passed without an accepted message
+    pub fn current_on(&self, today: NaiveDate) -> u32 {
+        match (today - self.last_day).num_days() {
+            ..=1 => self.current,
+            _ => 0,
+        }
+    }
+}
+
+/// Accepts both the current map of entries and the legacy plain list of entries, which had no
+/// metadata. Legacy entries are treated as first seen now.
+fn deserialize_cache<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<String, Entry>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum CacheFormat {
+        Current(HashMap<String, Entry>),
+        Legacy(HashSet<String>),
+    }
+    Ok(match CacheFormat::deserialize(deserializer)? {
+        CacheFormat::Current(cache) => cache,
+        CacheFormat::Legacy(cache) => {
+            let entry = Entry { first_seen: Utc::now(), author: None, message: None };
+            cache.into_iter().map(|key| (key, entry.clone())).collect()
+        }
+    })
+}
+
+impl Entry {
+    /// Whether the entry has outlived `ttl` and may be posted again
+    pub fn is_expired(&self, ttl: Option<Duration>, now: DateTime<Utc>) -> bool {
+        match ttl.and_then(|ttl| chrono::Duration::from_std(ttl).ok()) {
+            Some(ttl) => self.first_seen + ttl <= now,
+            None => false,
+        }
+    }
+}
+
+impl ChannelCache {
+    /// Records all of a message's keys as used
+    pub fn insert_keys(&mut self, keys: &[Key], first_seen: DateTime<Utc>, author: Option<u64>, message: Option<MessageRef>) {
+        for key in keys {
+            self.cache.insert(key.to_entry(), Entry { first_seen, author, message });
+        }
+    }
+    /// Counts an accepted message towards the round and its author's streak
+    pub fn record_accepted(&mut self, author: Option<u64>, sent_at: DateTime<Utc>) {
+        self.round.accepted += 1;
+        let day = sent_at.date_naive();
+        self.activity_on(day).accepted += 1;
+        let Some(author) = author else {
+            return;
+        };
+        let streak = self.streaks
+            .entry(author)
+            .and_modify(|streak| streak.record(day))
+            .or_insert_with(|| Streak::new(day));
+        // Messages seen out of order, e.g. while catching up, don't move it back
+        if streak.last_accepted_at.is_none_or(|last_accepted_at| last_accepted_at < sent_at) {
+            streak.last_accepted_at = Some(sent_at);
+        }
+    }
+    /// Counts a deleted duplicate towards the round and the day's activity
+    pub fn record_deleted(&mut self, now: DateTime<Utc>) {
+        self.round.deleted += 1;
+        self.activity_on(now.date_naive()).deleted += 1;
+    }
+    /// The activity of `day`, forgetting days that are too old to keep
+    fn activity_on(&mut self, day: NaiveDate) -> &mut DayActivity {
+        if let Some(oldest_kept) = day.checked_sub_days(chrono::Days::new(ACTIVITY_DAYS - 1)) {
+            if self.activity.first_key_value().is_some_and(|(oldest, _)| *oldest < oldest_kept) {
+                self.activity = self.activity.split_off(&oldest_kept);
+            }
+        }
+        self.activity.entry(day).or_default()
+    }
+    /// Drops every entry introduced by `author`, returning how many were removed
+    pub fn forget_author(&mut self, author: u64) -> usize {
+        let before = self.cache.len();
+        self.cache.retain(|_, entry| entry.author != Some(author));
+        before - self.cache.len()
+    }
+    /// Ends the current round and clears the cache for the next one, returning the finished round
+    ///
+    /// The channel's last message ID is kept so the next catch-up doesn't re-add the old round's messages.
+    pub fn start_new_round(&mut self, now: DateTime<Utc>) -> RoundArchive {
+        let next_round = RoundStats {
+            number: self.round.number + 1,
+            started_at: now,
+            ..RoundStats::default()
+        };
+        let mut stats = std::mem::replace(&mut self.round, next_round);
+        stats.ended_at = Some(now);
+        self.past_rounds.push(stats.clone());
+        RoundArchive {
+            stats,
+            cache: std::mem::take(&mut self.cache),
+        }
+    }
+    /// Drops entries that have outlived `ttl`, returning how many were removed
+    pub fn evict_expired(&mut self, ttl: Option<Duration>, now: DateTime<Utc>) -> usize {
+        let before = self.cache.len();
+        self.cache.retain(|_, entry| !entry.is_expired(ttl, now));
+        before - self.cache.len()
+    }
+    /// Re-normalizes every entry with `options` and drops expired ones, merging entries that now
+    /// collide into the one seen first
+    pub fn compact(&mut self, options: &NormalizationConfig, ttl: Option<Duration>, now: DateTime<Utc>) -> CompactionReport {
+        let mut report = CompactionReport {
+            expired: self.evict_expired(ttl, now),
+            ..CompactionReport::default()
+        };
+        let mut compacted: HashMap<String, Entry> = HashMap::with_capacity(self.cache.len());
+        for (entry, metadata) in self.cache.drain() {
+            let key = Key::from_entry(&entry).renormalize(options).to_entry();
+            if key != entry {
+                report.renormalized += 1;
+            }
+            match compacted.get_mut(&key) {
+                Some(existing) => {
+                    report.merged += 1;
+                    if metadata.first_seen < existing.first_seen {
+                        *existing = metadata;
+                    }
+                }
+                None => {
+                    compacted.insert(key, metadata);
+                }
+            }
+        }
+        self.cache = compacted;
+        report
+    }
+    /// The `count` most recently first seen entries, newest first
+    pub fn recent(&self, count: usize) -> Vec<(&str, &Entry)> {
+        let mut entries: Vec<_> = self.cache.iter().map(|(entry, metadata)| (entry.as_str(), metadata)).collect();
+        let newest_first = |a: &(&str, &Entry), b: &(&str, &Entry)| b.1.first_seen.cmp(&a.1.first_seen).then(a.0.cmp(b.0));
+        // Only the newest need to be in order, which matters for caches of millions of entries
+        if count < entries.len() {
+            entries.select_nth_unstable_by(count, newest_first);
+            entries.truncate(count);
+        }
+        entries.sort_unstable_by(newest_first);
+        entries
+    }
+    /// Whether the cache holds nothing worth keeping
+    pub fn is_orphaned(&self) -> bool {
+        self.cache.is_empty() && self.round.accepted == 0 && self.round.deleted == 0 && self.past_rounds.is_empty()
+    }
+}
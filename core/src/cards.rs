@@ -0,0 +1,191 @@
+//! The SET card game: every card combines one of three values of four attributes, and three cards
+//! form a SET if each attribute is the same on all of them or different on each.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Cards on the board once it is dealt, unless it needs more to hold a SET
+pub const BOARD_SIZE: usize = 12;
+/// Cards in a deck, one of every combination of attributes
+pub const DECK_SIZE: u8 = 81;
+
+/// A card, the values (0, 1 or 2) of its number, shape, shading and color as digits in base 3
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "u8", into = "u8")]
+pub struct Card(u8);
+
+impl Card {
+    /// How many symbols are on the card, less one
+    pub fn number(self) -> u8 {
+        self.0 % 3
+    }
+    pub fn shape(self) -> u8 {
+        self.0 / 3 % 3
+    }
+    pub fn shading(self) -> u8 {
+        self.0 / 9 % 3
+    }
+    pub fn color(self) -> u8 {
+        self.0 / 27
+    }
+    fn attributes(self) -> [u8; 4] {
+        [self.number(), self.shape(), self.shading(), self.color()]
+    }
+}
+
+impl TryFrom<u8> for Card {
+    type Error = String;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        if value < DECK_SIZE {
+            Ok(Self(value))
+        } else {
+            Err(format!("{} is not a card, there are only {}", value, DECK_SIZE))
+        }
+    }
+}
+
+impl From<Card> for u8 {
+    fn from(card: Card) -> Self {
+        card.0
+    }
+}
+
+/// Whether three different cards form a SET
+pub fn is_set(a: Card, b: Card, c: Card) -> bool {
+    // Three values of 0, 1 and 2 sum up to a multiple of 3 exactly if they are all the same or
+    // all different
+    a != b && a.attributes().iter().zip(b.attributes()).zip(c.attributes()).all(|((a, b), c)| (a + b + c) % 3 == 0)
+}
+
+/// Every card once, shuffled by the pseudo-random numbers of `seed`
+pub fn shuffled_deck(seed: u64) -> Vec<Card> {
+    let mut deck: Vec<Card> = (0..DECK_SIZE).map(Card).collect();
+    // Xorshift gets stuck at 0
+    let mut state = seed | 1;
+    for i in (1..deck.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        deck.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+    deck
+}
+
+/// Why a claim of three cards was turned down
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClaimError {
+    /// There is no card at this position of the board
+    NotOnBoard(usize),
+    /// The same card was named more than once
+    SameCard,
+    /// The cards don't form a SET, which costs the player points
+    NotASet,
+}
+
+impl std::fmt::Display for ClaimError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClaimError::NotOnBoard(position) => write!(f, "there is no card at position {} of the board", position),
+            ClaimError::SameCard => write!(f, "a SET takes three different cards"),
+            ClaimError::NotASet => write!(f, "the cards don't form a SET"),
+        }
+    }
+}
+
+/// A game of SET played in a channel
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CardGame {
+    /// Cards not dealt yet, dealt from the end
+    pub deck: Vec<Card>,
+    /// Cards on the board, in the order they are labeled in
+    pub board: Vec<Card>,
+    pub started_at: DateTime<Utc>,
+    /// Points by the Discord user ID of who scored them, one per SET found, less penalties
+    #[serde(default)]
+    pub scores: HashMap<u64, i64>,
+}
+
+impl CardGame {
+    /// Starts a game with `deck`, dealing the board from it
+    pub fn new(deck: Vec<Card>, started_at: DateTime<Utc>) -> Self {
+        let mut game = Self { deck, board: Vec::new(), started_at, scores: HashMap::new() };
+        game.deal();
+        game
+    }
+    /// Fills the board up to `BOARD_SIZE` cards, then deals three more at a time while it holds
+    /// no SET
+    pub fn deal(&mut self) {
+        while self.board.len() < BOARD_SIZE {
+            let Some(card) = self.deck.pop() else {
+                break;
+            };
+            self.board.push(card);
+        }
+        while self.find_set().is_none() && !self.deck.is_empty() {
+            let rest = self.deck.len().saturating_sub(3);
+            self.board.extend(self.deck.drain(rest..).rev());
+        }
+    }
+    /// The positions on the board of the first SET found, if there is any
+    pub fn find_set(&self) -> Option<[usize; 3]> {
+        let board = &self.board;
+        for i in 0..board.len() {
+            for j in i + 1..board.len() {
+                for k in j + 1..board.len() {
+                    if is_set(board[i], board[j], board[k]) {
+                        return Some([i, j, k]);
+                    }
+                }
+            }
+        }
+        None
+    }
+    /// Whether the game is over, since the deck is used up and the board holds no SET
+    pub fn is_over(&self) -> bool {
+        self.deck.is_empty() && self.find_set().is_none()
+    }
+    /// Takes the cards at `positions` off the board if they form a SET, crediting `player` with a
+    /// point, and deals again
+    ///
+    /// The cards are replaced where they were, so the other cards keep their labels, unless the
+    /// board had more than `BOARD_SIZE` cards or the deck is used up.
+    pub fn claim(&mut self, positions: [usize; 3], player: u64) -> Result<[Card; 3], ClaimError> {
+        if let Some(&position) = positions.iter().find(|&&position| position >= self.board.len()) {
+            return Err(ClaimError::NotOnBoard(position));
+        }
+        let [a, b, c] = positions;
+        if a == b || b == c || a == c {
+            return Err(ClaimError::SameCard);
+        }
+        let cards = positions.map(|position| self.board[position]);
+        if !is_set(cards[0], cards[1], cards[2]) {
+            return Err(ClaimError::NotASet);
+        }
+        let mut positions = positions;
+        positions.sort_unstable();
+        // From the back, so removing a card doesn't move the others claimed
+        for position in positions.into_iter().rev() {
+            match self.deck.pop() {
+                Some(card) if self.board.len() <= BOARD_SIZE => self.board[position] = card,
+                card => {
+                    self.deck.extend(card);
+                    self.board.remove(position);
+                }
+            }
+        }
+        self.deal();
+        *self.scores.entry(player).or_default() += 1;
+        Ok(cards)
+    }
+    /// Takes `penalty` points from `player`, e.g. for claiming cards that don't form a SET
+    pub fn penalize(&mut self, player: u64, penalty: u32) {
+        *self.scores.entry(player).or_default() -= i64::from(penalty);
+    }
+    /// The players and their points, best first
+    pub fn standings(&self) -> Vec<(u64, i64)> {
+        let mut standings: Vec<_> = self.scores.iter().map(|(&player, &score)| (player, score)).collect();
+        standings.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        standings
+    }
+}
@@ -0,0 +1,154 @@
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct FuzzyConfig {
+    /// Also treat entries that are merely similar to an existing entry as duplicates
+    pub enabled: bool,
+    /// Normalized Levenshtein similarity (0.0 to 1.0) at or above which an entry is a near-duplicate
+    pub threshold: f64,
+    /// Entries shorter than this (in characters) are only matched exactly
+    pub min_length: usize,
+}
+impl Default for FuzzyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 0.85,
+            min_length: 5,
+        }
+    }
+}
+
+/// Stricter ways for text entries to count as duplicates, besides fuzzy matching
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MatchingConfig {
+    /// Treat rearrangements of an existing entry's characters as duplicates
+    pub anagrams: bool,
+    /// Treat entries containing, or contained in, an existing entry as duplicates
+    pub substrings: bool,
+    /// Entries shorter than this (in characters) are never matched as substrings
+    pub min_substring_length: usize,
+}
+impl Default for MatchingConfig {
+    fn default() -> Self {
+        Self {
+            anagrams: false,
+            substrings: false,
+            min_substring_length: 3,
+        }
+    }
+}
+
+/// Optional stages of the normalization pipeline
+///
+/// Changing these alters how new messages are keyed, so entries cached before the change may no
+/// longer match their duplicates.
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct NormalizationConfig {
+    /// Map confusable characters (e.g. Cyrillic or Greek lookalikes) to their Unicode skeleton
+    pub confusables: bool,
+    /// Drop code blocks, inline code and `>` quotes
+    pub quotes_and_code: bool,
+    /// Strip Discord markdown (bold, italics, underline, strikethrough, spoilers and code)
+    pub markdown: bool,
+    /// Treat `<@!id>` user mentions the same as `<@id>`
+    pub mentions: bool,
+    /// Compare custom emoji (`<:name:id>`, `<a:name:id>`) by ID only
+    pub custom_emoji: bool,
+    /// Strip tracking query parameters and trailing slashes from links
+    pub links: bool,
+    /// How letters that only differ in case are made the same
+    pub case_folding: CaseFolding,
+    /// Strip accents and other diacritics, so e.g. "café" and "cafe" collide
+    pub diacritics: bool,
+    /// Spell every script in ASCII, so e.g. "Токио" and "Tōkyō" are compared as "tokio" and "tokyo"
+    pub transliterate: bool,
+    /// Read digits and symbols standing in for letters as those letters, e.g. "h3ll0" as "hello"
+    pub leet: bool,
+    /// Reduce words to their stem in this language, so e.g. "cats" and "cat" collide
+    pub stemming: Option<StemmingLanguage>,
+}
+
+/// Ways of ignoring case
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CaseFolding {
+    /// Lowercase every letter, which keeps e.g. "ß" and "ss" apart
+    #[default]
+    Lowercase,
+    /// Unicode full case folding, so e.g. "straße" and "STRASSE" collide
+    Full,
+    /// Full case folding with the Turkish and Azerbaijani dotted and dotless `i`
+    Turkic,
+}
+
+/// Languages words can be stemmed in
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StemmingLanguage {
+    Arabic,
+    Danish,
+    Dutch,
+    English,
+    Finnish,
+    French,
+    German,
+    Greek,
+    Hungarian,
+    Italian,
+    Norwegian,
+    Portuguese,
+    Romanian,
+    Russian,
+    Spanish,
+    Swedish,
+    Tamil,
+    Turkish,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AttachmentConfig {
+    /// Deduplicate attachments by the SHA-256 of their contents
+    pub enabled: bool,
+    /// Attachments larger than this many bytes are not downloaded or deduplicated
+    pub max_size: u64,
+    /// Additionally deduplicate images by a perceptual hash, catching re-encoded or resized copies
+    pub perceptual_hash: bool,
+    /// Maximum number of differing perceptual hash bits for two images to count as duplicates.
+    /// With 0, only identical hashes match.
+    pub max_distance: u32,
+}
+impl Default for AttachmentConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_size: 25 * 1024 * 1024,
+            perceptual_hash: false,
+            max_distance: 0,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TtlConfig {
+    /// How long an entry blocks duplicates for. Entries never expire if unset.
+    #[serde(with = "humantime_serde")]
+    pub expire_after: Option<Duration>,
+    /// How often expired entries are evicted from the cache
+    #[serde(with = "humantime_serde")]
+    pub sweep_interval: Duration,
+}
+impl Default for TtlConfig {
+    fn default() -> Self {
+        Self {
+            expire_after: None,
+            sweep_interval: Duration::from_secs(3600),
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use crate::{
+    cache::Entry,
+    config::{FuzzyConfig, MatchingConfig, NormalizationConfig},
+    normalize::{canonicalize_link, normalize_string},
+};
+use chrono::{DateTime, Utc};
+use std::{collections::HashMap, time::Duration};
+
+/// Something a message is deduplicated by
+#[derive(Debug, Clone, PartialEq)]
+pub enum Key {
+    /// Normalized message content
+    Text(String),
+    /// SHA-256 of an attachment's bytes, hex encoded
+    File(String),
+    /// Perceptual hash of an image attachment, base64 encoded
+    Image(String),
+    /// ID of a sticker
+    Sticker(String),
+    /// Canonical URL (or, lacking one, normalized title) of an embed
+    Embed(String),
+}
+
+/// Marks text that would otherwise be read back as another kind of key
+const TEXT_PREFIX: &str = "text:";
+const FILE_PREFIX: &str = "file:sha256:";
+const IMAGE_PREFIX: &str = "image:phash:";
+const STICKER_PREFIX: &str = "sticker:";
+const EMBED_PREFIX: &str = "embed:";
+const RESERVED_PREFIXES: [&str; 5] = [TEXT_PREFIX, FILE_PREFIX, IMAGE_PREFIX, STICKER_PREFIX, EMBED_PREFIX];
+
+/// The text a cache entry was stored for, if it was stored for a [`Key::Text`]
+fn entry_text(entry: &str) -> Option<&str> {
+    match entry.strip_prefix(TEXT_PREFIX) {
+        Some(text) => Some(text),
+        None => (!RESERVED_PREFIXES.iter().any(|prefix| entry.starts_with(prefix))).then_some(entry),
+    }
+}
+
+impl Key {
+    /// The string this key is stored as in the cache
+    pub fn to_entry(&self) -> String {
+        match self {
+            Key::Text(text) if RESERVED_PREFIXES.iter().any(|prefix| text.starts_with(prefix)) => format!("{}{}", TEXT_PREFIX, text),
+            Key::Text(text) => text.clone(),
+            Key::File(hash) => format!("{}{}", FILE_PREFIX, hash),
+            Key::Image(hash) => format!("{}{}", IMAGE_PREFIX, hash),
+            Key::Sticker(id) => format!("{}{}", STICKER_PREFIX, id),
+            Key::Embed(embed) => format!("{}{}", EMBED_PREFIX, embed),
+        }
+    }
+    /// The key a cache entry was stored for, the inverse of [`Key::to_entry`]
+    pub fn from_entry(entry: &str) -> Self {
+        let prefixed = [
+            (TEXT_PREFIX, Key::Text as fn(String) -> Key),
+            (FILE_PREFIX, Key::File),
+            (IMAGE_PREFIX, Key::Image),
+            (STICKER_PREFIX, Key::Sticker),
+            (EMBED_PREFIX, Key::Embed),
+        ];
+        prefixed
+            .into_iter()
+            .find_map(|(prefix, key)| Some(key(entry.strip_prefix(prefix)?.to_owned())))
+            .unwrap_or_else(|| Key::Text(entry.to_owned()))
+    }
+    /// The key normalized with `options`, for entries cached under different settings
+    pub fn renormalize(self, options: &NormalizationConfig) -> Self {
+        match self {
+            Key::Text(text) => Key::Text(normalize_string(&text, options)),
+            // Embeds are keyed by their URL, or by their title if they have none
+            Key::Embed(embed) if embed.starts_with("http://") || embed.starts_with("https://") => Key::Embed(canonicalize_link(&embed)),
+            Key::Embed(title) => Key::Embed(normalize_string(&title, options)),
+            key @ (Key::File(_) | Key::Image(_) | Key::Sticker(_)) => key,
+        }
+    }
+}
+
+/// The settings duplicates are decided by
+pub struct Rules<'a> {
+    pub fuzzy: &'a FuzzyConfig,
+    pub matching: &'a MatchingConfig,
+    /// Maximum number of differing perceptual hash bits for two images to count as duplicates
+    pub max_image_distance: u32,
+    /// How long an entry blocks duplicates for, forever if unset
+    pub expire_after: Option<Duration>,
+}
+
+/// Outcome of checking a key against the cache
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Unique,
+    Duplicate,
+    /// Not an exact match, but within the configured similarity threshold of `closest`
+    NearDuplicate { closest: String, similarity: f64 },
+    /// Not an exact match, but an anagram, substring or superstring of `closest`
+    Related { closest: String, relation: Relation },
+}
+
+/// How a text entry relates to the existing one it duplicates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relation {
+    Anagram,
+    /// The new entry is contained in the existing one
+    Substring,
+    /// The new entry contains the existing one
+    Superstring,
+}
+
+impl std::fmt::Display for Relation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Relation::Anagram => "an anagram of",
+            Relation::Substring => "contained in",
+            Relation::Superstring => "an extension of",
+        })
+    }
+}
+
+/// Decides whether all of a message's keys may be added to `cache`, reporting the first conflict
+///
+/// `now` is when the message was sent, entries that had expired by then are ignored.
+pub fn check_all(cache: &HashMap<String, Entry>, keys: &[Key], rules: &Rules, now: DateTime<Utc>) -> Verdict {
+    keys.iter()
+        .map(|key| check(cache, key, rules, now))
+        .find(|verdict| *verdict != Verdict::Unique)
+        .unwrap_or(Verdict::Unique)
+}
+
+/// Whether `keys` conflict with some entries of `cache`, all of which were introduced by `author`
+pub fn repeats_own(cache: &HashMap<String, Entry>, keys: &[Key], rules: &Rules, now: DateTime<Utc>, author: u64) -> bool {
+    let mut conflicts = keys
+        .iter()
+        .filter_map(|key| match check(cache, key, rules, now) {
+            Verdict::Unique => None,
+            Verdict::Duplicate => Some(key.to_entry()),
+            Verdict::NearDuplicate { closest, .. } | Verdict::Related { closest, .. } => Some(closest),
+        })
+        .peekable();
+    conflicts.peek().is_some() && conflicts.all(|entry| cache.get(&entry).is_some_and(|entry| entry.author == Some(author)))
+}
+
+/// Decides whether `key` may be added to `cache`
+pub fn check(cache: &HashMap<String, Entry>, key: &Key, rules: &Rules, now: DateTime<Utc>) -> Verdict {
+    let live = |entry: &Entry| !entry.is_expired(rules.expire_after, now);
+    if cache.get(&key.to_entry()).is_some_and(live) {
+        return Verdict::Duplicate;
+    }
+    let live_entries = || {
+        cache
+            .iter()
+            .filter(|(_, entry)| live(entry))
+            .map(|(cached, _)| cached)
+    };
+    match key {
+        Key::Text(entry) => match check_related(live_entries(), entry, rules.matching) {
+            Verdict::Unique => check_fuzzy(live_entries(), entry, rules.fuzzy),
+            verdict => verdict,
+        },
+        Key::Image(hash) => check_image(live_entries(), hash, rules.max_image_distance),
+        Key::File(_) | Key::Sticker(_) | Key::Embed(_) => Verdict::Unique,
+    }
+}
+
+/// Text entries least this similar to a search query are left out, unless they contain it
+const MIN_SEARCH_SIMILARITY: f64 = 0.5;
+
+/// The text entries of `cache` that contain `query` or are similar to it, best matches first
+///
+/// Entries containing the query rank above the rest, each by their similarity to it. Unlike
+/// [`check`], this ignores the configured rules, since it is for people to look around.
+pub fn search<'a>(cache: &'a HashMap<String, Entry>, query: &str, limit: usize) -> Vec<(&'a str, f64)> {
+    let mut matches: Vec<_> = cache
+        .keys()
+        .filter_map(|cached| Some((cached.as_str(), entry_text(cached)?)))
+        .map(|(cached, text)| (cached, text.contains(query), strsim::normalized_levenshtein(query, text)))
+        .filter(|(_, contains, similarity)| *contains || *similarity >= MIN_SEARCH_SIMILARITY)
+        .collect();
+    matches.sort_unstable_by(|(a, a_contains, a_similarity), (b, b_contains, b_similarity)| {
+        b_contains.cmp(a_contains).then(b_similarity.total_cmp(a_similarity)).then(a.cmp(b))
+    });
+    matches.into_iter().take(limit).map(|(cached, _, similarity)| (cached, similarity)).collect()
+}
+
+/// The characters of an entry in order, so anagrams have the same signature
+fn anagram_signature(entry: &str) -> Vec<char> {
+    let mut chars: Vec<char> = entry.chars().filter(|c| !c.is_whitespace()).collect();
+    chars.sort_unstable();
+    chars
+}
+
+fn check_related<'a>(cache: impl Iterator<Item = &'a String>, entry: &str, matching: &MatchingConfig) -> Verdict {
+    if !matching.anagrams && !matching.substrings {
+        return Verdict::Unique;
+    }
+    let signature = matching.anagrams.then(|| anagram_signature(entry));
+    let long_enough = |text: &str| text.chars().count() >= matching.min_substring_length;
+    for (cached, text) in cache.filter_map(|cached| Some((cached, entry_text(cached)?))) {
+        let relation = if signature.as_ref().is_some_and(|signature| *signature == anagram_signature(text)) {
+            Relation::Anagram
+        } else if matching.substrings && long_enough(entry) && text.contains(entry) {
+            Relation::Substring
+        } else if matching.substrings && long_enough(text) && entry.contains(text) {
+            Relation::Superstring
+        } else {
+            continue;
+        };
+        return Verdict::Related { closest: cached.clone(), relation };
+    }
+    Verdict::Unique
+}
+
+fn check_fuzzy<'a>(cache: impl Iterator<Item = &'a String>, entry: &str, fuzzy: &FuzzyConfig) -> Verdict {
+    if !fuzzy.enabled || entry.chars().count() < fuzzy.min_length {
+        return Verdict::Unique;
+    }
+    let closest = cache
+        .filter_map(|cached| Some((cached, entry_text(cached)?)))
+        .filter(|(_, text)| text.chars().count() >= fuzzy.min_length)
+        .map(|(cached, text)| (cached, strsim::normalized_levenshtein(entry, text)))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b));
+    match closest {
+        Some((closest, similarity)) if similarity >= fuzzy.threshold => Verdict::NearDuplicate {
+            closest: closest.clone(),
+            similarity,
+        },
+        _ => Verdict::Unique,
+    }
+}
+
+fn check_image<'a>(cache: impl Iterator<Item = &'a String>, hash: &str, max_distance: u32) -> Verdict {
+    if max_distance == 0 {
+        return Verdict::Unique;
+    }
+    let Ok(hash) = image_hasher::ImageHash::<Box<[u8]>>::from_base64(hash) else {
+        return Verdict::Unique;
+    };
+    let bits = hash.as_bytes().len() * 8;
+    let closest = cache
+        .filter_map(|cached| Some((cached, cached.strip_prefix(IMAGE_PREFIX)?)))
+        .filter_map(|(cached, other)| Some((cached, image_hasher::ImageHash::from_base64(other).ok()?)))
+        .map(|(cached, other)| (cached, hash.dist(&other)))
+        .min_by_key(|(_, distance)| *distance);
+    match closest {
+        Some((closest, distance)) if distance <= max_distance => Verdict::NearDuplicate {
+            closest: closest.clone(),
+            similarity: 1.0 - distance as f64 / bits as f64,
+        },
+        _ => Verdict::Unique,
+    }
+}
@@ -0,0 +1,104 @@
+use crate::cache::RoundStats;
+
+/// Why a message breaks the counting game's rules
+#[derive(Debug, Clone, PartialEq)]
+pub enum CountError {
+    NotANumber,
+    WrongNumber { expected: u64 },
+    /// The same user counted twice in a row
+    SameCounter,
+}
+
+impl std::fmt::Display for CountError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CountError::NotANumber => write!(f, "only numbers count here"),
+            CountError::WrongNumber { expected } => write!(f, "the next number is {}", expected),
+            CountError::SameCounter => write!(f, "someone else has to count the next number"),
+        }
+    }
+}
+
+/// Decides whether `author` may count `content` next in `round`, returning the number counted
+pub fn check_count(round: &RoundStats, content: &str, author: u64) -> Result<u64, CountError> {
+    let number: u64 = content.trim().parse().map_err(|_| CountError::NotANumber)?;
+    let expected = round.count + 1;
+    if number != expected {
+        return Err(CountError::WrongNumber { expected });
+    }
+    if round.last_counter == Some(author) {
+        return Err(CountError::SameCounter);
+    }
+    Ok(number)
+}
+
+/// Records that `author` counted `number`
+pub fn record_count(round: &mut RoundStats, number: u64, author: u64) {
+    round.count = number;
+    round.last_counter = Some(author);
+}
+
+/// Why an entry breaks the word-chain game's rules
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainError {
+    pub expected: char,
+}
+
+impl std::fmt::Display for ChainError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the next entry has to start with `{}`", self.expected)
+    }
+}
+
+/// Decides whether `entry` continues the chain of `round`, i.e. starts with the last letter of
+/// the previous entry. Anything but letters and digits is skipped on both ends.
+pub fn check_chain(round: &RoundStats, entry: &str) -> Result<(), ChainError> {
+    let Some(expected) = round.last_entry.as_deref().and_then(|last| last.chars().rev().find(|c| c.is_alphanumeric())) else {
+        return Ok(());
+    };
+    match entry.chars().find(|c| c.is_alphanumeric()) {
+        Some(first) if first == expected => Ok(()),
+        _ => Err(ChainError { expected }),
+    }
+}
+
+/// Records `entry` as the one the next has to continue
+pub fn record_chain(round: &mut RoundStats, entry: &str) {
+    round.last_entry = Some(entry.to_owned());
+}
+
+/// Why an entry breaks the alphabet game's rules
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlphabetError {
+    pub expected: char,
+}
+
+impl std::fmt::Display for AlphabetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the next entry has to start with `{}`", self.expected)
+    }
+}
+
+/// The letter the next entry of `round` has to start with: `a` first, then the one after the
+/// last entry's, wrapping around after `z`
+pub fn next_letter(round: &RoundStats) -> char {
+    match round.last_letter {
+        Some('z') | None => 'a',
+        Some(last) => (last as u8 + 1) as char,
+    }
+}
+
+/// Decides whether `entry` starts with the next letter of the alphabet. Anything but letters and
+/// digits is skipped.
+pub fn check_alphabet(round: &RoundStats, entry: &str) -> Result<char, AlphabetError> {
+    let expected = next_letter(round);
+    match entry.chars().find(|c| c.is_alphanumeric()) {
+        Some(first) if first.to_ascii_lowercase() == expected => Ok(expected),
+        _ => Err(AlphabetError { expected }),
+    }
+}
+
+/// Records `letter` as the one the last entry started with
+pub fn record_alphabet(round: &mut RoundStats, letter: char) {
+    round.last_letter = Some(letter);
+}
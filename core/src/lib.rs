@@ -0,0 +1,9 @@
+//! The Discord-independent part of the bot: how messages are normalized into keys, how the cache
+//! of entries is modeled, how duplicates are decided, and the rules of the other games.
+
+pub mod cache;
+pub mod cards;
+pub mod config;
+pub mod dedup;
+pub mod games;
+pub mod normalize;
@@ -0,0 +1,251 @@
+use crate::config::{CaseFolding, NormalizationConfig, StemmingLanguage};
+use regex::Regex;
+use std::sync::LazyLock;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+static USER_MENTION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<@!?(\d+)>").unwrap());
+static CUSTOM_EMOJI: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<a?:\w+:(\d+)>").unwrap());
+// Fenced blocks first, so their backticks aren't taken for inline code
+static CODE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)```.*?```|``[^`]+``|`[^`\n]+`").unwrap());
+// Discord also accepts links wrapped in `<>`, which suppresses the embed
+static LINK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<?(https?://[^\s<>]+)>?").unwrap());
+
+/// Query parameters that only identify where a link was shared from
+const TRACKING_PARAMS: &[&str] = &[
+    "fbclid", "gclid", "gclsrc", "dclid", "msclkid", "yclid", "mc_cid", "mc_eid", "igshid", "igsh",
+    "si", "feature", "ref_src", "ref_url", "_hsenc", "_hsmi", "spm",
+];
+
+/// Turns a message into the key it is deduplicated by
+pub fn normalize_string(msg: &str, options: &NormalizationConfig) -> String {
+    // Zero-width and other invisible code points are never meaningful for dedup
+    let mut msg: String = msg.chars().filter(|c| !is_invisible(*c)).collect();
+    if options.quotes_and_code {
+        msg = strip_quotes_and_code(&msg);
+    }
+    if options.markdown {
+        msg = strip_markdown(&msg);
+    }
+    if options.mentions {
+        // `<@!id>` is the legacy nickname mention, which renders the same as `<@id>`
+        msg = USER_MENTION.replace_all(&msg, "<@$1>").into_owned();
+    }
+    if options.custom_emoji {
+        // The emoji ID identifies the emoji, the name and animation flag are cosmetic
+        msg = CUSTOM_EMOJI.replace_all(&msg, "<emoji:$1>").into_owned();
+    }
+    if options.links {
+        msg = LINK
+            .replace_all(&msg, |captures: &regex::Captures| canonicalize_link(&captures[1]))
+            .into_owned();
+    }
+    let msg = fold_case(&msg, options.case_folding);
+    // Apply Unicode Normalization Form C
+    let mut msg: String = msg.nfc().collect();
+    if options.confusables {
+        // Map lookalike characters (e.g. Cyrillic `а` for Latin `a`) to a shared prototype.
+        // The skeleton may reintroduce uppercase letters, so fold case again afterwards.
+        let skeleton: String = unicode_security::skeleton(&msg).collect();
+        msg = fold_case(&skeleton, options.case_folding).nfc().collect();
+    }
+    if options.diacritics {
+        // Decomposing splits accented letters into their base letter and combining marks
+        msg = msg.nfd().filter(|c| !is_combining_mark(*c)).nfc().collect();
+    }
+    if options.transliterate {
+        // Scripts without case, like Chinese, come out capitalized per syllable
+        msg = fold_case(&any_ascii::any_ascii(&msg), options.case_folding);
+    }
+    if options.leet {
+        let words: Vec<_> = msg.split_whitespace().map(unleet).collect();
+        msg = words.join(" ");
+    }
+    // Remove all whitespaces, and split into tokens (formerly separated by whitespaces)
+    let tokens: Vec<_> = msg.split_whitespace().collect();
+    match options.stemming {
+        Some(language) => {
+            let stemmer = rust_stemmers::Stemmer::create(stemming_algorithm(language));
+            let stems: Vec<_> = tokens.iter().map(|token| stemmer.stem(token)).collect();
+            stems.join(" ")
+        }
+        None => tokens.join(" "),
+    }
+}
+
+fn stemming_algorithm(language: StemmingLanguage) -> rust_stemmers::Algorithm {
+    use rust_stemmers::Algorithm;
+    match language {
+        StemmingLanguage::Arabic => Algorithm::Arabic,
+        StemmingLanguage::Danish => Algorithm::Danish,
+        StemmingLanguage::Dutch => Algorithm::Dutch,
+        StemmingLanguage::English => Algorithm::English,
+        StemmingLanguage::Finnish => Algorithm::Finnish,
+        StemmingLanguage::French => Algorithm::French,
+        StemmingLanguage::German => Algorithm::German,
+        StemmingLanguage::Greek => Algorithm::Greek,
+        StemmingLanguage::Hungarian => Algorithm::Hungarian,
+        StemmingLanguage::Italian => Algorithm::Italian,
+        StemmingLanguage::Norwegian => Algorithm::Norwegian,
+        StemmingLanguage::Portuguese => Algorithm::Portuguese,
+        StemmingLanguage::Romanian => Algorithm::Romanian,
+        StemmingLanguage::Russian => Algorithm::Russian,
+        StemmingLanguage::Spanish => Algorithm::Spanish,
+        StemmingLanguage::Swedish => Algorithm::Swedish,
+        StemmingLanguage::Tamil => Algorithm::Tamil,
+        StemmingLanguage::Turkish => Algorithm::Turkish,
+    }
+}
+
+/// Strips tracking query parameters and trailing slashes from a link
+pub fn canonicalize_link(link: &str) -> String {
+    let Ok(mut url) = url::Url::parse(link) else {
+        return link.to_owned();
+    };
+    let params: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| !key.starts_with("utm_") && !TRACKING_PARAMS.contains(&key.as_ref()))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    if params.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(params);
+    }
+    let path = url.path().trim_end_matches('/').to_owned();
+    url.set_path(&path);
+    let link = url.to_string();
+    // An empty path is still serialized as `/`
+    match (url.query(), url.fragment()) {
+        (None, None) => link.trim_end_matches('/').to_owned(),
+        _ => link,
+    }
+}
+
+/// Makes letters that only differ in case the same
+fn fold_case(msg: &str, mode: CaseFolding) -> String {
+    match mode {
+        CaseFolding::Lowercase => msg.to_lowercase(),
+        CaseFolding::Full => caseless::default_case_fold_str(msg),
+        CaseFolding::Turkic => {
+            // Turkish and Azerbaijani pair dotless `ı` with `I`, and dotted `İ` with `i`
+            let msg: String = msg
+                .chars()
+                .map(|c| match c {
+                    'I' => 'ı',
+                    'İ' => 'i',
+                    c => c,
+                })
+                .collect();
+            caseless::default_case_fold_str(&msg)
+        }
+    }
+}
+
+/// Reads digits and symbols standing in for letters as those letters, e.g. "h3ll0" as "hello"
+///
+/// Words without any letters are left alone, so numbers stay numbers.
+fn unleet(word: &str) -> String {
+    if !word.chars().any(char::is_alphabetic) {
+        return word.to_owned();
+    }
+    word.chars()
+        .map(|c| match c {
+            '0' => 'o',
+            '1' | '!' | '|' => 'l',
+            '3' => 'e',
+            '4' | '@' => 'a',
+            '5' | '$' => 's',
+            '6' | '9' => 'g',
+            '7' | '+' => 't',
+            '8' => 'b',
+            '2' => 'z',
+            c => c,
+        })
+        .collect()
+}
+
+/// Removes code blocks, inline code and quotes, which usually cite something posted before
+fn strip_quotes_and_code(msg: &str) -> String {
+    let msg = CODE.replace_all(msg, "");
+    let mut lines = Vec::new();
+    for line in msg.lines() {
+        // `>>> ` quotes the rest of the message
+        if line.starts_with(">>> ") {
+            break;
+        }
+        if line.starts_with("> ") || line == ">" {
+            continue;
+        }
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+/// Removes Discord's inline formatting (bold, italics, underline, strikethrough, spoilers and
+/// code), keeping the formatted text itself
+fn strip_markdown(msg: &str) -> String {
+    let mut msg = strip_delimiter(msg, "```", false);
+    // Longer delimiters first, so `**` isn't mistaken for two `*`
+    for (delimiter, needs_word_boundary) in [("`", false), ("||", false), ("~~", false), ("**", false), ("__", false), ("*", false), ("_", true)] {
+        msg = strip_delimiter(&msg, delimiter, needs_word_boundary);
+    }
+    msg
+}
+
+/// Removes matched pairs of `delimiter` that enclose some text
+///
+/// Discord only treats `_` as italics at word boundaries, so `snake_case_words` are left alone
+/// when `needs_word_boundary` is set.
+fn strip_delimiter(msg: &str, delimiter: &str, needs_word_boundary: bool) -> String {
+    let is_word_char = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+    let mut out = String::with_capacity(msg.len());
+    let mut rest = msg;
+    while let Some(start) = rest.find(delimiter) {
+        let after = &rest[start + delimiter.len()..];
+        let Some(end) = after.find(delimiter).filter(|&end| end > 0) else {
+            break;
+        };
+        let closing_rest = &after[end + delimiter.len()..];
+        let at_boundary = !is_word_char(rest[..start].chars().next_back())
+            && !is_word_char(closing_rest.chars().next());
+        if needs_word_boundary && !at_boundary {
+            out.push_str(&rest[..start + delimiter.len()]);
+            rest = after;
+            continue;
+        }
+        out.push_str(&rest[..start]);
+        let mut inner = &after[..end];
+        if delimiter == "```" {
+            // Drop the language hint of fenced code blocks
+            if let Some((hint, code)) = inner.split_once('\n') {
+                if !hint.is_empty() && !hint.contains(char::is_whitespace) {
+                    inner = code;
+                }
+            }
+        }
+        out.push_str(inner);
+        rest = closing_rest;
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Code points that render as nothing, which can be used to make identical-looking messages differ
+fn is_invisible(c: char) -> bool {
+    matches!(c,
+        '\u{00AD}' // soft hyphen
+        | '\u{034F}' // combining grapheme joiner
+        | '\u{061C}' // arabic letter mark
+        | '\u{115F}' | '\u{1160}' | '\u{3164}' | '\u{FFA0}' // hangul fillers
+        | '\u{17B4}' | '\u{17B5}' // khmer inherent vowels
+        | '\u{180B}'..='\u{180F}' // mongolian variation selectors and vowel separator
+        | '\u{200B}'..='\u{200F}' // zero-width space, joiners and direction marks
+        | '\u{202A}'..='\u{202E}' // bidi embeddings and overrides
+        | '\u{2060}'..='\u{206F}' // word joiner, invisible operators and deprecated format characters
+        | '\u{FE00}'..='\u{FE0F}' // variation selectors
+        | '\u{FEFF}' // zero-width no-break space
+        | '\u{1BCA0}'..='\u{1BCA3}' // shorthand format controls
+        | '\u{1D173}'..='\u{1D17A}' // musical symbol formatting
+        | '\u{E0000}'..='\u{E0FFF}' // tags and variation selectors supplement
+    )
+}
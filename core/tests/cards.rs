@@ -0,0 +1,50 @@
+//! Properties the SET card game must keep for any shuffle
+
+use chrono::Utc;
+use proptest::prelude::*;
+use set_core::cards::{is_set, shuffled_deck, CardGame, ClaimError, BOARD_SIZE, DECK_SIZE};
+use std::collections::HashSet;
+
+proptest! {
+    #[test]
+    fn deck_holds_every_card_once(seed in any::<u64>()) {
+        let deck = shuffled_deck(seed);
+        prop_assert_eq!(deck.len(), DECK_SIZE as usize);
+        prop_assert_eq!(deck.iter().collect::<HashSet<_>>().len(), DECK_SIZE as usize);
+    }
+
+    #[test]
+    fn deals_a_board_with_a_set(seed in any::<u64>()) {
+        let game = CardGame::new(shuffled_deck(seed), Utc::now());
+        prop_assert!(game.board.len() >= BOARD_SIZE);
+        prop_assert_eq!(game.board.len() + game.deck.len(), DECK_SIZE as usize);
+        // Any 21 cards hold a SET, so the deck never runs out while dealing the first board
+        prop_assert!(game.find_set().is_some());
+    }
+
+    #[test]
+    fn plays_to_the_end(seed in any::<u64>()) {
+        let mut game = CardGame::new(shuffled_deck(seed), Utc::now());
+        let mut found = 0;
+        while let Some(set) = game.find_set() {
+            let cards = game.claim(set, 1).unwrap();
+            prop_assert!(is_set(cards[0], cards[1], cards[2]));
+            found += 1;
+            prop_assert_eq!(game.board.len() + game.deck.len() + 3 * found, DECK_SIZE as usize);
+        }
+        prop_assert!(game.is_over());
+        prop_assert_eq!(game.scores[&1], found as i64);
+    }
+
+    #[test]
+    fn turns_down_cards_that_are_no_set(seed in any::<u64>()) {
+        let mut game = CardGame::new(shuffled_deck(seed), Utc::now());
+        let board = game.board.clone();
+        prop_assert_eq!(game.claim([0, 0, 1], 1), Err(ClaimError::SameCard));
+        prop_assert_eq!(game.claim([0, 1, board.len()], 1), Err(ClaimError::NotOnBoard(board.len())));
+        if !is_set(board[0], board[1], board[2]) {
+            prop_assert_eq!(game.claim([0, 1, 2], 1), Err(ClaimError::NotASet));
+        }
+        prop_assert_eq!(game.board, board);
+    }
+}
@@ -0,0 +1,54 @@
+//! Properties of how keys are stored in the cache
+
+use proptest::prelude::*;
+use set_core::dedup::Key;
+
+/// Text that starts like the entry of another kind of key
+fn text_with_a_reserved_prefix() -> impl Strategy<Value = String> {
+    (
+        prop_oneof![
+            Just("text:"),
+            Just("file:sha256:"),
+            Just("image:phash:"),
+            Just("sticker:"),
+            Just("embed:"),
+        ],
+        any::<String>(),
+    )
+        .prop_map(|(prefix, rest)| format!("{}{}", prefix, rest))
+}
+
+fn key() -> impl Strategy<Value = Key> {
+    prop_oneof![
+        any::<String>().prop_map(Key::Text),
+        text_with_a_reserved_prefix().prop_map(Key::Text),
+        "[0-9a-f]{64}".prop_map(Key::File),
+        "[A-Za-z0-9+/]{11}=".prop_map(Key::Image),
+        "[0-9]{17,20}".prop_map(Key::Sticker),
+        any::<String>().prop_map(Key::Embed),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn entries_round_trip(key in key()) {
+        prop_assert_eq!(Key::from_entry(&key.to_entry()), key);
+    }
+
+    #[test]
+    fn text_is_never_read_back_as_another_kind(text in text_with_a_reserved_prefix()) {
+        prop_assert_eq!(Key::from_entry(&Key::Text(text.clone()).to_entry()), Key::Text(text));
+    }
+
+    #[test]
+    fn distinct_keys_have_distinct_entries(a in key(), b in key()) {
+        prop_assume!(a != b);
+        prop_assert_ne!(a.to_entry(), b.to_entry());
+    }
+}
+
+#[test]
+fn plain_text_is_stored_as_is() {
+    assert_eq!(Key::Text("hello world".to_owned()).to_entry(), "hello world");
+    assert_eq!(Key::Text("file:sha256:abc".to_owned()).to_entry(), "text:file:sha256:abc");
+}
@@ -0,0 +1,67 @@
+//! Properties the normalization pipeline must keep for any input
+
+use proptest::prelude::*;
+use set_core::{
+    config::{CaseFolding, NormalizationConfig, StemmingLanguage},
+    normalize::normalize_string,
+};
+use unicode_normalization::UnicodeNormalization;
+
+/// Every optional stage of the pipeline
+fn all_stages() -> NormalizationConfig {
+    NormalizationConfig {
+        confusables: true,
+        quotes_and_code: true,
+        markdown: true,
+        mentions: true,
+        custom_emoji: true,
+        links: true,
+        case_folding: CaseFolding::Turkic,
+        diacritics: true,
+        transliterate: true,
+        leet: true,
+        stemming: Some(StemmingLanguage::English),
+    }
+}
+
+/// Text that looks like chat messages, mixing scripts, accents, markdown and invisible characters
+fn message() -> impl Strategy<Value = String> {
+    prop_oneof![
+        any::<String>(),
+        "[a-zA-Z0-9 éèüßİıÅ\u{0301}\u{0308}\u{200B}\u{FE0F}*_~`|>@<:!\n-]{0,40}",
+    ]
+}
+
+proptest! {
+    #[test]
+    fn never_panics(msg in any::<String>()) {
+        normalize_string(&msg, &NormalizationConfig::default());
+        normalize_string(&msg, &all_stages());
+    }
+
+    #[test]
+    fn is_idempotent(msg in message()) {
+        for options in [NormalizationConfig::default(), NormalizationConfig { diacritics: true, ..Default::default() }] {
+            let once = normalize_string(&msg, &options);
+            prop_assert_eq!(normalize_string(&once, &options), once);
+        }
+    }
+
+    #[test]
+    fn ignores_the_normalization_form(msg in message()) {
+        let options = NormalizationConfig::default();
+        let composed: String = msg.nfc().collect();
+        let decomposed: String = msg.nfd().collect();
+        prop_assert_eq!(normalize_string(&composed, &options), normalize_string(&decomposed, &options));
+    }
+
+    #[test]
+    fn collapses_whitespace(msg in message()) {
+        for options in [NormalizationConfig::default(), all_stages()] {
+            let normalized = normalize_string(&msg, &options);
+            prop_assert_eq!(normalized.trim(), normalized.as_str());
+            prop_assert!(!normalized.contains("  "));
+            prop_assert!(!normalized.contains(['\n', '\t']));
+        }
+    }
+}
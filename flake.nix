@@ -28,10 +28,11 @@
         set-bot = pkgs.rustPackages.rustPlatform.buildRustPackage {
           pname = metadata.package.name;
           version = metadata.package.version;
-          src = ./app;
+          src = ./.;
+          cargoBuildFlags = [ "--package" "set-bot" ];
           buildInputs = [] ++ nixpkgs.lib.optionals pkgs.stdenv.isDarwin [ pkgs.darwin.apple_sdk.frameworks.SystemConfiguration ];
           cargoLock = {
-            lockFile = ./app/Cargo.lock;
+            lockFile = ./Cargo.lock;
           };
         };
         default = self.packages.${system}.set-bot;